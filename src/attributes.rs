@@ -0,0 +1,521 @@
+//! Attribute grammars, nonterminals carry attributes that are passed down from their parents (inherited)
+//! or computed from their children (synthesized)
+//!
+//! The rules are attached to the expansions of a grammar with `Grammar::with_attributes`.
+//! Every nonterminal node inherits the attributes of its parent unless the expansion of its parent has an
+//! inherited rule, and it synthesizes the merged attributes of its children unless its own expansion has a
+//! synthesized rule. A guard allows an expansion only for some inherited attributes: while a tree is expanded,
+//! the fuzzer computes the inherited attributes of a leaf from the root down and asks the strategy again
+//! when its guard doesn't allow the chosen expansion, ex: to generate well-typed expressions
+//! in a type environment. The attributes of a complete tree are computed with `AttributeRules::evaluate`.
+//!
+//! Attributes are JSON values, an object of attributes by name or `null` when no attribute is set.
+//! A subtree regenerated by a post-hook or a constraint inherits the attributes of the root.
+//! The rules of a node are found by the expansion-string rebuilt from its children, so they never fire
+//! for the subtrees whose text was produced by a pre-hook or rewritten by a post-hook.
+//!
+//! ## Example
+//!
+//! ```
+//! use grammar_fuzzer::attributes::{AttributeRules, Attributes};
+//! use grammar_fuzzer::{CloseStrategy, Grammar, GrammarFuzzer, RandomStrategy};
+//! use serde_json::json;
+//! use std::collections::HashMap;
+//!
+//! let expansions: HashMap<_, _> = [
+//!     ("<start>", vec!["<expr>"]),
+//!     ("<expr>", vec!["<expr> + <expr>", "<expr> == <expr>", "!<expr>", "<var>", "1", "true"]),
+//!     ("<var>", vec!["x", "p"]),
+//! ]
+//! .iter()
+//! .cloned()
+//! .collect();
+//!
+//! fn of_type(inherited: &Attributes, name: &str) -> Attributes {
+//!     let mut attributes = inherited.clone();
+//!     attributes["type"] = json!(name);
+//!     attributes
+//! }
+//!
+//! let rules = AttributeRules::new()
+//!     .with_root(json!({"type": "bool", "env": {"x": "int", "p": "bool"}}))
+//!     .with_guard("<expr>", "<expr> + <expr>", |inherited| inherited["type"] == "int")
+//!     .with_guard("<expr>", "<expr> == <expr>", |inherited| inherited["type"] == "bool")
+//!     .with_inherited("<expr>", "<expr> == <expr>", |inherited, _| of_type(inherited, "int"))
+//!     .with_guard("<expr>", "!<expr>", |inherited| inherited["type"] == "bool")
+//!     .with_guard("<expr>", "1", |inherited| inherited["type"] == "int")
+//!     .with_guard("<expr>", "true", |inherited| inherited["type"] == "bool")
+//!     .with_guard("<var>", "x", |inherited| inherited["env"]["x"] == inherited["type"])
+//!     .with_guard("<var>", "p", |inherited| inherited["env"]["p"] == inherited["type"]);
+//!
+//! let fuzzer = GrammarFuzzer::builder(Grammar::from(&expansions).with_attributes(rules))
+//!     .then(RandomStrategy::new(10, 100))
+//!     .then(CloseStrategy::new())
+//!     .seed(0)
+//!     .build();
+//! for input in fuzzer.inputs().take(10) {
+//!     // `p` and `true` are booleans, they are never added
+//!     assert!(!input.contains("p +") && !input.contains("+ p"));
+//!     assert!(!input.contains("true +") && !input.contains("+ true"));
+//! }
+//! ```
+
+use super::derivation_tree::{DerivationTree, Node, NodeId, Path};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// The attributes of a node, a JSON object of attributes by name or `null` when no attribute is set
+pub type Attributes = Value;
+
+type Inherit = dyn Fn(&Attributes, usize) -> Attributes + Send + Sync;
+type Synthesize = dyn Fn(&Attributes, &[Attributes], &str) -> Attributes + Send + Sync;
+type Guard = dyn Fn(&Attributes) -> bool + Send + Sync;
+
+/// The rules of the expansions of each symbol, by expansion-string
+type Rules<R> = HashMap<String, HashMap<String, Arc<R>>>;
+
+/// The inherited and synthesized attributes of a nonterminal node
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeAttributes {
+    pub inherited: Attributes,
+    /// An unexpanded nonterminal synthesizes no attributes
+    pub synthesized: Attributes,
+}
+
+/// The attribute rules of the expansions of a grammar, rules are shared by the clones of the rules
+#[derive(Clone, Default)]
+pub struct AttributeRules {
+    root: Attributes,
+    inherited: Rules<Inherit>,
+    synthesized: Rules<Synthesize>,
+    guards: Rules<Guard>,
+}
+
+fn insert<R: ?Sized>(rules: &mut Rules<R>, symbol: &str, expansion: &str, rule: Arc<R>) {
+    rules
+        .entry(String::from(symbol))
+        .or_default()
+        .insert(String::from(expansion), rule);
+}
+
+fn rule<'a, R: ?Sized>(rules: &'a Rules<R>, symbol: &str, expansion: &str) -> Option<&'a R> {
+    rules.get(symbol)?.get(expansion).map(|rule| &**rule)
+}
+
+/// The attributes of the objects merged from left to right, `null` when none of them is an object
+fn merge(attributes: &[Attributes]) -> Attributes {
+    let mut merged = Map::new();
+    for object in attributes.iter().filter_map(Value::as_object) {
+        merged.extend(object.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+    if merged.is_empty() {
+        Value::Null
+    } else {
+        Value::Object(merged)
+    }
+}
+
+impl AttributeRules {
+    /// Rules without any attribute
+    pub fn new() -> Self {
+        AttributeRules::default()
+    }
+
+    /// The attributes inherited by the root of every tree
+    pub fn with_root(mut self, attributes: Attributes) -> Self {
+        self.root = attributes;
+        self
+    }
+
+    /// Computes the attributes inherited by a child of the expansion
+    /// from the attributes inherited by the expanded node and the index of the child in the expansion
+    pub fn with_inherited<F>(mut self, symbol: &str, expansion: &str, rule: F) -> Self
+    where
+        F: Fn(&Attributes, usize) -> Attributes + Send + Sync + 'static,
+    {
+        insert(&mut self.inherited, symbol, expansion, Arc::new(rule));
+        self
+    }
+
+    /// Computes the attributes synthesized by a node expanded with the expansion
+    /// from its inherited attributes, the synthesized attributes of its children and its text,
+    /// terminal children synthesize `null`
+    pub fn with_synthesized<F>(mut self, symbol: &str, expansion: &str, rule: F) -> Self
+    where
+        F: Fn(&Attributes, &[Attributes], &str) -> Attributes + Send + Sync + 'static,
+    {
+        insert(&mut self.synthesized, symbol, expansion, Arc::new(rule));
+        self
+    }
+
+    /// Allows the expansion only for the inherited attributes that the guard accepts
+    pub fn with_guard<F>(mut self, symbol: &str, expansion: &str, guard: F) -> Self
+    where
+        F: Fn(&Attributes) -> bool + Send + Sync + 'static,
+    {
+        insert(&mut self.guards, symbol, expansion, Arc::new(guard));
+        self
+    }
+
+    pub fn root(&self) -> &Attributes {
+        &self.root
+    }
+
+    /// The attributes inherited by a child of the expansion,
+    /// the attributes of the expanded node when the expansion has no inherited rule
+    pub fn inherited(
+        &self,
+        symbol: &str,
+        expansion: &str,
+        parent: &Attributes,
+        child: usize,
+    ) -> Attributes {
+        match rule(&self.inherited, symbol, expansion) {
+            Some(inherit) => inherit(parent, child),
+            None => parent.clone(),
+        }
+    }
+
+    /// The attributes synthesized by a node expanded with the expansion,
+    /// the merged attributes of its children when the expansion has no synthesized rule
+    pub fn synthesized(
+        &self,
+        symbol: &str,
+        expansion: &str,
+        inherited: &Attributes,
+        children: &[Attributes],
+        text: &str,
+    ) -> Attributes {
+        match rule(&self.synthesized, symbol, expansion) {
+            Some(synthesize) => synthesize(inherited, children, text),
+            None => merge(children),
+        }
+    }
+
+    /// Returns true when the guard of the expansion accepts the inherited attributes
+    /// or when the expansion has no guard
+    pub fn allows(&self, symbol: &str, expansion: &str, inherited: &Attributes) -> bool {
+        rule(&self.guards, symbol, expansion).map_or(true, |guard| guard(inherited))
+    }
+
+    /// Returns true when an expansion of the symbol has a guard
+    pub fn has_guards(&self, symbol: &str) -> bool {
+        self.guards.contains_key(symbol)
+    }
+
+    /// The attributes inherited by a node of a tree that is being expanded,
+    /// computed from the root down to the node
+    pub fn inherited_at(&self, tree: &DerivationTree, node: NodeId) -> Attributes {
+        let mut ancestors = vec![node];
+        while let Some(parent) = tree.parent(*ancestors.last().unwrap()) {
+            ancestors.push(parent);
+        }
+        let mut attributes = self.root.clone();
+        for pair in ancestors.windows(2).rev() {
+            let (child, parent) = (pair[0], pair[1]);
+            let children = tree.children(parent);
+            let expansion: String = children.iter().map(|id| tree.symbol(*id)).collect();
+            let idx = children.iter().position(|id| *id == child).unwrap();
+            attributes = self.inherited(tree.symbol(parent), &expansion, &attributes, idx);
+        }
+        attributes
+    }
+
+    /// The attributes of the nonterminal nodes of a tree by path, the root inherits the root attributes
+    pub fn evaluate(&self, tree: &Node) -> HashMap<Path, NodeAttributes> {
+        let mut attributes = HashMap::new();
+        self.evaluate_node(tree, self.root.clone(), &mut Vec::new(), &mut attributes);
+        attributes
+    }
+
+    /// Evaluates the subtree at the path and returns its synthesized attributes
+    fn evaluate_node(
+        &self,
+        node: &Node,
+        inherited: Attributes,
+        path: &mut Path,
+        attributes: &mut HashMap<Path, NodeAttributes>,
+    ) -> Attributes {
+        let synthesized = match node {
+            Node::T(_) => return Value::Null,
+            Node::N(_) => Value::Null,
+            Node::EN(symbol, children) => {
+                let expansion = node.expansion_string().unwrap();
+                let synthesized_children: Vec<Attributes> = children
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, child)| {
+                        let child = child.borrow();
+                        if let Node::T(_) = *child {
+                            return Value::Null;
+                        }
+                        let child_inherited = self.inherited(symbol, &expansion, &inherited, idx);
+                        path.push(idx);
+                        let synthesized =
+                            self.evaluate_node(&child, child_inherited, path, attributes);
+                        path.pop();
+                        synthesized
+                    })
+                    .collect();
+                self.synthesized(
+                    symbol,
+                    &expansion,
+                    &inherited,
+                    &synthesized_children,
+                    &node.to_string(),
+                )
+            }
+        };
+        attributes.insert(
+            path.clone(),
+            NodeAttributes {
+                inherited,
+                synthesized: synthesized.clone(),
+            },
+        );
+        synthesized
+    }
+}
+
+/// Lists the root attributes and the expansions with rules, rules are functions
+impl fmt::Debug for AttributeRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn keys<R: ?Sized>(rules: &Rules<R>) -> Vec<(&str, &str)> {
+            let mut keys: Vec<(&str, &str)> = rules
+                .iter()
+                .flat_map(|(symbol, expansions)| {
+                    expansions
+                        .keys()
+                        .map(move |expansion| (symbol.as_str(), expansion.as_str()))
+                })
+                .collect();
+            keys.sort_unstable();
+            keys
+        }
+        f.debug_struct("AttributeRules")
+            .field("root", &self.root)
+            .field("inherited", &keys(&self.inherited))
+            .field("synthesized", &keys(&self.synthesized))
+            .field("guards", &keys(&self.guards))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::earley::EarleyParser;
+    use crate::error::GrammarError;
+    use crate::fuzzer::GrammarFuzzer;
+    use crate::grammar::Grammar;
+    use crate::shared::grammar;
+    use crate::strategy::{CloseStrategy, RandomStrategy};
+    use serde_json::json;
+
+    fn with(attributes: &Attributes, name: &str, value: Value) -> Attributes {
+        let mut attributes = attributes.clone();
+        attributes[name] = value;
+        attributes
+    }
+
+    fn sum_grammar() -> Grammar<()> {
+        grammar(&[
+            ("<start>", vec!["<sum>"]),
+            ("<sum>", vec!["<sum>+<digit>", "<digit>"]),
+            ("<digit>", vec!["1", "2", "3"]),
+        ])
+    }
+
+    /// The value of a sum and the nesting level of its nodes
+    fn sum_rules() -> AttributeRules {
+        let mut rules = AttributeRules::new()
+            .with_root(json!({"level": 0}))
+            .with_inherited("<sum>", "<sum>+<digit>", |inherited, _| {
+                with(
+                    inherited,
+                    "level",
+                    json!(inherited["level"].as_u64().unwrap() + 1),
+                )
+            })
+            .with_synthesized("<sum>", "<sum>+<digit>", |_, children, _| {
+                let sum =
+                    children[0]["value"].as_u64().unwrap() + children[2]["value"].as_u64().unwrap();
+                json!({ "value": sum })
+            });
+        for digit in ["1", "2", "3"].iter() {
+            rules = rules.with_synthesized(
+                "<digit>",
+                digit,
+                |_, _, text| json!({"value": text.parse::<u64>().unwrap()}),
+            );
+        }
+        rules
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let rules = sum_rules();
+        let tree = EarleyParser::new(&sum_grammar(), "<start>")
+            .parse("1+2+3")
+            .unwrap()
+            .to_node();
+        let attributes = rules.evaluate(&tree);
+
+        // the value is merged up from <sum> to <start> without a rule
+        assert_eq!(attributes[&vec![]].synthesized, json!({"value": 6}));
+        assert_eq!(attributes[&vec![0]].synthesized, json!({"value": 6}));
+        assert_eq!(attributes[&vec![0, 0]].synthesized, json!({"value": 3}));
+        assert_eq!(attributes[&vec![0, 0]].inherited, json!({"level": 1}));
+        assert_eq!(attributes[&vec![0, 0, 0]].inherited, json!({"level": 2}));
+        assert_eq!(attributes[&vec![0, 0, 0, 0]].inherited, json!({"level": 2}));
+        assert_eq!(attributes.len(), 7);
+
+        // an unexpanded nonterminal only inherits
+        let attributes = rules.evaluate(&Node::new_nonterminal("<start>"));
+        assert_eq!(
+            attributes[&vec![]],
+            NodeAttributes {
+                inherited: json!({"level": 0}),
+                synthesized: Value::Null,
+            }
+        );
+    }
+
+    #[test]
+    fn test_inherited_at() {
+        let rules = sum_rules();
+        let mut tree = DerivationTree::new("<start>");
+        let sum = tree.expand(tree.root(), "<sum>")[0];
+        let children = tree.expand(sum, "<sum>+<digit>").to_vec();
+        let inner = tree.expand(children[0], "<sum>+<digit>").to_vec();
+
+        assert_eq!(rules.inherited_at(&tree, tree.root()), json!({"level": 0}));
+        assert_eq!(rules.inherited_at(&tree, sum), json!({"level": 0}));
+        assert_eq!(rules.inherited_at(&tree, children[2]), json!({"level": 1}));
+        assert_eq!(rules.inherited_at(&tree, inner[0]), json!({"level": 2}));
+
+        // the same attributes as the complete tree
+        while let Some(&leaf) = tree.open_leaves().first() {
+            let expansion = if tree.symbol(leaf) == "<sum>" {
+                "<digit>"
+            } else {
+                "1"
+            };
+            tree.expand(leaf, expansion);
+        }
+        assert_eq!(
+            rules.inherited_at(&tree, inner[0]),
+            rules.evaluate(&tree.to_node())[&vec![0, 0, 0]].inherited
+        );
+    }
+
+    /// Expressions that are well-typed in the type environment of the root
+    fn typed_rules(root: Attributes) -> AttributeRules {
+        let mut rules = AttributeRules::new()
+            .with_root(root)
+            .with_guard("<expr>", "(<expr> + <expr>)", |inh| inh["type"] == "int")
+            .with_guard("<expr>", "(<expr> == <expr>)", |inh| inh["type"] == "bool")
+            .with_inherited("<expr>", "(<expr> == <expr>)", |inh, _| {
+                with(inh, "type", json!("int"))
+            })
+            .with_guard("<expr>", "<var>", |inh| {
+                inh["env"]
+                    .as_object()
+                    .map_or(false, |env| env.values().any(|t| *t == inh["type"]))
+            })
+            .with_guard("<expr>", "0", |inh| inh["type"] == "int")
+            .with_guard("<expr>", "false", |inh| inh["type"] == "bool");
+        for var in ["a", "b", "c"].iter() {
+            rules = rules
+                .with_guard("<var>", var, move |inh| inh["env"][var] == inh["type"])
+                .with_synthesized(
+                    "<var>",
+                    var,
+                    move |inh, _, _| json!({"type": inh["env"][var]}),
+                );
+        }
+        rules
+            .with_synthesized("<expr>", "(<expr> + <expr>)", |_, children, _| {
+                let int = children[1]["type"] == "int" && children[3]["type"] == "int";
+                json!({"type": if int { "int" } else { "error" }})
+            })
+            .with_synthesized("<expr>", "(<expr> == <expr>)", |_, children, _| {
+                let int = children[1]["type"] == "int" && children[3]["type"] == "int";
+                json!({"type": if int { "bool" } else { "error" }})
+            })
+            .with_synthesized("<expr>", "0", |_, _, _| json!({"type": "int"}))
+            .with_synthesized("<expr>", "false", |_, _, _| json!({"type": "bool"}))
+    }
+
+    fn typed_grammar(rules: AttributeRules) -> Grammar<()> {
+        grammar(&[
+            ("<start>", vec!["<expr>"]),
+            (
+                "<expr>",
+                vec![
+                    "(<expr> + <expr>)",
+                    "(<expr> == <expr>)",
+                    "<var>",
+                    "0",
+                    "false",
+                ],
+            ),
+            ("<var>", vec!["a", "b", "c"]),
+        ])
+        .with_attributes(rules)
+    }
+
+    #[test]
+    fn test_typed_expressions() {
+        for root_type in ["int", "bool"].iter() {
+            let rules = typed_rules(json!({"type": root_type, "env": {"a": "int", "b": "bool"}}));
+            let grammar = typed_grammar(rules.clone());
+            let parser = EarleyParser::new(&grammar, "<start>");
+            let fuzzer = GrammarFuzzer::builder(grammar)
+                .then(RandomStrategy::new(10, 100))
+                .then(CloseStrategy::new())
+                .seed(2)
+                .build();
+            for input in fuzzer.inputs().take(50) {
+                // c is not in the environment
+                assert!(!input.contains('c'), "{}", input);
+                let tree = parser.parse(&input).unwrap().to_node();
+                assert_eq!(
+                    rules.evaluate(&tree)[&vec![]].synthesized,
+                    json!({ "type": root_type }),
+                    "{}",
+                    input
+                );
+            }
+        }
+
+        // the strategy picks among the allowed expansions, the cheapest one isn't favored
+        let rules = typed_rules(json!({"type": "bool", "env": {"a": "int", "b": "bool"}}));
+        let fuzzer = GrammarFuzzer::builder(typed_grammar(rules))
+            .then(RandomStrategy::new(10, 100))
+            .then(CloseStrategy::new())
+            .seed(2)
+            .build();
+        let inputs: Vec<String> = fuzzer.inputs().take(600).collect();
+        let comparisons = inputs.iter().filter(|input| input.starts_with('(')).count();
+        let constants = inputs.iter().filter(|input| *input == "false").count();
+        assert!(
+            comparisons > 160 && constants < 260,
+            "{} {}",
+            comparisons,
+            constants
+        );
+
+        // no expression is a string
+        let rules = typed_rules(json!({"type": "string", "env": {"a": "int"}}));
+        let fuzzer = GrammarFuzzer::builder(typed_grammar(rules))
+            .then(RandomStrategy::new(10, 100))
+            .build();
+        assert_eq!(
+            fuzzer.try_fuzz(),
+            Err(GrammarError::NoAllowedExpansion(String::from("<expr>")))
+        );
+    }
+}
@@ -0,0 +1,294 @@
+//! Semantic constraints over derivation trees, in the spirit of
+//! [ISLa](https://github.com/rindPHI/isla), that a grammar alone can't express
+//!
+//! A constraint finds the first place where a complete tree violates it and may repair the tree in place,
+//! ex: by rewriting a length field. The fuzzer checks its constraints on every generated tree,
+//! it repairs the violations it can and regenerates the subtree of a violation it can't repair,
+//! until every constraint holds. A repair is kept only when the grammar derives the new text of the subtree.
+//!
+//! ## Example
+//!
+//! ```
+//! use grammar_fuzzer::constraints::DefinedBefore;
+//! use grammar_fuzzer::{CloseStrategy, Grammar, GrammarFuzzer, RandomStrategy};
+//! use std::collections::HashMap;
+//!
+//! let expansions: HashMap<_, _> = [
+//!     ("<start>", vec!["<stmt>;<start>", "<stmt>"]),
+//!     ("<stmt>", vec!["<decl>", "<use>"]),
+//!     ("<decl>", vec!["let <id>"]),
+//!     ("<use>", vec!["print <id>"]),
+//!     ("<id>", vec!["x", "y", "z"]),
+//! ]
+//! .iter()
+//! .cloned()
+//! .collect();
+//!
+//! let fuzzer = GrammarFuzzer::builder(Grammar::from(&expansions))
+//!     .then(RandomStrategy::new(10, 100))
+//!     .then(CloseStrategy::new())
+//!     .constraint(DefinedBefore::new("<decl>", "<use>", "<id>"))
+//!     .seed(0)
+//!     .build();
+//! for input in fuzzer.inputs().take(10) {
+//!     let statements: Vec<&str> = input.split(';').collect();
+//!     for (idx, statement) in statements.iter().enumerate() {
+//!         if let Some(id) = statement.strip_prefix("print ") {
+//!             assert!(statements[..idx].contains(&format!("let {}", id).as_str()));
+//!         }
+//!     }
+//! }
+//! ```
+
+use super::derivation_tree::{Children, Node, Path};
+use rand::seq::SliceRandom;
+use rand::RngCore;
+
+/// Where a tree violates a constraint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// The subtree that is regenerated when the violation can't be repaired
+    pub path: Path,
+    pub message: String,
+}
+
+/// A property of complete derivation trees
+///
+/// Constraints are shared by the threads of `GrammarFuzzer::generate_parallel`
+pub trait Constraint: Send + Sync {
+    /// The first violation of the constraint in the tree, None when the constraint holds
+    fn check(&self, tree: &Node) -> Option<Violation>;
+
+    /// Fixes the violation in place by rewriting the subtree at the path of the violation,
+    /// returns false when it can't be fixed
+    /// rng: is the random generator of the fuzzer, ex: to pick one of several fixes
+    ///
+    /// The fuzzer parses the new text of the subtree, so the repair only needs to get the text right
+    fn repair(&self, _tree: &mut Node, _violation: &Violation, _rng: &mut dyn RngCore) -> bool {
+        false
+    }
+}
+
+/// The paths of the subtrees with the symbol, in the order they start in the input
+fn paths_of(tree: &Node, symbol: &str) -> Vec<Path> {
+    tree.nonterminal_paths()
+        .into_iter()
+        .filter(|path| tree.at(path, |node| node.symbol() == symbol) == Some(true))
+        .collect()
+}
+
+/// The path of the first subtree with the symbol inside the subtree at the path
+fn first_inside(tree: &Node, path: &[usize], symbol: &str) -> Option<Path> {
+    tree.at(path, |subtree| paths_of(subtree, symbol).into_iter().next())?
+        .map(|inner| [path, &inner[..]].concat())
+}
+
+// ------------------------------- Length Of ----------------------------------
+
+/// The decimal value of a length field equals the byte length of a payload, ex: in a `<message>`
+/// the value of the first `<length>` equals the byte length of the first `<payload>`
+///
+/// A violation is repaired by rewriting the text of the length field, the fuzzer regenerates
+/// the scope instead when the length field can't derive the length
+pub struct LengthOf {
+    scope: String,
+    length: String,
+    payload: String,
+}
+
+impl LengthOf {
+    pub fn new(scope: &str, length: &str, payload: &str) -> Self {
+        LengthOf {
+            scope: String::from(scope),
+            length: String::from(length),
+            payload: String::from(payload),
+        }
+    }
+
+    /// The paths of the first scope whose length field doesn't match its payload and of its length field,
+    /// and the length of the payload
+    fn mismatch(&self, tree: &Node) -> Option<(Path, Path, usize)> {
+        paths_of(tree, &self.scope).into_iter().find_map(|scope| {
+            let length = first_inside(tree, &scope, &self.length)?;
+            let payload = first_inside(tree, &scope, &self.payload)?;
+            let payload_length = tree.at(&payload, |node| node.to_string().len())?;
+            match tree.at(&length, |node| node.to_string().parse::<usize>())? {
+                Ok(value) if value == payload_length => None,
+                _ => Some((scope, length, payload_length)),
+            }
+        })
+    }
+}
+
+impl Constraint for LengthOf {
+    fn check(&self, tree: &Node) -> Option<Violation> {
+        // a length field that can't hold the length is fixed by regenerating the whole scope
+        self.mismatch(tree).map(|(scope, _, _)| Violation {
+            path: scope,
+            message: format!(
+                "{} is not the length of {} in {}",
+                self.length, self.payload, self.scope
+            ),
+        })
+    }
+
+    fn repair(&self, tree: &mut Node, _violation: &Violation, _rng: &mut dyn RngCore) -> bool {
+        match self.mismatch(tree) {
+            Some((_, length, payload_length)) => {
+                let text = Children::from_text(&payload_length.to_string());
+                tree.replace_subtree(&length, Node::new_expanded(&self.length, text))
+                    .is_some()
+            }
+            None => false,
+        }
+    }
+}
+
+// ----------------------------- Defined Before -------------------------------
+
+/// Every identifier of a usage is the identifier of a declaration that starts before it,
+/// ex: every `<id>` of a `<use>` is the `<id>` of an earlier `<decl>`
+///
+/// A violation is repaired by copying the identifier of a random earlier declaration,
+/// when there is no earlier declaration the whole tree is regenerated
+pub struct DefinedBefore {
+    declaration: String,
+    usage: String,
+    identifier: String,
+}
+
+impl DefinedBefore {
+    pub fn new(declaration: &str, usage: &str, identifier: &str) -> Self {
+        DefinedBefore {
+            declaration: String::from(declaration),
+            usage: String::from(usage),
+            identifier: String::from(identifier),
+        }
+    }
+
+    /// The path of the first identifier that isn't declared before its usage
+    /// and the identifiers declared before it
+    fn undefined(&self, tree: &Node) -> Option<(Path, Vec<Node>)> {
+        // the paths and texts of the declared identifiers, copied only when a violation is found
+        let mut declared: Vec<(Path, String)> = Vec::new();
+        let text = |path: &Path| tree.at(path, |node| node.to_string()).unwrap();
+        for path in tree.nonterminal_paths() {
+            let symbol = tree.at(&path, |node| node.symbol().to_string()).unwrap();
+            if symbol == self.declaration {
+                if let Some(identifier) = first_inside(tree, &path, &self.identifier) {
+                    declared.push((identifier.clone(), text(&identifier)));
+                }
+            } else if symbol == self.usage {
+                if let Some(identifier) = first_inside(tree, &path, &self.identifier) {
+                    let used = text(&identifier);
+                    if !declared.iter().any(|(_, d)| *d == used) {
+                        let declared = declared
+                            .iter()
+                            .filter_map(|(path, _)| tree.subtree(path))
+                            .collect();
+                        return Some((identifier, declared));
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Constraint for DefinedBefore {
+    fn check(&self, tree: &Node) -> Option<Violation> {
+        self.undefined(tree).map(|(path, declared)| Violation {
+            path: if declared.is_empty() {
+                Vec::new()
+            } else {
+                path
+            },
+            message: format!(
+                "the {} of a {} is not defined by an earlier {}",
+                self.identifier, self.usage, self.declaration
+            ),
+        })
+    }
+
+    fn repair(&self, tree: &mut Node, _violation: &Violation, rng: &mut dyn RngCore) -> bool {
+        match self.undefined(tree) {
+            Some((path, declared)) => match declared.choose(rng) {
+                Some(identifier) => tree.replace_subtree(&path, identifier.clone()).is_some(),
+                None => false,
+            },
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::earley::EarleyParser;
+    use crate::shared::grammar;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_length_of() {
+        let grammar = grammar(&[
+            ("<start>", vec!["<message>|<start>", "<message>"]),
+            ("<message>", vec!["<length>:<payload>"]),
+            ("<length>", vec!["<digit><length>", "<digit>"]),
+            (
+                "<digit>",
+                vec!["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"],
+            ),
+            ("<payload>", vec!["<char><payload>", ""]),
+            ("<char>", vec!["a", "b"]),
+        ]);
+        let parser = EarleyParser::new(&grammar, "<start>");
+        let constraint = LengthOf::new("<message>", "<length>", "<payload>");
+        let mut rng = StdRng::seed_from_u64(0);
+
+        assert_eq!(
+            constraint.check(&parser.parse("2:ab|0:").unwrap().to_node()),
+            None
+        );
+        let mut tree = parser.parse("2:ab|3:a|01:b").unwrap().to_node();
+        let violation = constraint.check(&tree).unwrap();
+        assert_eq!(violation.path, vec![2, 0]);
+        assert!(constraint.repair(&mut tree, &violation, &mut rng));
+        assert_eq!(tree.to_string(), "2:ab|1:a|01:b");
+        assert_eq!(constraint.check(&tree), None);
+    }
+
+    #[test]
+    fn test_defined_before() {
+        let grammar = grammar(&[
+            ("<start>", vec!["<stmt>;<start>", "<stmt>"]),
+            ("<stmt>", vec!["<decl>", "<use>"]),
+            ("<decl>", vec!["let <id>"]),
+            ("<use>", vec!["print <id>"]),
+            ("<id>", vec!["x", "y"]),
+        ]);
+        let parser = EarleyParser::new(&grammar, "<start>");
+        let constraint = DefinedBefore::new("<decl>", "<use>", "<id>");
+        let mut rng = StdRng::seed_from_u64(0);
+
+        assert_eq!(
+            constraint.check(
+                &parser
+                    .parse("let x;print x;let y;print y")
+                    .unwrap()
+                    .to_node()
+            ),
+            None
+        );
+        let mut tree = parser.parse("let x;print y").unwrap().to_node();
+        let violation = constraint.check(&tree).unwrap();
+        assert!(constraint.repair(&mut tree, &violation, &mut rng));
+        assert_eq!(tree.to_string(), "let x;print x");
+
+        // without an earlier declaration only regenerating the whole tree can help
+        let mut tree = parser.parse("print x;let x").unwrap().to_node();
+        let violation = constraint.check(&tree).unwrap();
+        assert_eq!(violation.path, Vec::<usize>::new());
+        assert!(!constraint.repair(&mut tree, &violation, &mut rng));
+    }
+}
@@ -0,0 +1,651 @@
+//! Keeps track of the expansions used by the fuzzer and steers it towards the expansions that
+//! haven't been used yet, following the ["Grammar Coverage"](https://www.fuzzingbook.org/html/GrammarCoverageFuzzer.html)
+//! chapter of The Fuzzing Book
+//!
+//! Besides single expansions, the coverage of k-paths, chains of `k` nested nonterminal symbols
+//! where each symbol is a child of the previous one, can be measured on derivation trees and grammars
+//! and steered with a `KPathStrategy`
+//!
+//! ## Example
+//!
+//! ```
+//! use grammar_fuzzer::{CloseStrategy, CoverageStrategy, Grammar, GrammarFuzzer};
+//! use std::collections::HashMap;
+//! use std::sync::Arc;
+//!
+//! let expansions: HashMap<_, _> = [
+//!     ("<start>", vec!["<digit>"]),
+//!     ("<digit>", vec!["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"]),
+//! ]
+//! .iter()
+//! .cloned()
+//! .collect();
+//! let grammar = Grammar::from(&expansions);
+//!
+//! // The fuzzer shares the strategy, so that its coverage can be read afterwards
+//! let coverage = Arc::new(CoverageStrategy::new(10, 100));
+//! let fuzzer = GrammarFuzzer::builder(Grammar::from(&expansions))
+//!     .then(Arc::clone(&coverage))
+//!     .then(CloseStrategy::new())
+//!     .seed(0)
+//!     .build();
+//! for _ in 0..10 {
+//!     fuzzer.fuzz();
+//! }
+//!
+//! let report = coverage.report(&grammar);
+//! assert_eq!(report.covered, report.total);
+//! assert!(report.missing.is_empty());
+//! ```
+
+use super::derivation_tree::{DerivationTree, Node, NodeId};
+use super::grammar::{nonterminal_tokens, Grammar};
+use super::shared::max_idx;
+use super::strategy::Strategy;
+use rand::seq::SliceRandom;
+use rand::RngCore;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+/// A nonterminal symbol and one of its expansion-strings
+pub type ExpansionKey = (String, String);
+
+/// A chain of nested nonterminal symbols, from the outermost to the innermost
+pub type KPath = Vec<String>;
+
+/// How many of the expansions of a grammar have been used
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoverageReport<K> {
+    pub covered: usize,
+    pub total: usize,
+    /// The expansions that haven't been used, sorted
+    pub missing: Vec<K>,
+}
+
+impl<K> CoverageReport<K> {
+    /// The covered fraction of the expansions, 1 for a grammar without expansions
+    pub fn ratio(&self) -> f64 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.covered as f64 / self.total as f64
+        }
+    }
+}
+
+// ------------------------------- Coverage -----------------------------------
+
+/// Picks an expansion that hasn't been used yet, otherwise the expansion that
+/// leads to the most expansions that haven't been used yet, otherwise a random expansion
+///
+/// The used expansions are recorded by the fuzzer for every strategy in the pipeline,
+/// so the coverage accumulates across calls to `expand_tree`
+pub struct CoverageStrategy {
+    nonterminals_threshold: usize,
+    max_steps: usize,
+    covered: Mutex<HashSet<ExpansionKey>>,
+}
+
+impl CoverageStrategy {
+    pub fn new(nonterminals_threshold: usize, max_steps: usize) -> Self {
+        CoverageStrategy {
+            nonterminals_threshold,
+            max_steps,
+            covered: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// The expansions that have been used so far
+    pub fn covered(&self) -> HashSet<ExpansionKey> {
+        self.covered.lock().unwrap().clone()
+    }
+
+    /// Forgets the expansions that have been used so far
+    pub fn reset(&self) {
+        self.covered.lock().unwrap().clear();
+    }
+
+    /// Compares the used expansions with the expansions defined in the grammar
+    pub fn report<T>(&self, grammar: &Grammar<T>) -> CoverageReport<ExpansionKey> {
+        let covered = self.covered.lock().unwrap();
+        let all = grammar_expansions(grammar);
+        let mut missing: Vec<ExpansionKey> = all
+            .iter()
+            .filter(|key| !covered.contains(*key))
+            .cloned()
+            .collect();
+        missing.sort();
+        CoverageReport {
+            covered: all.len() - missing.len(),
+            total: all.len(),
+            missing,
+        }
+    }
+
+    fn is_covered(&self, symbol: &str, expansion: &str) -> bool {
+        self.covered
+            .lock()
+            .unwrap()
+            .contains(&(String::from(symbol), String::from(expansion)))
+    }
+
+    /// The number of expansions that haven't been used yet and that can be reached from the nonterminals of an expansion
+    fn reachable_uncovered<T>(&self, grammar: &Grammar<T>, expansion: &str) -> usize {
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut stack = nonterminal_tokens(expansion);
+        let mut uncovered = 0;
+        while let Some(symbol) = stack.pop() {
+            if !seen.insert(symbol) {
+                continue;
+            }
+            if let Some(alternatives) = grammar.get(symbol) {
+                for alternative in alternatives {
+                    if !self.is_covered(symbol, &alternative.string) {
+                        uncovered += 1;
+                    }
+                    stack.extend(nonterminal_tokens(&alternative.string));
+                }
+            }
+        }
+        uncovered
+    }
+}
+
+impl<T> Strategy<T> for CoverageStrategy {
+    /// Continue until reaching the expected number of nonterminal nodes or passing the expansions limit
+    fn cont(&self, tree: &DerivationTree, num_steps: usize) -> bool {
+        tree.num_possible_expansions() < self.nonterminals_threshold && num_steps < self.max_steps
+    }
+
+    /// Choose an uncovered expansion or the expansion that reaches the most uncovered expansions
+    fn choose(
+        &self,
+        grammar: &Grammar<T>,
+        tree: &DerivationTree,
+        leaf: NodeId,
+        rng: &mut dyn RngCore,
+    ) -> Option<String> {
+        match tree.open_symbol(leaf) {
+            Some(symbol) => {
+                let expansions = &grammar[symbol];
+                let uncovered: Vec<&str> = expansions
+                    .iter()
+                    .map(|e| e.string.as_str())
+                    .filter(|e| !self.is_covered(symbol, e))
+                    .collect();
+                if let Some(expansion) = uncovered.choose(rng) {
+                    return Some(String::from(*expansion));
+                }
+                let reachable: Vec<f64> = expansions
+                    .iter()
+                    .map(|e| self.reachable_uncovered(grammar, &e.string) as f64)
+                    .collect();
+                let choosen_expansion = expansions[max_idx(&reachable, rng)].string.clone();
+                Some(choosen_expansion)
+            }
+            None => None,
+        }
+    }
+
+    /// Records the expansion as covered
+    fn expanded(&self, tree: &DerivationTree, node: NodeId, expansion: &str) {
+        self.covered
+            .lock()
+            .unwrap()
+            .insert((String::from(tree.symbol(node)), String::from(expansion)));
+    }
+}
+
+// -------------------------------- K-Paths -----------------------------------
+
+/// The k-paths of a derivation tree, unexpanded nonterminal nodes are included
+pub fn node_k_paths(node: &Node, k: usize) -> HashSet<KPath> {
+    fn collect(node: &Node, k: usize, path: &mut Vec<String>, paths: &mut HashSet<KPath>) {
+        let symbol = match node {
+            Node::T(_) => return,
+            Node::N(symbol) | Node::EN(symbol, _) => symbol,
+        };
+        path.push(symbol.clone());
+        if path.len() >= k {
+            paths.insert(path[path.len() - k..].to_vec());
+        }
+        if let Node::EN(_, children) = node {
+            for child in children.iter() {
+                collect(&child.borrow(), k, path, paths);
+            }
+        }
+        path.pop();
+    }
+
+    let mut paths = HashSet::new();
+    if k > 0 {
+        collect(node, k, &mut Vec::new(), &mut paths);
+    }
+    paths
+}
+
+/// Every k-path that a derivation tree of the grammar could contain,
+/// the paths only go through the symbols defined in the grammar
+pub fn grammar_k_paths<T>(grammar: &Grammar<T>, k: usize) -> HashSet<KPath> {
+    if k == 0 {
+        return HashSet::new();
+    }
+    let mut paths: Vec<KPath> = grammar.keys().map(|symbol| vec![symbol.clone()]).collect();
+    for _ in 1..k {
+        paths = paths
+            .iter()
+            .flat_map(|path| {
+                children_symbols(grammar, path.last().unwrap())
+                    .into_iter()
+                    .map(move |child| {
+                        let mut path = path.clone();
+                        path.push(String::from(child));
+                        path
+                    })
+            })
+            .collect();
+    }
+    paths.into_iter().collect()
+}
+
+/// Compares a set of covered k-paths with the k-paths of the grammar
+pub fn k_path_report<T>(
+    grammar: &Grammar<T>,
+    k: usize,
+    covered: &HashSet<KPath>,
+) -> CoverageReport<KPath> {
+    let all = grammar_k_paths(grammar, k);
+    let mut missing: Vec<KPath> = all.difference(covered).cloned().collect();
+    missing.sort();
+    CoverageReport {
+        covered: all.len() - missing.len(),
+        total: all.len(),
+        missing,
+    }
+}
+
+/// The defined nonterminal symbols that appear in the expansions of a symbol
+fn children_symbols<'a, T>(grammar: &'a Grammar<T>, symbol: &str) -> HashSet<&'a str> {
+    grammar
+        .get(symbol)
+        .into_iter()
+        .flatten()
+        .flat_map(|expansion| nonterminal_tokens(&expansion.string))
+        .filter(|child| grammar.contains_key(*child))
+        .collect()
+}
+
+/// Picks the expansion whose nonterminals complete the most k-paths that haven't been generated yet,
+/// ties are broken by the number of uncovered k-paths of the grammar that go from the symbol to
+/// the nonterminals of the expansion
+///
+/// Like `CoverageStrategy`, the generated k-paths are recorded by the fuzzer across calls to `expand_tree`
+///
+/// The k-paths of the grammar are computed on the first choice, the strategy keeps using them,
+/// so it must not be shared between fuzzers of different grammars
+pub struct KPathStrategy {
+    k: usize,
+    nonterminals_threshold: usize,
+    max_steps: usize,
+    covered: Mutex<HashSet<KPath>>,
+    grammar_paths: Mutex<Option<Arc<GrammarKPaths>>>,
+}
+
+/// The k-paths of a grammar, indexed by the (symbol, child) steps that they go through
+struct GrammarKPaths {
+    paths: Vec<KPath>,
+    steps: HashMap<(String, String), Vec<usize>>,
+}
+
+impl GrammarKPaths {
+    fn new<T>(grammar: &Grammar<T>, k: usize) -> Self {
+        let paths: Vec<KPath> = grammar_k_paths(grammar, k).into_iter().collect();
+        let mut steps: HashMap<(String, String), Vec<usize>> = HashMap::new();
+        for (idx, path) in paths.iter().enumerate() {
+            for step in path.windows(2) {
+                let through = steps.entry((step[0].clone(), step[1].clone())).or_default();
+                if through.last() != Some(&idx) {
+                    through.push(idx);
+                }
+            }
+        }
+        GrammarKPaths { paths, steps }
+    }
+
+    /// The indices of the paths that go from the symbol to any of the children
+    fn through(&self, symbol: &str, children: &[&str]) -> HashSet<usize> {
+        children
+            .iter()
+            .filter_map(|child| {
+                self.steps
+                    .get(&(String::from(symbol), String::from(*child)))
+            })
+            .flatten()
+            .cloned()
+            .collect()
+    }
+}
+
+impl KPathStrategy {
+    pub fn new(k: usize, nonterminals_threshold: usize, max_steps: usize) -> Self {
+        assert!(k > 0, "k-paths have at least one symbol");
+        KPathStrategy {
+            k,
+            nonterminals_threshold,
+            max_steps,
+            covered: Mutex::new(HashSet::new()),
+            grammar_paths: Mutex::new(None),
+        }
+    }
+
+    /// The k-paths that have been generated so far
+    pub fn covered(&self) -> HashSet<KPath> {
+        self.covered.lock().unwrap().clone()
+    }
+
+    /// Marks the k-paths of an existing derivation tree as generated, ex: the trees of a seed corpus
+    pub fn record(&self, node: &Node) {
+        self.covered
+            .lock()
+            .unwrap()
+            .extend(node_k_paths(node, self.k));
+    }
+
+    /// Forgets the k-paths that have been generated so far
+    pub fn reset(&self) {
+        self.covered.lock().unwrap().clear();
+    }
+
+    /// Compares the generated k-paths with the k-paths of the grammar
+    pub fn report<T>(&self, grammar: &Grammar<T>) -> CoverageReport<KPath> {
+        k_path_report(grammar, self.k, &self.covered.lock().unwrap())
+    }
+
+    /// The k-paths of the grammar, computed on the first call
+    fn grammar_paths<T>(&self, grammar: &Grammar<T>) -> Arc<GrammarKPaths> {
+        let mut grammar_paths = self.grammar_paths.lock().unwrap();
+        Arc::clone(
+            grammar_paths.get_or_insert_with(|| Arc::new(GrammarKPaths::new(grammar, self.k))),
+        )
+    }
+
+    /// The last `len` symbols on the path from the root to a node, fewer when the node is too close to the root
+    fn path_to(tree: &DerivationTree, node: NodeId, len: usize) -> KPath {
+        let mut path = Vec::new();
+        let mut current = Some(node);
+        while let Some(id) = current {
+            if path.len() == len {
+                break;
+            }
+            path.push(String::from(tree.symbol(id)));
+            current = tree.parent(id);
+        }
+        path.reverse();
+        path
+    }
+}
+
+impl<T> Strategy<T> for KPathStrategy {
+    /// Continue until reaching the expected number of nonterminal nodes or passing the expansions limit
+    fn cont(&self, tree: &DerivationTree, num_steps: usize) -> bool {
+        tree.num_possible_expansions() < self.nonterminals_threshold && num_steps < self.max_steps
+    }
+
+    /// Choose the expansion that leads to the most uncovered k-paths
+    fn choose(
+        &self,
+        grammar: &Grammar<T>,
+        tree: &DerivationTree,
+        leaf: NodeId,
+        rng: &mut dyn RngCore,
+    ) -> Option<String> {
+        match tree.open_symbol(leaf) {
+            Some(symbol) => {
+                let expansions = &grammar[symbol];
+                let grammar_paths = self.grammar_paths(grammar);
+                let prefix = KPathStrategy::path_to(tree, leaf, self.k - 1);
+                let covered = self.covered.lock().unwrap();
+                let scores: Vec<f64> = expansions
+                    .iter()
+                    .map(|expansion| {
+                        let children = nonterminal_tokens(&expansion.string);
+                        let completed = children
+                            .iter()
+                            .filter(|child| {
+                                let mut path = prefix.clone();
+                                path.push(String::from(**child));
+                                path.len() == self.k && !covered.contains(&path)
+                            })
+                            .count();
+                        let through = grammar_paths
+                            .through(symbol, &children)
+                            .into_iter()
+                            .filter(|idx| !covered.contains(&grammar_paths.paths[*idx]))
+                            .count();
+                        (completed * (grammar_paths.paths.len() + 1) + through) as f64
+                    })
+                    .collect();
+                drop(covered);
+                let choosen_expansion = expansions[max_idx(&scores, rng)].string.clone();
+                Some(choosen_expansion)
+            }
+            None => None,
+        }
+    }
+
+    /// Records the k-path that ends at the expanded node
+    fn expanded(&self, tree: &DerivationTree, node: NodeId, _expansion: &str) {
+        let path = KPathStrategy::path_to(tree, node, self.k);
+        if path.len() == self.k {
+            self.covered.lock().unwrap().insert(path);
+        }
+    }
+}
+
+/// Every (symbol, expansion-string) pair of the grammar
+fn grammar_expansions<T>(grammar: &Grammar<T>) -> HashSet<ExpansionKey> {
+    grammar
+        .iter()
+        .flat_map(|(symbol, alternatives)| {
+            alternatives
+                .iter()
+                .map(move |e| (symbol.clone(), e.string.clone()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fuzzer::GrammarFuzzer;
+    use crate::shared::grammar;
+    use crate::strategy::CloseStrategy;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::sync::Arc;
+
+    fn key(symbol: &str, expansion: &str) -> ExpansionKey {
+        (String::from(symbol), String::from(expansion))
+    }
+
+    #[test]
+    fn test_covers_every_expansion() {
+        let rules = [
+            ("<start>", vec!["<values>"]),
+            ("<values>", vec!["<values>, <int>", "<int>"]),
+            ("<int>", vec!["<digit><int>", "<digit>"]),
+            ("<digit>", vec!["0", "1", "2", "3", "4", "5", "6", "7"]),
+        ];
+        let coverage = Arc::new(CoverageStrategy::new(5, 100));
+        let fuzzer = GrammarFuzzer::builder(grammar(&rules))
+            .then(Arc::clone(&coverage))
+            .then(CloseStrategy::new())
+            .seed(0)
+            .build();
+        for _ in 0..10 {
+            fuzzer.fuzz();
+        }
+
+        let report = coverage.report(&grammar(&rules));
+        assert_eq!(report.total, 13);
+        assert_eq!(report.covered, 13);
+        assert_eq!(report.ratio(), 1.0);
+
+        coverage.reset();
+        assert_eq!(coverage.report(&grammar(&rules)).covered, 0);
+    }
+
+    #[test]
+    fn test_report_missing_expansions() {
+        let grammar = grammar(&[("<start>", vec!["<bit>"]), ("<bit>", vec!["0", "1"])]);
+        let coverage = CoverageStrategy::new(10, 10);
+        let tree = DerivationTree::new("<start>");
+        Strategy::<()>::expanded(&coverage, &tree, tree.root(), "<bit>");
+
+        assert_eq!(
+            coverage.report(&grammar),
+            CoverageReport {
+                covered: 1,
+                total: 3,
+                missing: vec![key("<bit>", "0"), key("<bit>", "1")],
+            }
+        );
+    }
+
+    #[test]
+    fn test_coverage_strategy_choose() {
+        let grammar = grammar(&[
+            ("<start>", vec!["<a>", "<b>"]),
+            ("<a>", vec!["a"]),
+            ("<b>", vec!["<c>"]),
+            ("<c>", vec!["c", "cc"]),
+        ]);
+        let coverage = CoverageStrategy::new(10, 10);
+        let tree = DerivationTree::new("<start>");
+        let mut rng = StdRng::seed_from_u64(0);
+        for (symbol, expansion) in &[("<start>", "<a>"), ("<start>", "<b>"), ("<b>", "<c>")] {
+            coverage
+                .covered
+                .lock()
+                .unwrap()
+                .insert(key(symbol, expansion));
+        }
+
+        // both alternatives are covered, only <b> leads to uncovered expansions of <c>
+        for _ in 0..10 {
+            let choice = coverage.choose(&grammar, &tree, tree.root(), &mut rng);
+            assert_eq!(choice, Some(String::from("<b>")));
+        }
+
+        coverage
+            .covered
+            .lock()
+            .unwrap()
+            .remove(&key("<start>", "<a>"));
+        let choice = coverage.choose(&grammar, &tree, tree.root(), &mut rng);
+        assert_eq!(choice, Some(String::from("<a>")));
+    }
+
+    fn path(symbols: &[&str]) -> KPath {
+        symbols.iter().map(|s| String::from(*s)).collect()
+    }
+
+    fn k_path_grammar() -> Grammar<()> {
+        grammar(&[
+            ("<start>", vec!["<expr>"]),
+            ("<expr>", vec!["<term> + <expr>", "<term>"]),
+            ("<term>", vec!["(<expr>)", "<digit>"]),
+            ("<digit>", vec!["0", "1"]),
+        ])
+    }
+
+    #[test]
+    fn test_node_k_paths() {
+        let mut tree = DerivationTree::new("<start>");
+        let root = tree.root();
+        let expr = tree.expand(root, "<expr>")[0];
+        let term = tree.expand(expr, "<term>")[0];
+        tree.expand(term, "<digit>");
+        let node = tree.to_node();
+
+        let expected: HashSet<KPath> = [
+            path(&["<start>", "<expr>"]),
+            path(&["<expr>", "<term>"]),
+            path(&["<term>", "<digit>"]),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        assert_eq!(node_k_paths(&node, 2), expected);
+        assert_eq!(node_k_paths(&node, 4).len(), 1);
+        assert!(node_k_paths(&node, 5).is_empty());
+        assert_eq!(node_k_paths(&node, 1).len(), 4);
+    }
+
+    #[test]
+    fn test_grammar_k_paths() {
+        let grammar = k_path_grammar();
+        let expected: HashSet<KPath> = [
+            path(&["<start>", "<expr>"]),
+            path(&["<expr>", "<term>"]),
+            path(&["<expr>", "<expr>"]),
+            path(&["<term>", "<expr>"]),
+            path(&["<term>", "<digit>"]),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+
+        assert_eq!(grammar_k_paths(&grammar, 1).len(), 4);
+        assert_eq!(grammar_k_paths(&grammar, 2), expected);
+        assert!(grammar_k_paths(&grammar, 3).contains(&path(&["<term>", "<expr>", "<expr>"])));
+        assert!(!grammar_k_paths(&grammar, 3).contains(&path(&["<start>", "<term>", "<digit>"])));
+    }
+
+    #[test]
+    fn test_k_path_strategy_covers_grammar() {
+        let strategy = Arc::new(KPathStrategy::new(2, 10, 100));
+        let fuzzer = GrammarFuzzer::builder(k_path_grammar())
+            .then(Arc::clone(&strategy))
+            .then(CloseStrategy::new())
+            .seed(0)
+            .build();
+        for _ in 0..10 {
+            fuzzer.fuzz();
+        }
+
+        let report = strategy.report(&k_path_grammar());
+        assert_eq!(report.total, 5);
+        assert_eq!(report.missing, Vec::<KPath>::new());
+    }
+
+    #[test]
+    fn test_k_path_strategy_choose() {
+        let grammar = k_path_grammar();
+        let strategy = KPathStrategy::new(2, 10, 10);
+        let mut tree = DerivationTree::new("<start>");
+        let root = tree.root();
+        let expr = tree.expand(root, "<expr>")[0];
+        let term = tree.expand(expr, "<term>")[0];
+        let digit = tree.expand(term, "<digit>")[0];
+        tree.expand(digit, "0");
+        strategy.record(&tree.to_node());
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // <term> ::= (<expr>) leads to the uncovered k-path <term> <expr>
+        let mut tree = DerivationTree::new("<term>");
+        let choice = strategy.choose(&grammar, &tree, tree.root(), &mut rng);
+        assert_eq!(choice, Some(String::from("(<expr>)")));
+
+        // <expr> ::= <term> + <expr> leads to the uncovered k-path <expr> <expr>
+        let leaf = tree.expand(tree.root(), "(<expr>)")[1];
+        let choice = strategy.choose(&grammar, &tree, leaf, &mut rng);
+        assert_eq!(choice, Some(String::from("<term> + <expr>")));
+
+        // the k-paths of the grammar are only computed once
+        assert!(Arc::ptr_eq(
+            &strategy.grammar_paths(&grammar),
+            &strategy.grammar_paths(&grammar)
+        ));
+    }
+}
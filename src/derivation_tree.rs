@@ -3,10 +3,11 @@
 
 use super::parser::{self, Token};
 use std::cell::RefCell;
+use std::fmt;
 use std::ops::Deref;
 
 /// A Derivation Tree Node
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Node {
     /// T is a `Terminal Node`
     T(String),
@@ -17,7 +18,7 @@ pub enum Node {
 }
 
 /// A sequence of child nodes for an Expanded Nonterminal Node
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Children {
     pub roots: Vec<RefCell<Node>>,
 }
@@ -47,8 +48,13 @@ impl Deref for Children {
 
 impl Children {
     pub fn epsilon() -> Self {
+        Children::from_text("")
+    }
+
+    /// A single terminal child, unlike `Children::from` the text is not split into tokens
+    pub fn from_text(text: &str) -> Self {
         Children {
-            roots: vec![RefCell::new(Node::new_terminal(""))],
+            roots: vec![RefCell::new(Node::new_terminal(text))],
         }
     }
 }
@@ -109,6 +115,399 @@ impl Node {
                 .sum(),
         }
     }
+
+    /// The terminal text of a Node::T or the nonterminal symbol of a Node::N and Node::EN
+    pub fn symbol(&self) -> &str {
+        match self {
+            Node::T(sym) | Node::N(sym) | Node::EN(sym, _) => sym,
+        }
+    }
+
+    /// The expansion-string that was used to expand a Node::EN, rebuilt from the symbols of its children,
+    /// None for a Node::T and a Node::N
+    pub(crate) fn expansion_string(&self) -> Option<String> {
+        match self {
+            Node::EN(_, chl) => Some(
+                chl.iter()
+                    .map(|child| String::from(child.borrow().symbol()))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of nodes in a subtree
+    pub fn num_nodes(&self) -> usize {
+        match self {
+            Node::T(_) | Node::N(_) => 1,
+            Node::EN(_, chl) => {
+                1 + chl
+                    .iter()
+                    .map(|child| child.borrow().num_nodes())
+                    .sum::<usize>()
+            }
+        }
+    }
+
+    /// Returns the derivation depth of a subtree, the length of its longest path from the root to a leaf
+    pub fn depth(&self) -> usize {
+        match self {
+            Node::T(_) | Node::N(_) => 0,
+            Node::EN(_, chl) => {
+                1 + chl
+                    .iter()
+                    .map(|child| child.borrow().depth())
+                    .max()
+                    .unwrap_or(0)
+            }
+        }
+    }
+
+    /// Returns the paths of the Node::N and Node::EN in a subtree, parents before their children
+    pub fn nonterminal_paths(&self) -> Vec<Path> {
+        fn collect(node: &Node, path: &mut Path, paths: &mut Vec<Path>) {
+            match node {
+                Node::T(_) => (),
+                Node::N(_) => paths.push(path.clone()),
+                Node::EN(_, chl) => {
+                    paths.push(path.clone());
+                    for (idx, child) in chl.iter().enumerate() {
+                        path.push(idx);
+                        collect(&child.borrow(), path, paths);
+                        path.pop();
+                    }
+                }
+            }
+        }
+
+        let mut paths = Vec::new();
+        collect(self, &mut Vec::new(), &mut paths);
+        paths
+    }
+
+    /// Returns a copy of the subtree at the path
+    pub fn subtree(&self, path: &[usize]) -> Option<Node> {
+        match path.split_first() {
+            None => Some(self.clone()),
+            Some((idx, rest)) => match self {
+                Node::EN(_, chl) => chl.get(*idx)?.borrow().subtree(rest),
+                _ => None,
+            },
+        }
+    }
+
+    /// Calls `f` with the subtree at the path without copying it, None when there is no node at the path
+    pub fn at<R>(&self, path: &[usize], f: impl FnOnce(&Node) -> R) -> Option<R> {
+        match path.split_first() {
+            None => Some(f(self)),
+            Some((idx, rest)) => match self {
+                Node::EN(_, chl) => chl.get(*idx)?.borrow().at(rest, f),
+                _ => None,
+            },
+        }
+    }
+
+    /// Replaces the subtree at the path, returns the replaced subtree
+    pub fn replace_subtree(&mut self, path: &[usize], subtree: Node) -> Option<Node> {
+        match path.split_first() {
+            None => Some(std::mem::replace(self, subtree)),
+            Some((idx, rest)) => match self {
+                Node::EN(_, chl) => chl
+                    .roots
+                    .get_mut(*idx)?
+                    .get_mut()
+                    .replace_subtree(rest, subtree),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Identifies a node of a Node tree by the indices of the children on the way from the root
+pub type Path = Vec<usize>;
+
+// ---------------------------- Derivation Tree -------------------------------
+
+/// Identifies a node in a DerivationTree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+#[derive(Debug, Clone)]
+enum Slot {
+    Terminal,
+    /// An unexpanded nonterminal and its index in the list of open leaves
+    Open(usize),
+    Expanded(Vec<NodeId>),
+}
+
+#[derive(Debug, Clone)]
+struct ArenaNode {
+    symbol: String,
+    parent: Option<NodeId>,
+    depth: usize,
+    slot: Slot,
+}
+
+/// An arena-backed derivation tree that keeps track of its unexpanded nonterminal leaves,
+/// so that picking and expanding a leaf doesn't need to walk the tree
+#[derive(Debug, Clone)]
+pub struct DerivationTree {
+    nodes: Vec<ArenaNode>,
+    open: Vec<NodeId>,
+}
+
+impl DerivationTree {
+    /// A tree with a single unexpanded nonterminal node
+    pub fn new(symbol: &str) -> Self {
+        let mut tree = DerivationTree {
+            nodes: Vec::new(),
+            open: Vec::new(),
+        };
+        tree.push(symbol, None, true);
+        tree
+    }
+
+    /// A tree with a single unexpanded nonterminal node that replaces a node at `depth` of a larger tree,
+    /// the depths of its nodes are counted from the root of the larger tree
+    pub fn at_depth(symbol: &str, depth: usize) -> Self {
+        let mut tree = DerivationTree::new(symbol);
+        tree.nodes[0].depth = depth;
+        tree
+    }
+
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    /// The number of nodes in the tree
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn symbol(&self, id: NodeId) -> &str {
+        &self.nodes[id.0].symbol
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].parent
+    }
+
+    /// The number of ancestors of the node, the root is at depth 0 unless the tree is built with `at_depth`
+    pub fn depth(&self, id: NodeId) -> usize {
+        self.nodes[id.0].depth
+    }
+
+    /// The children of an expanded nonterminal, leaves have no children
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        match &self.nodes[id.0].slot {
+            Slot::Expanded(children) => children,
+            _ => &[],
+        }
+    }
+
+    pub fn is_terminal(&self, id: NodeId) -> bool {
+        matches!(self.nodes[id.0].slot, Slot::Terminal)
+    }
+
+    pub fn is_open(&self, id: NodeId) -> bool {
+        matches!(self.nodes[id.0].slot, Slot::Open(_))
+    }
+
+    /// The symbol of an unexpanded nonterminal leaf, None for any other node
+    pub fn open_symbol(&self, id: NodeId) -> Option<&str> {
+        if self.is_open(id) {
+            Some(self.symbol(id))
+        } else {
+            None
+        }
+    }
+
+    /// The unexpanded nonterminal leaves, in no particular order
+    pub fn open_leaves(&self) -> &[NodeId] {
+        &self.open
+    }
+
+    /// Returns true when there is an unexpanded nonterminal leaf
+    pub fn any_possible_expansions(&self) -> bool {
+        !self.open.is_empty()
+    }
+
+    /// Returns the number of unexpanded nonterminal leaves
+    pub fn num_possible_expansions(&self) -> usize {
+        self.open.len()
+    }
+
+    /// Splits the expansion-string into terminal and nonterminal children of an unexpanded leaf
+    /// and returns the new children
+    ///
+    /// Panics when the node is not an unexpanded nonterminal
+    pub fn expand(&mut self, leaf: NodeId, expansion: &str) -> &[NodeId] {
+        let idx = match self.nodes[leaf.0].slot {
+            Slot::Open(idx) => idx,
+            _ => panic!("{} is not an unexpanded nonterminal", self.symbol(leaf)),
+        };
+        self.close(idx);
+
+        let tokens = parser::tokens(expansion);
+        let children = if tokens.is_empty() {
+            vec![self.push("", Some(leaf), false)]
+        } else {
+            tokens
+                .iter()
+                .map(|token| match token {
+                    Token::Nonterminal(t) => self.push(t, Some(leaf), true),
+                    Token::Terminal(t) => self.push(t, Some(leaf), false),
+                })
+                .collect()
+        };
+        self.nodes[leaf.0].slot = Slot::Expanded(children);
+        self.children(leaf)
+    }
+
+    /// Builds the Node view of the tree
+    pub fn to_node(&self) -> Node {
+        self.subtree(self.root())
+    }
+
+    /// Builds the Node view of the subtree rooted at `id`
+    pub fn subtree(&self, id: NodeId) -> Node {
+        let node = &self.nodes[id.0];
+        match &node.slot {
+            Slot::Terminal => Node::new_terminal(&node.symbol),
+            Slot::Open(_) => Node::new_nonterminal(&node.symbol),
+            Slot::Expanded(children) => Node::new_expanded(
+                &node.symbol,
+                Children {
+                    roots: children
+                        .iter()
+                        .map(|child| RefCell::new(self.subtree(*child)))
+                        .collect(),
+                },
+            ),
+        }
+    }
+
+    /// A tree with an expanded root whose children are added with `push_terminal`, `push_expanded` and `push_subtree`
+    pub(crate) fn expanded_root(symbol: &str) -> Self {
+        let mut tree = DerivationTree {
+            nodes: Vec::new(),
+            open: Vec::new(),
+        };
+        let root = tree.push(symbol, None, false);
+        tree.nodes[root.0].slot = Slot::Expanded(Vec::new());
+        tree
+    }
+
+    pub(crate) fn push_terminal(&mut self, parent: NodeId, text: &str) -> NodeId {
+        self.push(text, Some(parent), false)
+    }
+
+    /// Adds an expanded nonterminal, its children are set with `set_children` once they have been added
+    pub(crate) fn push_expanded(&mut self, parent: NodeId, symbol: &str) -> NodeId {
+        let id = self.push(symbol, Some(parent), false);
+        self.nodes[id.0].slot = Slot::Expanded(Vec::new());
+        id
+    }
+
+    pub(crate) fn push_subtree(&mut self, parent: NodeId, node: &Node) -> NodeId {
+        self.push_node(node, Some(parent))
+    }
+
+    /// Sets the children of a node added by `expanded_root` or `push_expanded`, in order from left to right
+    pub(crate) fn set_children(&mut self, id: NodeId, children: Vec<NodeId>) {
+        self.nodes[id.0].slot = Slot::Expanded(children);
+    }
+
+    /// Adds a node to the arena, nonterminals are added as open leaves
+    fn push(&mut self, symbol: &str, parent: Option<NodeId>, nonterminal: bool) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        let slot = if nonterminal {
+            self.open.push(id);
+            Slot::Open(self.open.len() - 1)
+        } else {
+            Slot::Terminal
+        };
+        self.nodes.push(ArenaNode {
+            symbol: String::from(symbol),
+            parent,
+            depth: parent.map_or(0, |p| self.nodes[p.0].depth + 1),
+            slot,
+        });
+        id
+    }
+
+    /// Removes the open leaf at `idx` from the list of open leaves
+    fn close(&mut self, idx: usize) {
+        self.open.swap_remove(idx);
+        if let Some(moved) = self.open.get(idx) {
+            self.nodes[moved.0].slot = Slot::Open(idx);
+        }
+    }
+
+    /// Copies a Node into the arena
+    fn push_node(&mut self, node: &Node, parent: Option<NodeId>) -> NodeId {
+        match node {
+            Node::T(sym) => self.push(sym, parent, false),
+            Node::N(sym) => self.push(sym, parent, true),
+            Node::EN(sym, chl) => {
+                let id = self.push(sym, parent, false);
+                let children = chl
+                    .iter()
+                    .map(|child| self.push_node(&child.borrow(), Some(id)))
+                    .collect();
+                self.nodes[id.0].slot = Slot::Expanded(children);
+                id
+            }
+        }
+    }
+}
+
+impl From<&Node> for DerivationTree {
+    fn from(node: &Node) -> Self {
+        let mut tree = DerivationTree {
+            nodes: Vec::new(),
+            open: Vec::new(),
+        };
+        tree.push_node(node, None);
+        tree
+    }
+}
+
+impl PartialEq for DerivationTree {
+    /// Trees are equal when they have the same symbols in the same shape, whatever order their nodes were added in
+    fn eq(&self, other: &Self) -> bool {
+        let mut stack = vec![(self.root(), other.root())];
+        while let Some((a, b)) = stack.pop() {
+            let same_slot = match (&self.nodes[a.0].slot, &other.nodes[b.0].slot) {
+                (Slot::Terminal, Slot::Terminal) | (Slot::Open(_), Slot::Open(_)) => true,
+                (Slot::Expanded(xs), Slot::Expanded(ys)) if xs.len() == ys.len() => {
+                    stack.extend(xs.iter().cloned().zip(ys.iter().cloned()));
+                    true
+                }
+                _ => false,
+            };
+            if !same_slot || self.symbol(a) != other.symbol(b) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Eq for DerivationTree {}
+
+impl fmt::Display for DerivationTree {
+    /// Writes the leaves from left to right
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut stack = vec![self.root()];
+        while let Some(id) = stack.pop() {
+            match &self.nodes[id.0].slot {
+                Slot::Expanded(children) => stack.extend(children.iter().rev()),
+                _ => write!(f, "{}", self.symbol(id))?,
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -179,6 +578,42 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_derivation_tree_expand() {
+        let mut tree = DerivationTree::new("<int>");
+        let root = tree.root();
+        assert_eq!(tree.open_leaves(), &[root]);
+
+        let children = tree.expand(root, "<digit><int>").to_vec();
+        assert_eq!(tree.num_possible_expansions(), 2);
+        assert_eq!(tree.parent(children[1]), Some(root));
+        assert_eq!(tree.depth(children[1]), 1);
+        assert_eq!(tree.open_symbol(children[0]), Some("<digit>"));
+        assert_eq!(tree.open_symbol(root), None);
+
+        tree.expand(children[0], "7");
+        let grandchildren = tree.expand(children[1], "").to_vec();
+        assert!(tree.is_terminal(grandchildren[0]));
+        assert!(!tree.any_possible_expansions());
+        assert_eq!(tree.to_string(), "7");
+
+        let mut tree = DerivationTree::at_depth("<int>", 3);
+        let children = tree.expand(tree.root(), "<digit><int>").to_vec();
+        assert_eq!(tree.depth(children[0]), 4);
+    }
+
+    #[test]
+    fn test_derivation_tree_node_view() {
+        let node = int_derivation_tree(9);
+        let tree = DerivationTree::from(&node);
+        assert_eq!(tree.to_node(), node);
+        assert_eq!(tree.to_string(), node.to_string());
+
+        let tree = DerivationTree::from(&unexpanded_digit_derivation_tree());
+        assert_eq!(tree.num_possible_expansions(), 1);
+        assert_eq!(tree.to_string(), "<digit>");
+    }
+
     #[test]
     fn test_num_possible_expansions() {
         assert_eq!(int_derivation_tree(9).num_possible_expansions(), 0);
@@ -187,4 +622,27 @@ mod tests {
             1
         );
     }
+
+    #[test]
+    fn test_node_paths() {
+        let mut tree = int_derivation_tree(3);
+        assert_eq!(tree.num_nodes(), 7);
+        assert_eq!(tree.depth(), 3);
+        assert_eq!(
+            tree.nonterminal_paths(),
+            vec![vec![], vec![0], vec![0, 1], vec![1]]
+        );
+        assert_eq!(tree.subtree(&[1]), Some(digit_derivation_tree(3)));
+        assert_eq!(tree.subtree(&[1, 0, 0]), None);
+        assert_eq!(
+            tree.at(&[0, 1], |node| node.symbol().to_string()),
+            Some(String::from("<digit>"))
+        );
+        assert_eq!(tree.at(&[1, 0, 0], |node| node.num_nodes()), None);
+
+        let old = tree.replace_subtree(&[0, 1], digit_derivation_tree(7));
+        assert_eq!(old, Some(digit_derivation_tree(2)));
+        assert_eq!(tree.to_string(), "173");
+        assert_eq!(tree.subtree(&[0]).unwrap().symbol(), "<int>");
+    }
 }
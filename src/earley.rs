@@ -0,0 +1,753 @@
+//! Parses strings into derivation trees with an Earley parser, following the
+//! ["Parsing Inputs"](https://www.fuzzingbook.org/html/Parser.html) chapter of The Fuzzing Book
+//!
+//! The parser works on BNF grammars, EBNF grammars have to be converted with `ebnf_to_bnf` first.
+//! It supports any context-free grammar, including left recursive grammars and epsilon expansions,
+//! nullable symbols are handled as described by Aycock and Horspool in "Practical Earley Parsing".
+//!
+//! ## Example
+//!
+//! ```
+//! use grammar_fuzzer::{EarleyParser, Grammar};
+//! use std::collections::HashMap;
+//!
+//! let expansions: HashMap<_, _> = [
+//!     ("<start>", vec!["<expr>"]),
+//!     ("<expr>", vec!["<expr>+<digit>", "<digit>"]),
+//!     ("<digit>", vec!["0", "1", "2"]),
+//! ]
+//! .iter()
+//! .cloned()
+//! .collect();
+//! let grammar = Grammar::from(&expansions);
+//! let parser = EarleyParser::new(&grammar, "<start>");
+//!
+//! let tree = parser.parse("1+2+0").unwrap();
+//! assert_eq!(tree.to_string(), "1+2+0");
+//! assert!(parser.parse("1+").is_err());
+//! ```
+
+use super::derivation_tree::{Children, DerivationTree, Node, NodeId};
+use super::error::GrammarError;
+use super::grammar::Grammar;
+use super::parser::{self, Token};
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Item {
+    Terminal(String),
+    Nonterminal(String),
+}
+
+/// An alternative expansion of a symbol split into terminal and nonterminal items
+struct Rule {
+    symbol: String,
+    items: Vec<Item>,
+}
+
+/// A rule, how many of its items have been matched and the input position where the rule started
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct State {
+    rule: usize,
+    dot: usize,
+    origin: usize,
+}
+
+/// How a state was first added, following the links back to the prediction of a completed state
+/// gives the children of one of its derivation trees
+#[derive(Debug, Clone, Copy)]
+enum Link {
+    /// Nothing was matched yet
+    Predicted,
+    /// The previous item is a terminal that ends at the position of the state
+    Scanned,
+    /// The previous item is a nullable nonterminal that was skipped
+    Skipped,
+    /// The previous item is a nonterminal derived by the `child` state, which ends at the position of the state,
+    /// the state before the item is in the set of the child's origin
+    Completed { child: State },
+    /// Like `Completed`, but the states between the `child` and this one were skipped by a Leo item
+    Leo { child: State },
+}
+
+/// The only state of a set that waits for a symbol as its last item,
+/// `top` is the state that completing the symbol completes after every chained Leo item
+#[derive(Debug, Clone, Copy)]
+struct LeoItem {
+    waiting: State,
+    top: State,
+}
+
+/// The states of an input position, in the order they were added
+#[derive(Default)]
+struct StateSet {
+    states: Vec<State>,
+    links: HashMap<State, Link>,
+    leo: HashMap<String, Option<LeoItem>>,
+}
+
+impl StateSet {
+    fn add(&mut self, state: State, link: Link) {
+        if let Entry::Vacant(entry) = self.links.entry(state) {
+            entry.insert(link);
+            self.states.push(state);
+        }
+    }
+
+    fn contains(&self, state: &State) -> bool {
+        self.links.contains_key(state)
+    }
+}
+
+/// An Earley parser for the derivation trees of a start symbol
+pub struct EarleyParser {
+    start_symbol: String,
+    rules: Vec<Rule>,
+    by_symbol: HashMap<String, Vec<usize>>,
+    nullable: HashMap<String, usize>,
+}
+
+impl EarleyParser {
+    pub fn new<T>(grammar: &Grammar<T>, start_symbol: &str) -> Self {
+        let mut rules = Vec::new();
+        let mut by_symbol: HashMap<String, Vec<usize>> = HashMap::new();
+        for (symbol, alternatives) in grammar.iter() {
+            for expansion in alternatives {
+                let items = parser::tokens(&expansion.string)
+                    .iter()
+                    .map(|token| match token {
+                        Token::Terminal(t) => Item::Terminal(String::from(*t)),
+                        Token::Nonterminal(t) => Item::Nonterminal(String::from(*t)),
+                    })
+                    .collect();
+                by_symbol
+                    .entry(symbol.clone())
+                    .or_default()
+                    .push(rules.len());
+                rules.push(Rule {
+                    symbol: symbol.clone(),
+                    items,
+                });
+            }
+        }
+        let nullable = nullable_symbols(&rules);
+        EarleyParser {
+            start_symbol: String::from(start_symbol),
+            rules,
+            by_symbol,
+            nullable,
+        }
+    }
+
+    /// Parses the input into one of its derivation trees
+    ///
+    /// The tree is built without recursion from the links of the chart into an arena, so the trees of long inputs
+    /// can be displayed, cloned and dropped without overflowing the stack. Their `to_node` view is recursive
+    pub fn parse(&self, input: &str) -> Result<DerivationTree, GrammarError> {
+        let chart = self.chart(input, true)?;
+        let rule = chart
+            .completed(&self.start_symbol, 0, input.len())
+            .next()
+            .ok_or(GrammarError::ParseFailure {
+                position: input.len(),
+            })?;
+        Ok(chart.tree(State {
+            rule,
+            dot: self.rules[rule].items.len(),
+            origin: 0,
+        }))
+    }
+
+    /// Parses the input into all its derivation trees, an ambiguous grammar can have exponentially many of them
+    ///
+    /// Trees where a symbol derives the same part of the input inside itself are skipped,
+    /// there are infinitely many of them when a symbol can derive itself.
+    /// The trees are enumerated recursively, use `parse` for long inputs
+    pub fn parse_all(&self, input: &str) -> Result<Vec<DerivationTree>, GrammarError> {
+        let chart = self.chart(input, false)?;
+        Ok(chart
+            .trees(
+                &self.start_symbol,
+                0,
+                input.len(),
+                usize::MAX,
+                &mut HashSet::new(),
+            )
+            .iter()
+            .map(DerivationTree::from)
+            .collect())
+    }
+
+    /// A derivation tree of a nullable symbol for the empty string
+    fn empty_tree(&self, symbol: &str) -> Node {
+        let rule = &self.rules[self.nullable[symbol]];
+        let children = rule
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Nonterminal(symbol) => self.empty_tree(symbol),
+                Item::Terminal(_) => unreachable!("nullable rules only have nonterminals"),
+            })
+            .collect();
+        node(symbol, children)
+    }
+
+    /// Recognizes the input, the error reports the position of the first character that can't be parsed
+    ///
+    /// With `leo`, right recursion is recognized in linear time as described by Leo in "A general context-free
+    /// parsing algorithm running in linear time on every LR(k) grammar without using lookahead".
+    /// The states that Leo items skip are missing from the chart, so it only has the states of one tree per symbol
+    fn chart<'a>(&'a self, input: &'a str, leo: bool) -> Result<Chart<'a>, GrammarError> {
+        let start_rules = self
+            .by_symbol
+            .get(&self.start_symbol)
+            .ok_or_else(|| GrammarError::UndefinedNonterminals(vec![self.start_symbol.clone()]))?;
+        let mut sets: Vec<StateSet> = (0..=input.len()).map(|_| StateSet::default()).collect();
+        for rule in start_rules {
+            sets[0].add(
+                State {
+                    rule: *rule,
+                    dot: 0,
+                    origin: 0,
+                },
+                Link::Predicted,
+            );
+        }
+
+        for position in 0..=input.len() {
+            let mut next = 0;
+            while next < sets[position].states.len() {
+                let state = sets[position].states[next];
+                next += 1;
+                match self.rules[state.rule].items.get(state.dot) {
+                    // Predict
+                    Some(Item::Nonterminal(symbol)) => {
+                        for rule in self.by_symbol.get(symbol).into_iter().flatten() {
+                            sets[position].add(
+                                State {
+                                    rule: *rule,
+                                    dot: 0,
+                                    origin: position,
+                                },
+                                Link::Predicted,
+                            );
+                        }
+                        if self.nullable.contains_key(symbol) {
+                            sets[position].add(
+                                State {
+                                    dot: state.dot + 1,
+                                    ..state
+                                },
+                                Link::Skipped,
+                            );
+                        }
+                    }
+                    // Scan
+                    Some(Item::Terminal(terminal)) => {
+                        if input[position..].starts_with(terminal.as_str()) {
+                            sets[position + terminal.len()].add(
+                                State {
+                                    dot: state.dot + 1,
+                                    ..state
+                                },
+                                Link::Scanned,
+                            );
+                        }
+                    }
+                    // Complete
+                    None => {
+                        let symbol = &self.rules[state.rule].symbol;
+                        if leo && state.origin < position {
+                            if let Some(top) = self.leo_top(&mut sets, state.origin, symbol) {
+                                sets[position].add(top, Link::Leo { child: state });
+                                continue;
+                            }
+                        }
+                        let waiting: Vec<State> = sets[state.origin]
+                            .states
+                            .iter()
+                            .filter(|waiting| {
+                                matches!(
+                                    self.rules[waiting.rule].items.get(waiting.dot),
+                                    Some(Item::Nonterminal(waiting_for)) if waiting_for == symbol
+                                )
+                            })
+                            .cloned()
+                            .collect();
+                        for waiting in waiting {
+                            sets[position].add(
+                                State {
+                                    dot: waiting.dot + 1,
+                                    ..waiting
+                                },
+                                Link::Completed { child: state },
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        let chart = Chart {
+            parser: self,
+            input,
+            sets,
+        };
+        if chart.derives(&self.start_symbol, 0, input.len()) {
+            Ok(chart)
+        } else {
+            let position = (0..=input.len())
+                .rev()
+                .find(|position| !chart.sets[*position].states.is_empty())
+                .unwrap_or(0);
+            Err(GrammarError::ParseFailure { position })
+        }
+    }
+
+    /// The state completed by completing the symbol from the set at `position`, when a chain of Leo items applies.
+    /// A Leo item needs a unique waiting state whose last item is the symbol and that starts before the set,
+    /// so the chain always ends at a lower origin
+    fn leo_top(&self, sets: &mut [StateSet], position: usize, symbol: &str) -> Option<State> {
+        let mut chain: Vec<(usize, String, State)> = Vec::new();
+        let mut position = position;
+        let mut symbol = String::from(symbol);
+        let mut top = loop {
+            if let Some(item) = sets[position].leo.get(&symbol) {
+                break item.map(|item| item.top);
+            }
+            let mut waiting = sets[position].states.iter().filter(|state| {
+                matches!(
+                    self.rules[state.rule].items.get(state.dot),
+                    Some(Item::Nonterminal(waiting_for)) if *waiting_for == symbol
+                )
+            });
+            match (waiting.next(), waiting.next()) {
+                (Some(state), None)
+                    if state.dot + 1 == self.rules[state.rule].items.len()
+                        && state.origin < position =>
+                {
+                    let state = *state;
+                    chain.push((position, symbol, state));
+                    position = state.origin;
+                    symbol = self.rules[state.rule].symbol.clone();
+                }
+                _ => {
+                    sets[position].leo.insert(symbol, None);
+                    break None;
+                }
+            }
+        };
+        for (position, symbol, waiting) in chain.into_iter().rev() {
+            let item = LeoItem {
+                waiting,
+                top: top.unwrap_or(State {
+                    dot: waiting.dot + 1,
+                    ..waiting
+                }),
+            };
+            sets[position].leo.insert(symbol, Some(item));
+            top = Some(item.top);
+        }
+        top
+    }
+}
+
+/// The state sets of a recognized input
+struct Chart<'a> {
+    parser: &'a EarleyParser,
+    input: &'a str,
+    sets: Vec<StateSet>,
+}
+
+impl<'a> Chart<'a> {
+    fn rule(&self, rule: usize) -> &'a Rule {
+        &self.parser.rules[rule]
+    }
+
+    /// Whether a rule of the symbol was completed from `start` to `end`
+    fn derives(&self, symbol: &str, start: usize, end: usize) -> bool {
+        self.completed(symbol, start, end).next().is_some()
+    }
+
+    fn completed(
+        &self,
+        symbol: &str,
+        start: usize,
+        end: usize,
+    ) -> impl Iterator<Item = usize> + '_ {
+        let set = &self.sets[end];
+        self.parser
+            .by_symbol
+            .get(symbol)
+            .into_iter()
+            .flatten()
+            .cloned()
+            .filter(move |rule| {
+                set.contains(&State {
+                    rule: *rule,
+                    dot: self.rule(*rule).items.len(),
+                    origin: start,
+                })
+            })
+    }
+
+    /// Builds the derivation tree of a completed state that ends at the end of the input
+    ///
+    /// The links only point to states that were added before, so following them always reaches the predictions
+    fn tree(&self, completed: State) -> DerivationTree {
+        struct Frame {
+            state: State,
+            position: usize,
+            node: NodeId,
+            // the children are found from the last item to the first
+            children: Vec<NodeId>,
+            // the completed states skipped by a Leo item, each one is the last child of the next one
+            skipped: Vec<State>,
+        }
+
+        let mut tree = DerivationTree::expanded_root(&self.rule(completed.rule).symbol);
+        let mut stack = vec![Frame {
+            state: completed,
+            position: self.input.len(),
+            node: tree.root(),
+            children: Vec::new(),
+            skipped: Vec::new(),
+        }];
+        loop {
+            let frame = stack.last_mut().unwrap();
+            let rule = self.rule(frame.state.rule);
+            if frame.state.dot == 0 {
+                let frame = stack.pop().unwrap();
+                let mut children: Vec<NodeId> = frame.children.into_iter().rev().collect();
+                if children.is_empty() {
+                    children.push(tree.push_terminal(frame.node, ""));
+                }
+                tree.set_children(frame.node, children);
+                if stack.is_empty() {
+                    return tree;
+                }
+                continue;
+            }
+            let previous = State {
+                dot: frame.state.dot - 1,
+                ..frame.state
+            };
+            let link = match frame.skipped.pop() {
+                Some(child) => Link::Completed { child },
+                None => self.sets[frame.position].links[&frame.state],
+            };
+            let link = match link {
+                Link::Leo { child } => {
+                    frame.skipped = self.leo_chain(child, frame.state);
+                    Link::Completed {
+                        child: frame.skipped.pop().unwrap(),
+                    }
+                }
+                link => link,
+            };
+            let item = &rule.items[previous.dot];
+            frame.state = previous;
+            match (link, item) {
+                (Link::Scanned, Item::Terminal(terminal)) => {
+                    frame
+                        .children
+                        .push(tree.push_terminal(frame.node, terminal));
+                    frame.position -= terminal.len();
+                }
+                (Link::Skipped, Item::Nonterminal(symbol)) => {
+                    let empty_tree = self.parser.empty_tree(symbol);
+                    frame
+                        .children
+                        .push(tree.push_subtree(frame.node, &empty_tree));
+                }
+                (Link::Completed { child }, Item::Nonterminal(_)) => {
+                    let position = frame.position;
+                    frame.position = child.origin;
+                    let skipped = std::mem::take(&mut frame.skipped);
+                    let node = tree.push_expanded(frame.node, &self.rule(child.rule).symbol);
+                    frame.children.push(node);
+                    stack.push(Frame {
+                        state: child,
+                        position,
+                        node,
+                        children: Vec::new(),
+                        skipped,
+                    });
+                }
+                (link, item) => unreachable!("{:?} can't match {:?}", link, item),
+            }
+        }
+    }
+
+    /// The completed states from the `child` of a Leo link up to the state before `top`
+    fn leo_chain(&self, child: State, top: State) -> Vec<State> {
+        let mut chain = vec![child];
+        loop {
+            let completed = chain.last().unwrap();
+            let symbol = &self.rule(completed.rule).symbol;
+            let item = self.sets[completed.origin].leo[symbol].unwrap();
+            let next = State {
+                dot: item.waiting.dot + 1,
+                ..item.waiting
+            };
+            if next == top {
+                return chain;
+            }
+            chain.push(next);
+        }
+    }
+
+    /// Builds up to `limit` derivation trees of the symbol for the input from `start` to `end`,
+    /// `active` holds the symbols and spans that are being built to avoid building them inside themselves
+    fn trees(
+        &self,
+        symbol: &str,
+        start: usize,
+        end: usize,
+        limit: usize,
+        active: &mut HashSet<(String, usize, usize)>,
+    ) -> Vec<Node> {
+        let key = (String::from(symbol), start, end);
+        if !active.insert(key.clone()) {
+            return Vec::new();
+        }
+        let mut trees = Vec::new();
+        for rule in self.completed(symbol, start, end).collect::<Vec<_>>() {
+            let items = self.rule(rule).items.len();
+            for children in self.sequences(rule, items, start, end, limit - trees.len(), active) {
+                trees.push(node(symbol, children));
+            }
+            if trees.len() >= limit {
+                break;
+            }
+        }
+        active.remove(&key);
+        trees
+    }
+
+    /// Builds up to `limit` sequences of child nodes for the first `dot` items of a rule,
+    /// the items span the input from `origin` to `end`. The chart is walked backwards so
+    /// only the splits of the input that the recognizer found are tried.
+    fn sequences(
+        &self,
+        rule: usize,
+        dot: usize,
+        origin: usize,
+        end: usize,
+        limit: usize,
+        active: &mut HashSet<(String, usize, usize)>,
+    ) -> Vec<Vec<Node>> {
+        if dot == 0 {
+            return if origin == end {
+                vec![Vec::new()]
+            } else {
+                Vec::new()
+            };
+        }
+        let prefix_state = State {
+            rule,
+            dot: dot - 1,
+            origin,
+        };
+        let mut sequences = Vec::new();
+        match &self.rule(rule).items[dot - 1] {
+            Item::Terminal(terminal) => {
+                if end >= terminal.len()
+                    && self.input[..end].ends_with(terminal.as_str())
+                    && self.sets[end - terminal.len()].contains(&prefix_state)
+                {
+                    let mid = end - terminal.len();
+                    for mut prefix in self.sequences(rule, dot - 1, origin, mid, limit, active) {
+                        prefix.push(Node::new_terminal(terminal));
+                        sequences.push(prefix);
+                    }
+                }
+            }
+            Item::Nonterminal(symbol) => {
+                for mid in origin..=end {
+                    if !self.sets[mid].contains(&prefix_state) || !self.derives(symbol, mid, end) {
+                        continue;
+                    }
+                    let children = self.trees(symbol, mid, end, limit, active);
+                    if children.is_empty() {
+                        continue;
+                    }
+                    let prefixes = self.sequences(rule, dot - 1, origin, mid, limit, active);
+                    for prefix in prefixes.iter() {
+                        for child in children.iter() {
+                            let mut sequence: Vec<Node> = prefix.clone();
+                            sequence.push(child.clone());
+                            sequences.push(sequence);
+                            if sequences.len() >= limit {
+                                return sequences;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        sequences.truncate(limit);
+        sequences
+    }
+}
+
+/// An expanded node, a node without children derives the empty string
+fn node(symbol: &str, children: Vec<Node>) -> Node {
+    let children = if children.is_empty() {
+        Children::epsilon()
+    } else {
+        Children {
+            roots: children.into_iter().map(RefCell::new).collect(),
+        }
+    };
+    Node::new_expanded(symbol, children)
+}
+
+/// The symbols that can derive the empty string, with the rule that derives it.
+/// The items of that rule were found to be nullable before the symbol, so the rules never lead back to the symbol
+fn nullable_symbols(rules: &[Rule]) -> HashMap<String, usize> {
+    let mut nullable = HashMap::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (idx, rule) in rules.iter().enumerate() {
+            if nullable.contains_key(&rule.symbol) {
+                continue;
+            }
+            let all_nullable = rule.items.iter().all(|item| match item {
+                Item::Terminal(_) => false,
+                Item::Nonterminal(symbol) => nullable.contains_key(symbol),
+            });
+            if all_nullable {
+                nullable.insert(rule.symbol.clone(), idx);
+                changed = true;
+            }
+        }
+    }
+    nullable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fuzzer::GrammarFuzzer;
+    use crate::shared::grammar;
+    use crate::strategy::{CloseStrategy, RandomStrategy};
+
+    fn expr_grammar() -> Grammar<()> {
+        grammar(&[
+            ("<start>", vec!["<expr>"]),
+            ("<expr>", vec!["<expr> + <term>", "<term>"]),
+            ("<term>", vec!["(<expr>)", "<int>"]),
+            ("<int>", vec!["<digit><int>", "<digit>"]),
+            (
+                "<digit>",
+                vec!["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"],
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_parse_tree() {
+        let grammar = grammar(&[("<start>", vec!["<a>b"]), ("<a>", vec!["a<a>", ""])]);
+        let parser = EarleyParser::new(&grammar, "<start>");
+
+        let expected = Node::new_expanded(
+            "<start>",
+            Children {
+                roots: vec![
+                    RefCell::new(Node::new_expanded(
+                        "<a>",
+                        Children {
+                            roots: vec![
+                                RefCell::new(Node::new_terminal("a")),
+                                RefCell::new(Node::new_expanded("<a>", Children::epsilon())),
+                            ],
+                        },
+                    )),
+                    RefCell::new(Node::new_terminal("b")),
+                ],
+            },
+        );
+        assert_eq!(parser.parse("ab"), Ok(DerivationTree::from(&expected)));
+        assert_eq!(parser.parse("b").unwrap().to_string(), "b");
+    }
+
+    #[test]
+    fn test_parse_generated_inputs() {
+        let fuzzer = GrammarFuzzer::builder(expr_grammar())
+            .then(RandomStrategy::new(20, 100))
+            .then(CloseStrategy::new())
+            .seed(0)
+            .build();
+        let grammar = expr_grammar();
+        let parser = EarleyParser::new(&grammar, "<start>");
+
+        for _ in 0..20 {
+            let node = fuzzer.fuzz_tree();
+            // the expression grammar is unambiguous, so the parse tree is the generated tree
+            assert_eq!(
+                parser.parse(&node.to_string()),
+                Ok(DerivationTree::from(&node))
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_all() {
+        let grammar = grammar(&[("<e>", vec!["<e>+<e>", "<e>", "1"])]);
+        let parser = EarleyParser::new(&grammar, "<e>");
+
+        assert_eq!(parser.parse_all("1").unwrap().len(), 1);
+        assert_eq!(parser.parse_all("1+1").unwrap().len(), 1);
+        assert_eq!(parser.parse_all("1+1+1").unwrap().len(), 2);
+        assert!(parser
+            .parse_all("1+1+1+1")
+            .unwrap()
+            .iter()
+            .all(|tree| tree.to_string() == "1+1+1+1"));
+    }
+
+    #[test]
+    fn test_parse_long_inputs() {
+        // every character adds a level to the tree
+        let input = "a".repeat(100_000);
+        for expansion in &["<l>a", "a<l>"] {
+            let grammar = grammar(&[("<l>", vec![expansion, "a"])]);
+            let parser = EarleyParser::new(&grammar, "<l>");
+
+            let tree = parser.parse(&input).unwrap();
+            assert_eq!(tree.num_nodes(), 2 * input.len());
+            assert_eq!(tree.to_string(), input);
+            assert_eq!(tree.clone(), tree);
+            // the unambiguous short input has the same tree with and without Leo items
+            assert_eq!(
+                parser.parse("aaaa").ok(),
+                parser.parse_all("aaaa").unwrap().pop()
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_failure() {
+        let grammar = expr_grammar();
+        let parser = EarleyParser::new(&grammar, "<start>");
+
+        assert_eq!(
+            parser.parse("(1 + 2) * 3"),
+            Err(GrammarError::ParseFailure { position: 7 })
+        );
+        assert_eq!(
+            parser.parse("(1 + 2"),
+            Err(GrammarError::ParseFailure { position: 6 })
+        );
+        assert_eq!(
+            EarleyParser::new(&grammar, "<float>").parse("1"),
+            Err(GrammarError::UndefinedNonterminals(vec![String::from(
+                "<float>"
+            )]))
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Systematically enumerates every derivation tree of a grammar up to a bound,
+//! instead of sampling them at random
+//!
+//! The trees are produced in a deterministic order by expanding the leftmost nonterminal first
+//! and trying the alternatives in the order they are defined in the grammar.
+//! The precomputed cost and depth tables of the grammar are used to skip the expansions that
+//! can't be completed within the bound.
+//!
+//! ## Example
+//!
+//! ```
+//! use grammar_fuzzer::{Bound, Enumerator, Grammar};
+//! use std::collections::HashMap;
+//!
+//! let expansions: HashMap<_, _> = [
+//!     ("<expr>", vec!["<expr>+<digit>", "<digit>"]),
+//!     ("<digit>", vec!["0", "1"]),
+//! ]
+//! .iter()
+//! .cloned()
+//! .collect();
+//! let grammar = Grammar::from(&expansions);
+//!
+//! let inputs: Vec<String> = Enumerator::new(&grammar, "<expr>", Bound::Depth(3))
+//!     .map(|tree| tree.to_string())
+//!     .collect();
+//! assert_eq!(inputs, vec!["0+0", "0+1", "1+0", "1+1", "0", "1"]);
+//! ```
+
+use super::derivation_tree::{DerivationTree, Node, NodeId};
+use super::grammar::Grammar;
+use std::collections::HashSet;
+
+/// Limits the derivation trees that are enumerated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    /// The maximum number of nested nonterminal nodes from the root to a leaf,
+    /// the root expanded into terminals has depth 1
+    Depth(usize),
+    /// The maximum number of nonterminal nodes
+    Size(usize),
+}
+
+/// A partially expanded tree and the nonterminal leaves that are still open, the leftmost is the last one
+struct Frame {
+    tree: DerivationTree,
+    pending: Vec<NodeId>,
+    /// The number of expanded nodes plus the minimum number of expansions of the open leaves
+    size: f64,
+}
+
+/// An iterator over every derivation tree of a symbol within a bound, each tree is produced once
+pub struct Enumerator<'a, T> {
+    grammar: &'a Grammar<T>,
+    bound: Bound,
+    stack: Vec<Frame>,
+}
+
+impl<'a, T> Enumerator<'a, T> {
+    /// Enumerates the derivation trees of `start_symbol`,
+    /// a symbol that can't be turned into terminals within the bound, ex: an undefined symbol, has no trees
+    pub fn new(grammar: &'a Grammar<T>, start_symbol: &str, bound: Bound) -> Self {
+        let tree = DerivationTree::new(start_symbol);
+        let frame = Frame {
+            pending: vec![tree.root()],
+            size: grammar.symbol_cost(start_symbol, &HashSet::new()),
+            tree,
+        };
+        let fits = match bound {
+            Bound::Depth(depth) => grammar.symbol_depth(start_symbol) <= depth as f64,
+            Bound::Size(size) => frame.size <= size as f64,
+        };
+        Enumerator {
+            grammar,
+            bound,
+            stack: if fits { vec![frame] } else { Vec::new() },
+        }
+    }
+
+    /// The strings of the enumerated trees, without the repeated strings of an ambiguous grammar
+    pub fn strings(self) -> impl Iterator<Item = String> + 'a
+    where
+        T: 'a,
+    {
+        let mut seen = HashSet::new();
+        self.map(|tree| tree.to_string())
+            .filter(move |string| seen.insert(string.clone()))
+    }
+
+    /// Whether the tree can still be completed within the bound after expanding the leaf with an alternative
+    fn fits(&self, frame: &Frame, leaf: NodeId, alternative: usize) -> bool {
+        let symbol = frame.tree.symbol(leaf);
+        match self.bound {
+            Bound::Depth(depth) => {
+                let depths = self.grammar.expansion_depths(symbol);
+                frame.tree.depth(leaf) as f64 + depths[alternative] <= depth as f64
+            }
+            Bound::Size(size) => {
+                let costs = self.grammar.expansion_costs(symbol);
+                frame.size - self.grammar.symbol_cost(symbol, &HashSet::new()) + costs[alternative]
+                    <= size as f64
+            }
+        }
+    }
+}
+
+impl<'a, T> Iterator for Enumerator<'a, T> {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(frame) = self.stack.pop() {
+            let leaf = match frame.pending.last() {
+                Some(leaf) => *leaf,
+                None => return Some(frame.tree.to_node()),
+            };
+            let symbol = frame.tree.symbol(leaf);
+            let alternatives = match self.grammar.get(symbol) {
+                Some(alternatives) => alternatives,
+                None => continue,
+            };
+            let symbol_cost = self.grammar.symbol_cost(symbol, &HashSet::new());
+            // Pushed in reverse so that the first alternative is explored first
+            for idx in (0..alternatives.len()).rev() {
+                if !self.fits(&frame, leaf, idx) {
+                    continue;
+                }
+                let mut tree = frame.tree.clone();
+                let mut pending = frame.pending.clone();
+                pending.pop();
+                let children = tree.expand(leaf, &alternatives[idx].string).to_vec();
+                let open: Vec<NodeId> = children
+                    .iter()
+                    .rev()
+                    .filter(|child| tree.is_open(**child))
+                    .cloned()
+                    .collect();
+                pending.extend(open);
+                self.stack.push(Frame {
+                    tree,
+                    pending,
+                    size: frame.size - symbol_cost + self.grammar.expansion_costs(symbol)[idx],
+                });
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::grammar;
+
+    fn strings(enumerator: Enumerator<()>) -> Vec<String> {
+        enumerator.map(|tree| tree.to_string()).collect()
+    }
+
+    #[test]
+    fn test_enumerates_in_order() {
+        let grammar = grammar(&[
+            ("<start>", vec!["<digit><digit>"]),
+            ("<digit>", vec!["0", "1"]),
+        ]);
+
+        assert_eq!(
+            strings(Enumerator::new(&grammar, "<start>", Bound::Depth(2))),
+            vec!["00", "01", "10", "11"]
+        );
+        assert!(strings(Enumerator::new(&grammar, "<start>", Bound::Depth(1))).is_empty());
+        assert!(strings(Enumerator::new(&grammar, "<start>", Bound::Size(2))).is_empty());
+        assert_eq!(
+            strings(Enumerator::new(&grammar, "<start>", Bound::Size(3))).len(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_enumerates_recursive_grammar() {
+        let grammar = grammar(&[("<s>", vec!["(<s>)<s>", ""])]);
+
+        let by_depth = strings(Enumerator::new(&grammar, "<s>", Bound::Depth(3)));
+        assert_eq!(by_depth, vec!["(())()", "(())", "()()", "()", ""]);
+
+        let by_size = strings(Enumerator::new(&grammar, "<s>", Bound::Size(5)));
+        assert_eq!(by_size, vec!["(())", "()()", "()", ""]);
+        // 1 + 1 + 2 + 5 balanced strings with up to 3 pairs of parentheses
+        assert_eq!(Enumerator::new(&grammar, "<s>", Bound::Size(7)).count(), 9);
+        assert!(Enumerator::new(&grammar, "<s>", Bound::Size(5))
+            .all(|tree| !tree.any_possible_expansions()));
+    }
+
+    #[test]
+    fn test_strings_of_ambiguous_grammar() {
+        let grammar = grammar(&[("<e>", vec!["<e>+<e>", "1"])]);
+
+        assert_eq!(Enumerator::new(&grammar, "<e>", Bound::Size(5)).count(), 4);
+        assert_eq!(
+            Enumerator::new(&grammar, "<e>", Bound::Size(5))
+                .strings()
+                .collect::<Vec<_>>(),
+            vec!["1+1+1", "1+1", "1"]
+        );
+    }
+
+    #[test]
+    fn test_undefined_start_symbol() {
+        let grammar = grammar(&[("<e>", vec!["1"])]);
+        assert_eq!(
+            Enumerator::new(&grammar, "<start>", Bound::Depth(10)).count(),
+            0
+        );
+    }
+}
@@ -0,0 +1,180 @@
+//! Errors reported while building, validating, converting and expanding grammars,
+//! and while running target programs on the generated inputs
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Describes why a grammar can't be used by the fuzzer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GrammarError {
+    /// Nonterminal symbols that appear in expansions but have no alternatives defined
+    UndefinedNonterminals(Vec<String>),
+    /// Defined nonterminal symbols that can't be reached from the start symbol
+    UnreachableNonterminals(Vec<String>),
+    /// Nonterminal symbols that can't be expanded into a sequence of terminals
+    UnavoidableCycle(Vec<String>),
+    /// A grammar key that is not a nonterminal symbol, ex: `digit` instead of `<digit>`
+    InvalidNonterminal(String),
+    /// An expansion-string that can't be split into terminal and nonterminal tokens
+    MalformedToken { expansion: String, position: usize },
+    /// An EBNF expansion-string with a parenthesis that is never closed or never opened
+    UnbalancedParentheses { expansion: String, position: usize },
+    /// An EBNF operator that is not one of `*+?`
+    UnknownOperator(String),
+    /// A strategy didn't choose an expansion for the nonterminal symbol
+    NoExpansion(String),
+    /// A grammar file that doesn't follow the grammar file format
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// A grammar file that couldn't be read
+    Io(String),
+    /// A JSON grammar that doesn't map symbols to lists of expansions
+    Json(String),
+    /// A symbol whose expansion probabilities are not between 0 and 1 or sum to more than 1
+    InvalidProbabilities(String),
+    /// A symbol without derivation trees of the requested size
+    NoDerivationOfSize { symbol: String, size: usize },
+    /// A symbol without derivation trees within the depth limit of the fuzzer
+    NoDerivationOfDepth { symbol: String, depth: usize },
+    /// A symbol whose alternatives are all excluded by their guards for the inherited attributes of the node
+    NoAllowedExpansion(String),
+    /// A symbol that derives itself without changing the size, so it has infinitely many derivation trees of that size
+    InfiniteDerivations { symbol: String, size: usize },
+    /// A subtree that was rejected by the post-hook of its expansion every time it was generated
+    Rejected { symbol: String, attempts: usize },
+    /// A tree that kept violating a constraint after the subtrees of the violations it couldn't repair were regenerated
+    Unsatisfied { violation: String, attempts: usize },
+    /// An input that isn't in the language of the grammar, the position is the byte offset where parsing got stuck
+    ParseFailure { position: usize },
+}
+
+/// Formats a list of symbols as `<a>, <b>, <c>`
+fn join(symbols: &[String]) -> String {
+    symbols.join(", ")
+}
+
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GrammarError::UndefinedNonterminals(symbols) => {
+                write!(f, "undefined nonterminals: {}", join(symbols))
+            }
+            GrammarError::UnreachableNonterminals(symbols) => {
+                write!(f, "unreachable nonterminals: {}", join(symbols))
+            }
+            GrammarError::UnavoidableCycle(symbols) => {
+                write!(f, "nonterminals in unavoidable cycles: {}", join(symbols))
+            }
+            GrammarError::InvalidNonterminal(symbol) => {
+                write!(f, "invalid nonterminal symbol: {:?}", symbol)
+            }
+            GrammarError::MalformedToken {
+                expansion,
+                position,
+            } => write!(
+                f,
+                "malformed token at position {} of expansion {:?}",
+                position, expansion
+            ),
+            GrammarError::UnbalancedParentheses {
+                expansion,
+                position,
+            } => write!(
+                f,
+                "unbalanced parenthesis at position {} of expansion {:?}",
+                position, expansion
+            ),
+            GrammarError::UnknownOperator(op) => write!(f, "unknown EBNF operator: {:?}", op),
+            GrammarError::NoExpansion(symbol) => {
+                write!(f, "no expansion was chosen for nonterminal {}", symbol)
+            }
+            GrammarError::Syntax {
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}: {}", line, column, message),
+            GrammarError::Io(message) => write!(f, "{}", message),
+            GrammarError::Json(message) => write!(f, "invalid JSON grammar: {}", message),
+            GrammarError::InvalidProbabilities(symbol) => write!(
+                f,
+                "the expansion probabilities of {} must be between 0 and 1 and sum to at most 1",
+                symbol
+            ),
+            GrammarError::NoDerivationOfSize { symbol, size } => {
+                write!(f, "{} has no derivation tree of size {}", symbol, size)
+            }
+            GrammarError::NoDerivationOfDepth { symbol, depth } => write!(
+                f,
+                "{} has no derivation tree of depth {} or less",
+                symbol, depth
+            ),
+            GrammarError::NoAllowedExpansion(symbol) => write!(
+                f,
+                "no expansion of {} is allowed by the inherited attributes",
+                symbol
+            ),
+            GrammarError::InfiniteDerivations { symbol, size } => write!(
+                f,
+                "{} has infinitely many derivation trees of size {}",
+                symbol, size
+            ),
+            GrammarError::Rejected { symbol, attempts } => write!(
+                f,
+                "{} was rejected by its post-hook {} times",
+                symbol, attempts
+            ),
+            GrammarError::Unsatisfied {
+                violation,
+                attempts,
+            } => write!(f, "{}, still after {} regenerations", violation, attempts),
+            GrammarError::ParseFailure { position } => {
+                write!(f, "the input can't be parsed at position {}", position)
+            }
+        }
+    }
+}
+
+impl Error for GrammarError {}
+
+/// Describes why a `Runner` stopped before running the target program on every input
+#[derive(Debug)]
+pub enum RunnerError {
+    /// The fuzzer couldn't generate an input
+    Generate(GrammarError),
+    /// The target program couldn't be run or a crash couldn't be saved
+    Io(io::Error),
+}
+
+impl fmt::Display for RunnerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunnerError::Generate(error) => write!(f, "{}", error),
+            RunnerError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for RunnerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RunnerError::Generate(error) => Some(error),
+            RunnerError::Io(error) => Some(error),
+        }
+    }
+}
+
+impl From<GrammarError> for RunnerError {
+    fn from(error: GrammarError) -> Self {
+        RunnerError::Generate(error)
+    }
+}
+
+impl From<io::Error> for RunnerError {
+    fn from(error: io::Error) -> Self {
+        RunnerError::Io(error)
+    }
+}
@@ -5,7 +5,7 @@
 //! ```
 //! use grammar_fuzzer::{Grammar, ebnf_to_bnf};
 //! use std::collections::HashMap;
-//! 
+//!
 //! let ebnf_grammar: HashMap<&str, Vec<&str>> = [
 //!     ("<list>", vec!["[(<string>, )*<string>]"]),
 //!     ("<assoc>", vec!["{(<string>: <string>, )+}"]),
@@ -20,22 +20,46 @@
 //!
 //! let bnf_grammar = ebnf_to_bnf(&ebnf_grammar);
 //! ```
+//!
+//! The grammars of the `loader` escape the characters `\()*+?` of the terminals in their EBNF expansions,
+//! the escapes are removed from the converted grammar and their expansions without EBNF operators are kept as they are
 
+use super::error::GrammarError;
 use super::grammar::{Alternatives, Expansion, Expansions, Grammar};
 use super::parser;
 use std::collections::HashSet;
 
 /// Converts a grammar in EBNF to BNF, the only supported EBNF operators are: `*+?`
-pub fn ebnf_to_bnf<T: Copy>(grammar: &Grammar<T>) -> Grammar<T> {
-    let grammar = convert_grammar(grammar, convert_ebnf_parentheses);
-    let grammar = convert_grammar(&grammar, convert_ebnf_operators);
-    grammar
+///
+/// Panics when the grammar can't be converted, see `try_ebnf_to_bnf`
+pub fn ebnf_to_bnf<T: Clone>(grammar: &Grammar<T>) -> Grammar<T> {
+    try_ebnf_to_bnf(grammar).unwrap_or_else(|error| panic!("{}", error))
+}
+
+/// Converts a grammar in EBNF to BNF, the only supported EBNF operators are: `*+?`
+///
+/// The attribute rules are kept, the rules of the converted EBNF expansions no longer match any expansion
+pub fn try_ebnf_to_bnf<T: Clone>(grammar: &Grammar<T>) -> Result<Grammar<T>, GrammarError> {
+    let no_verbatim = HashSet::new();
+    let verbatim = grammar.verbatim().unwrap_or(&no_verbatim);
+    let bnf = convert_grammar(grammar, verbatim, convert_ebnf_parentheses)?;
+    let bnf = convert_grammar(&bnf, verbatim, convert_ebnf_operators)?;
+    // only loaded grammars escape their terminals
+    let bnf = match grammar.verbatim() {
+        Some(_) => convert_grammar(&bnf, verbatim, unescape_terminals)?,
+        None => bnf,
+    };
+    Ok(bnf.with_attributes(grammar.attributes().clone()))
 }
 
-/// Invokes `apply` function with all expansions in a grammar and returns a new grammar
-fn convert_grammar<T: Copy, F>(grammar: &Grammar<T>, apply: F) -> Grammar<T>
+/// Invokes `apply` function with all expansions in a grammar, except the verbatim ones, and returns a new grammar
+fn convert_grammar<T: Clone, F>(
+    grammar: &Grammar<T>,
+    verbatim: &HashSet<(String, String)>,
+    apply: F,
+) -> Result<Grammar<T>, GrammarError>
 where
-    F: Fn(&Expansion<T>, &mut Symbols) -> (Expansion<T>, Expansions<T>),
+    F: Fn(&Expansion<T>, &mut Symbols) -> Result<(Expansion<T>, Expansions<T>), GrammarError>,
 {
     let mut expansions_for_new_grammar = Expansions::new();
     let mut new_symbol = Symbols::from(grammar);
@@ -45,33 +69,46 @@ where
 
     for token in tokens.iter() {
         for expansion in &grammar[token] {
-            let (converted_expansion, new_expansions) = apply(expansion, &mut new_symbol);
+            if verbatim.contains(&(token.clone(), expansion.string.clone())) {
+                expansions_for_new_grammar
+                    .entry(token.to_owned())
+                    .or_insert_with(Vec::new)
+                    .push(expansion.clone());
+                continue;
+            }
+            let (converted_expansion, new_expansions) = apply(expansion, &mut new_symbol)?;
             expansions_for_new_grammar
                 .entry(token.to_owned())
-                .or_insert(Vec::new())
+                .or_insert_with(Vec::new)
                 .push(converted_expansion);
             expansions_for_new_grammar.extend(new_expansions);
         }
     }
 
-    Grammar::new(expansions_for_new_grammar)
+    Grammar::try_new(expansions_for_new_grammar)
 }
 
 /// Converts parenthesized expressions, ex: `(<json>)+`
-fn convert_ebnf_parentheses<T: Copy>(
+fn convert_ebnf_parentheses<T: Clone>(
     expansion: &Expansion<T>,
     symbols: &mut Symbols,
-) -> (Expansion<T>, Expansions<T>) {
+) -> Result<(Expansion<T>, Expansions<T>), GrammarError> {
+    if let Some(position) = parser::unbalanced_parenthesis(&expansion.string) {
+        return Err(GrammarError::UnbalancedParentheses {
+            expansion: expansion.string.clone(),
+            position,
+        });
+    }
+
     let mut expansion_symbol = expansion.string.clone();
     let mut new_expansions = Expansions::new();
     loop {
         if let Some(expression) = parser::next_parenthesized_expression(&expansion_symbol.clone()) {
             let new_symbol = symbols.new(None);
 
-            expansion_symbol = expansion_symbol.replacen(
-                expression.token,
+            expansion_symbol.replace_range(
+                expression.position..expression.position + expression.token.len(),
                 &format!("{}{}", new_symbol, expression.op),
-                1,
             );
             new_expansions.insert(new_symbol, vec![Expansion::new(expression.content, None)]);
         } else {
@@ -79,17 +116,17 @@ fn convert_ebnf_parentheses<T: Copy>(
         }
     }
 
-    (
-        Expansion::new(&expansion_symbol, expansion.opts),
+    Ok((
+        Expansion::new(&expansion_symbol, expansion.opts.clone()),
         new_expansions,
-    )
+    ))
 }
 
 /// Converts extended nonterminals, ex: `<json>+`
-fn convert_ebnf_operators<T: Copy>(
+fn convert_ebnf_operators<T: Clone>(
     expansion: &Expansion<T>,
     symbols: &mut Symbols,
-) -> (Expansion<T>, Expansions<T>) {
+) -> Result<(Expansion<T>, Expansions<T>), GrammarError> {
     let mut expansion_symbol = expansion.string.clone();
     let mut new_expansions = Expansions::new();
     loop {
@@ -99,36 +136,45 @@ fn convert_ebnf_operators<T: Copy>(
             expansion_symbol = expansion_symbol.replacen(extension.token, &new_symbol, 1);
             new_expansions.insert(
                 new_symbol.clone(),
-                operator_expansions(&extension, &new_symbol),
+                operator_expansions(&extension, &new_symbol)?,
             );
         } else {
             break;
         }
     }
 
-    (
-        Expansion::new(&expansion_symbol, expansion.opts),
+    Ok((
+        Expansion::new(&expansion_symbol, expansion.opts.clone()),
         new_expansions,
-    )
+    ))
+}
+
+/// Removes the escapes once the groups and operators are converted
+fn unescape_terminals<T: Clone>(
+    expansion: &Expansion<T>,
+    _symbols: &mut Symbols,
+) -> Result<(Expansion<T>, Expansions<T>), GrammarError> {
+    Ok((
+        Expansion::new(&parser::unescape(&expansion.string), expansion.opts.clone()),
+        Expansions::new(),
+    ))
 }
 
 fn operator_expansions<T>(
     extension: &parser::ExtendedNonterminal,
     new_symbol: &str,
-) -> Alternatives<T> {
+) -> Result<Alternatives<T>, GrammarError> {
     let original_symbol = String::from(extension.symbol);
-    match extension.op {
-        "?" => vec![format!(""), original_symbol],
-        "*" => vec![format!(""), format!("{}{}", original_symbol, new_symbol)],
+    let expansions = match extension.op {
+        "?" => vec![String::new(), original_symbol],
+        "*" => vec![String::new(), format!("{}{}", original_symbol, new_symbol)],
         "+" => vec![
-            format!("{}", original_symbol),
+            original_symbol.clone(),
             format!("{}{}", original_symbol, new_symbol),
         ],
-        _ => panic!(),
-    }
-    .iter()
-    .map(|e| Expansion::new(e, None))
-    .collect()
+        op => return Err(GrammarError::UnknownOperator(String::from(op))),
+    };
+    Ok(expansions.iter().map(|e| Expansion::new(e, None)).collect())
 }
 
 // -------------------------------- NewSymbols --------------------------------
@@ -206,4 +252,39 @@ mod tests {
 
         assert_eq!(ebnf_to_bnf(&ebnf_grammar), expected_bnf_grammar);
     }
+
+    #[test]
+    fn test_try_ebnf_to_bnf_errors() {
+        let grammar = |expansion| {
+            let expansions: HashMap<&str, Vec<&str>> =
+                [("<start>", vec![expansion])].iter().cloned().collect();
+            Grammar::from(&expansions)
+        };
+
+        assert_eq!(
+            try_ebnf_to_bnf(&grammar("<a>*+")),
+            Err(GrammarError::UnknownOperator(String::from("*+")))
+        );
+        assert_eq!(
+            try_ebnf_to_bnf(&grammar("[(<a>, *<a>]")),
+            Err(GrammarError::UnbalancedParentheses {
+                expansion: String::from("[(<a>, *<a>]"),
+                position: 1
+            })
+        );
+        // a parenthesis that doesn't open or close a group is a terminal
+        assert_eq!(try_ebnf_to_bnf(&grammar("(")), Ok(grammar("(")));
+        // only loaded grammars escape their terminals
+        assert_eq!(
+            try_ebnf_to_bnf(&grammar("x\\+y\\\\")),
+            Ok(grammar("x\\+y\\\\"))
+        );
+        assert_eq!(
+            try_ebnf_to_bnf(&grammar("<a> < <b>")),
+            Err(GrammarError::MalformedToken {
+                expansion: String::from("<a> < <b>"),
+                position: 4
+            })
+        );
+    }
 }
@@ -3,13 +3,9 @@
 //! # Example
 //!
 //! ```
-//! use grammar_fuzzer::{Grammar, GrammarFuzzer, Node};
-//! use grammar_fuzzer::{RandomStrategy, Strategy};
+//! use grammar_fuzzer::{CloseStrategy, Grammar, GrammarFuzzer, Node, RandomStrategy};
 //! use std::collections::HashMap;
-//! 
-//! // Strategies
-//! let random = RandomStrategy::new(10, 8000);
-//! let strategies: Vec<&dyn Strategy<()>> = vec![&random];
+//!
 //! // Grammar
 //! let expansios: HashMap<_, _> = [
 //!         ("<string>", vec!["<string><char>", "<char>"]),
@@ -20,83 +16,1297 @@
 //!     .collect();
 //! let grammar = Grammar::from(&expansios);
 //! assert_eq!(grammar.is_valid_grammar(Some("<string>")), true);
-//! // Fuzzer
-//! let fuzzer = GrammarFuzzer::new(grammar, &strategies);
-//! // Expand the derivation tree
-//! let mut node = Node::N(String::from("<string>"));
-//! fuzzer.expand_tree(&mut node);
+//! // Fuzzer with its strategies, seeded to make the generated inputs reproducible
+//! let fuzzer = GrammarFuzzer::builder(grammar)
+//!     .then(RandomStrategy::new(10, 8000))
+//!     .then(CloseStrategy::new())
+//!     .seed(42)
+//!     .start_symbol("<string>")
+//!     .build();
+//! // Expand a derivation tree from the start symbol
+//! let node: Node = fuzzer.fuzz_tree();
 //! println!("{}\n", node);
+//! // Or any other nonterminal
+//! println!("{}\n", fuzzer.fuzz_from("<char>"));
 //! ```
-use super::derivation_tree::{Children, Node};
+use super::constraints::Constraint;
+use super::derivation_tree::{Children, DerivationTree, Node, NodeId};
+use super::earley::EarleyParser;
+use super::error::GrammarError;
 use super::grammar::Grammar;
-use super::shared::random_element;
-use super::strategy::Strategy;
+use super::hooks::{ExpansionHooks, Verdict};
+use super::shared::min_idx;
+use super::strategy::{expansions_within_depth, Strategy};
+use super::uniform::{Measure, UniformSampler};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+
+/// The number of times a subtree rejected by a post-hook is generated,
+/// or the subtree of a violation that can't be repaired is regenerated, before giving up
+const MAX_REGENERATIONS: usize = 100;
 
-pub struct GrammarFuzzer<'a, T> {
+/// The number of times a strategy is asked again for an expansion of a leaf when its guard doesn't allow
+/// the chosen expansion, before the cheapest allowed expansion is taken
+const MAX_RESAMPLES: usize = 32;
+
+pub struct GrammarFuzzer<T> {
     grammar: Grammar<T>,
-    steps: &'a Vec<&'a dyn Strategy<T>>,
+    steps: Vec<Box<dyn Strategy<T>>>,
+    constraints: Vec<Box<dyn Constraint>>,
+    start_symbol: String,
+    max_depth: Option<usize>,
+    rng: Mutex<Box<dyn RngCore + Send>>,
+    samplers: Mutex<HashMap<Measure, UniformSampler>>,
+    /// The parsers that check the repairs of the constraints, by the symbol of the repaired subtree
+    parsers: Mutex<HashMap<String, EarleyParser>>,
+    /// The entry points that passed `Grammar::validate_entry_point`
+    entry_points: Mutex<HashSet<String>>,
 }
 
-impl<'a, T> GrammarFuzzer<'a, T> {
-    pub fn new(grammar: Grammar<T>, steps: &'a Vec<&'a dyn Strategy<T>>) -> GrammarFuzzer<'a, T> {
-        GrammarFuzzer { grammar, steps }
+impl<T> GrammarFuzzer<T> {
+    /// Starts building a fuzzer for the grammar, see `GrammarFuzzerBuilder`
+    pub fn builder(grammar: Grammar<T>) -> GrammarFuzzerBuilder<T> {
+        GrammarFuzzerBuilder {
+            grammar,
+            steps: Vec::new(),
+            constraints: Vec::new(),
+            rng: None,
+            start_symbol: String::from("<start>"),
+            max_depth: None,
+        }
+    }
+
+    /// Creates a fuzzer whose random choices are seeded from the operating system
+    pub fn new(grammar: Grammar<T>, steps: Vec<Box<dyn Strategy<T>>>) -> GrammarFuzzer<T> {
+        GrammarFuzzer::with_rng(grammar, steps, StdRng::from_entropy())
+    }
+
+    /// Creates a fuzzer that generates the same derivation trees for the same grammar, strategies and seed
+    pub fn with_seed(
+        grammar: Grammar<T>,
+        steps: Vec<Box<dyn Strategy<T>>>,
+        seed: u64,
+    ) -> GrammarFuzzer<T> {
+        GrammarFuzzer::with_rng(grammar, steps, StdRng::seed_from_u64(seed))
+    }
+
+    /// Creates a fuzzer that makes all its random choices with `rng`
+    pub fn with_rng<R: RngCore + Send + 'static>(
+        grammar: Grammar<T>,
+        steps: Vec<Box<dyn Strategy<T>>>,
+        rng: R,
+    ) -> GrammarFuzzer<T> {
+        GrammarFuzzer {
+            grammar,
+            steps,
+            constraints: Vec::new(),
+            start_symbol: String::from("<start>"),
+            max_depth: None,
+            rng: Mutex::new(Box::new(rng)),
+            samplers: Mutex::new(HashMap::new()),
+            parsers: Mutex::new(HashMap::new()),
+            entry_points: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Changes the symbol that `fuzz` and `fuzz_tree` expand, it's `<start>` by default
+    pub fn with_start_symbol(mut self, symbol: &str) -> Self {
+        self.start_symbol = String::from(symbol);
+        self
+    }
+
+    /// The symbol that `fuzz` and `fuzz_tree` expand
+    pub fn start_symbol(&self) -> &str {
+        &self.start_symbol
     }
 
-    /// Selects an expansion given a strategy and divides the expansion-string into
+    /// Bounds the derivation depth of the trees, the root is at depth 0 and its children at depth 1
+    ///
+    /// An expansion chosen by a strategy that can't be completed within the depth left is replaced
+    /// with the cheapest expansion that can, so that every strategy of the pipeline respects the limit
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Adds a constraint that every generated tree satisfies, see the `constraints` module
+    pub fn with_constraint<C: Constraint + 'static>(mut self, constraint: C) -> Self {
+        self.constraints.push(Box::new(constraint));
+        self
+    }
+
+    /// The depth limit of the derivation trees, if any
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    /// The grammar used to expand the derivation trees
+    pub fn grammar(&self) -> &Grammar<T> {
+        &self.grammar
+    }
+
+    /// The source of randomness of the fuzzer, shared with the tools built on top of it
+    /// so that a single seed reproduces all their choices
+    pub(crate) fn rng(&self) -> MutexGuard<'_, Box<dyn RngCore + Send>> {
+        self.rng.lock().unwrap()
+    }
+
+    /// Restarts the random choices of the fuzzer from `seed`
+    pub fn reseed(&self, seed: u64) {
+        *self.rng() = Box::new(StdRng::seed_from_u64(seed));
+    }
+
+    /// Selects an expansion for an unexpanded leaf given a strategy and divides the expansion-string into
     /// a sequence of terminal and nonterminal child nodes
-    fn expand_nonterminal(&self, node: &Node, strategy: &dyn Strategy<T>) -> Children {
-        match node {
-            Node::N(_) => {
-                let chosen_expantion = strategy.choose(&self.grammar, &node).unwrap();
-                let children = Children::from(chosen_expantion.as_str());
-                children
+    fn expand_leaf(
+        &self,
+        tree: &mut DerivationTree,
+        leaf: NodeId,
+        strategy: &dyn Strategy<T>,
+        rng: &mut dyn RngCore,
+    ) -> Result<(), GrammarError> {
+        let symbol = tree.symbol(leaf);
+        match self.grammar.get(symbol) {
+            None => Err(GrammarError::UndefinedNonterminals(vec![String::from(
+                symbol,
+            )])),
+            Some(alternatives) if alternatives.is_empty() => {
+                Err(GrammarError::NoExpansion(String::from(symbol)))
             }
-            _ => panic!(),
+            Some(_) => match strategy.choose(&self.grammar, tree, leaf, rng) {
+                Some(chosen_expansion) => {
+                    let chosen_expansion =
+                        self.allowed(tree, leaf, chosen_expansion, strategy, rng)?;
+                    tree.expand(leaf, &chosen_expansion);
+                    for step in self.steps.iter() {
+                        step.expanded(tree, leaf, &chosen_expansion);
+                    }
+                    Ok(())
+                }
+                None => Err(GrammarError::NoExpansion(String::from(symbol))),
+            },
         }
     }
 
-    /// Expands a nonterminal leaf node in the derivation tree
-    fn expand_tree_once(&self, node: &mut Node, strategy: &dyn Strategy<T>) {
-        match node {
-            Node::T(_) => (),
-            Node::N(sym) => {
-                let children = self.expand_nonterminal(&Node::N(sym.to_owned()), strategy);
-                let new_subtree = Node::new_expanded(sym, children);
-                std::mem::replace(node, new_subtree);
-            }
-            Node::EN(_, Children { roots }) => {
-                let random_root = random_element(&roots, |r| r.borrow().any_possible_expansions());
-                if let Some(root) = random_root {
-                    let root: &mut Node = &mut *root.borrow_mut();
-                    self.expand_tree_once(root, strategy);
+    /// Keeps the chosen expansion when it can be completed within `max_depth` and its guard
+    /// allows the inherited attributes of the leaf, otherwise picks the cheapest expansion that is allowed
+    ///
+    /// An expansion that its guard doesn't allow is chosen again by the strategy, so that the strategy
+    /// picks among the allowed expansions as if the others weren't there
+    fn allowed(
+        &self,
+        tree: &DerivationTree,
+        leaf: NodeId,
+        mut chosen_expansion: String,
+        strategy: &dyn Strategy<T>,
+        rng: &mut dyn RngCore,
+    ) -> Result<String, GrammarError> {
+        let symbol = tree.symbol(leaf);
+        let rules = self.grammar.attributes();
+        if self.max_depth.is_none() && !rules.has_guards(symbol) {
+            return Ok(chosen_expansion);
+        }
+
+        let alternatives = &self.grammar[symbol];
+        let mut allowed = match self.max_depth {
+            Some(max_depth) => {
+                let fitting = expansions_within_depth(&self.grammar, tree, leaf, max_depth);
+                if fitting.is_empty() {
+                    return Err(GrammarError::NoDerivationOfDepth {
+                        symbol: String::from(symbol),
+                        depth: max_depth.saturating_sub(tree.depth(leaf)),
+                    });
                 }
-                ()
+                fitting
+            }
+            None => (0..alternatives.len()).collect(),
+        };
+        let inherited = if rules.has_guards(symbol) {
+            let inherited = rules.inherited_at(tree, leaf);
+            allowed.retain(|idx| rules.allows(symbol, &alternatives[*idx].string, &inherited));
+            if allowed.is_empty() {
+                return Err(GrammarError::NoAllowedExpansion(String::from(symbol)));
+            }
+            Some(inherited)
+        } else {
+            None
+        };
+        for _ in 0..MAX_RESAMPLES {
+            if allowed
+                .iter()
+                .any(|idx| alternatives[*idx].string == chosen_expansion)
+            {
+                return Ok(chosen_expansion);
+            }
+            let guarded_out = inherited.as_ref().map_or(false, |inherited| {
+                !rules.allows(symbol, &chosen_expansion, inherited)
+            });
+            if !guarded_out {
+                break;
+            }
+            match strategy.choose(&self.grammar, tree, leaf, rng) {
+                Some(expansion) => chosen_expansion = expansion,
+                None => break,
             }
         }
+
+        let costs = self.grammar.expansion_costs(symbol);
+        let allowed_costs: Vec<f64> = allowed.iter().map(|idx| costs[*idx]).collect();
+        let idx = allowed[min_idx(&allowed_costs, rng)];
+        Ok(alternatives[idx].string.clone())
     }
 
     /// Expands the derivation tree following a strategy
     /// it terminates when `strategy.cont` returns false or when all the nonterminal nodes have been expanded
-    fn expand_tree_with_strategy(&self, root: &mut Node, strategy: &dyn Strategy<T>) {
+    fn expand_tree_with_strategy(
+        &self,
+        tree: &mut DerivationTree,
+        strategy: &dyn Strategy<T>,
+        rng: &mut dyn RngCore,
+    ) -> Result<(), GrammarError> {
         let mut step = 0;
         loop {
-            if !root.any_possible_expansions() {
+            if !tree.any_possible_expansions() {
                 break;
             }
 
-            if !strategy.cont(root, step) {
+            if !strategy.cont(tree, step) {
                 break;
             }
 
-            self.expand_tree_once(root, strategy);
+            let open_leaves = tree.open_leaves();
+            let leaf = open_leaves[rng.gen_range(0, open_leaves.len())];
+            self.expand_leaf(tree, leaf, strategy, rng)?;
             step += 1;
         }
+        Ok(())
     }
 
     /// Applies a sequence of strategies
+    ///
+    /// Panics when a nonterminal can't be expanded, see `try_expand_tree`
     pub fn expand_tree(&self, root: &mut Node) {
-        for strategy in self.steps {
-            self.expand_tree_with_strategy(root, *strategy);
+        if let Err(error) = self.try_expand_tree(root) {
+            panic!("{}", error);
+        }
+    }
+
+    /// Applies a sequence of strategies, stops at the first nonterminal that can't be expanded
+    pub fn try_expand_tree(&self, root: &mut Node) -> Result<(), GrammarError> {
+        let mut tree = DerivationTree::from(&*root);
+        let result = self.try_expand_derivation_tree(&mut tree);
+        *root = tree.to_node();
+        result
+    }
+
+    /// Replaces an unexpanded root with a derivation tree drawn uniformly at random among
+    /// all the derivation trees of its symbol with the given size, the strategies are not used
+    ///
+    /// Panics when there is no such tree, see `try_expand_tree_uniform`
+    pub fn expand_tree_uniform(&self, root: &mut Node, measure: Measure, size: usize) {
+        if let Err(error) = self.try_expand_tree_uniform(root, measure, size) {
+            panic!("{}", error);
+        }
+    }
+
+    /// Replaces an unexpanded root with a derivation tree drawn uniformly at random among
+    /// all the derivation trees of its symbol with the given size, other nodes are left unchanged
+    ///
+    /// The tree counts are memoized by the fuzzer, so drawing more trees from the same symbol is cheap
+    pub fn try_expand_tree_uniform(
+        &self,
+        root: &mut Node,
+        measure: Measure,
+        size: usize,
+    ) -> Result<(), GrammarError> {
+        if let Node::N(symbol) = root {
+            let mut samplers = self.samplers.lock().unwrap();
+            let sampler = samplers
+                .entry(measure)
+                .or_insert_with(|| UniformSampler::new(&self.grammar, measure));
+            let rng: &mut dyn RngCore = &mut **self.rng();
+            *root = sampler.sample(symbol, size, rng)?;
+        }
+        Ok(())
+    }
+
+    /// Applies a sequence of strategies to an arena-backed derivation tree
+    ///
+    /// Panics when a nonterminal can't be expanded, see `try_expand_derivation_tree`
+    pub fn expand_derivation_tree(&self, tree: &mut DerivationTree) {
+        if let Err(error) = self.try_expand_derivation_tree(tree) {
+            panic!("{}", error);
+        }
+    }
+
+    /// Applies a sequence of strategies to an arena-backed derivation tree,
+    /// stops at the first nonterminal that can't be expanded
+    pub fn try_expand_derivation_tree(
+        &self,
+        tree: &mut DerivationTree,
+    ) -> Result<(), GrammarError> {
+        let rng: &mut dyn RngCore = &mut **self.rng();
+        self.expand_with_rng(tree, rng)
+    }
+
+    fn expand_with_rng(
+        &self,
+        tree: &mut DerivationTree,
+        rng: &mut dyn RngCore,
+    ) -> Result<(), GrammarError> {
+        for strategy in self.steps.iter() {
+            self.expand_tree_with_strategy(tree, strategy.as_ref(), rng)?;
+        }
+        Ok(())
+    }
+}
+
+/// The inputs are generated by the methods that apply the hooks of the expansions, see the `hooks` module
+impl<T: ExpansionHooks> GrammarFuzzer<T> {
+    /// Generates an input from the start symbol
+    ///
+    /// Panics when the input can't be generated, see `try_fuzz`
+    pub fn fuzz(&self) -> String {
+        self.fuzz_tree().to_string()
+    }
+
+    /// Generates an input from the start symbol
+    pub fn try_fuzz(&self) -> Result<String, GrammarError> {
+        Ok(self.try_fuzz_tree()?.to_string())
+    }
+
+    /// Generates a derivation tree from the start symbol
+    ///
+    /// Panics when the tree can't be generated, see `try_fuzz_tree`
+    pub fn fuzz_tree(&self) -> Node {
+        self.try_fuzz_tree()
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Generates a derivation tree from the start symbol
+    pub fn try_fuzz_tree(&self) -> Result<Node, GrammarError> {
+        self.try_fuzz_tree_from(&self.start_symbol)
+    }
+
+    /// Generates an input from any nonterminal symbol of the grammar
+    ///
+    /// Panics when the input can't be generated, see `try_fuzz_from`
+    pub fn fuzz_from(&self, symbol: &str) -> String {
+        self.fuzz_tree_from(symbol).to_string()
+    }
+
+    /// Generates an input from any nonterminal symbol of the grammar
+    pub fn try_fuzz_from(&self, symbol: &str) -> Result<String, GrammarError> {
+        Ok(self.try_fuzz_tree_from(symbol)?.to_string())
+    }
+
+    /// Generates a derivation tree from any nonterminal symbol of the grammar
+    ///
+    /// Panics when the tree can't be generated, see `try_fuzz_tree_from`
+    pub fn fuzz_tree_from(&self, symbol: &str) -> Node {
+        self.try_fuzz_tree_from(symbol)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Generates a derivation tree from any nonterminal symbol of the grammar
+    ///
+    /// The part of the grammar reachable from the symbol is validated the first time it's used,
+    /// see `Grammar::validate_entry_point`, and the strategies have to expand every nonterminal
+    pub fn try_fuzz_tree_from(&self, symbol: &str) -> Result<Node, GrammarError> {
+        self.validate_entry_point(symbol)?;
+        let rng: &mut dyn RngCore = &mut **self.rng();
+        self.generate(symbol, rng)
+    }
+
+    fn validate_entry_point(&self, symbol: &str) -> Result<(), GrammarError> {
+        let mut entry_points = self.entry_points.lock().unwrap();
+        if !entry_points.contains(symbol) {
+            self.grammar.validate_entry_point(symbol)?;
+            entry_points.insert(String::from(symbol));
+        }
+        Ok(())
+    }
+
+    /// Expands a new derivation tree from the symbol, making the random choices with `rng`
+    fn generate(&self, symbol: &str, rng: &mut dyn RngCore) -> Result<Node, GrammarError> {
+        let mut root = self.generate_unconstrained(symbol, 0, rng)?;
+        self.satisfy_constraints(&mut root, rng)?;
+        for step in self.steps.iter() {
+            step.generated(&root);
+        }
+        Ok(root)
+    }
+
+    /// Expands a new derivation tree from the symbol and applies the hooks of its expansions,
+    /// the tree replaces a node at `depth` so it's expanded within the depth left by `max_depth`
+    fn generate_unconstrained(
+        &self,
+        symbol: &str,
+        depth: usize,
+        rng: &mut dyn RngCore,
+    ) -> Result<Node, GrammarError> {
+        let mut tree = DerivationTree::at_depth(symbol, depth);
+        self.expand_with_rng(&mut tree, rng)?;
+        let mut root = tree.to_node();
+        fully_expanded(&root)?;
+        self.apply_hooks(&mut root, depth, rng)?;
+        Ok(root)
+    }
+
+    /// Repairs the violations of the constraints, a violation that can't be repaired
+    /// is removed by regenerating the subtree at its path
+    pub(crate) fn try_satisfy_constraints(&self, root: &mut Node) -> Result<(), GrammarError> {
+        let rng: &mut dyn RngCore = &mut **self.rng();
+        self.satisfy_constraints(root, rng)
+    }
+
+    fn satisfy_constraints(
+        &self,
+        root: &mut Node,
+        rng: &mut dyn RngCore,
+    ) -> Result<(), GrammarError> {
+        // only the violations that had to be regenerated count, a large tree can have many repairable ones
+        let mut regenerations = 0;
+        let mut last_violation = None;
+        while regenerations < MAX_REGENERATIONS {
+            let (constraint, violation) = match self
+                .constraints
+                .iter()
+                .find_map(|constraint| Some((constraint, constraint.check(root)?)))
+            {
+                Some(violation) => violation,
+                None => return Ok(()),
+            };
+            let path = &violation.path;
+            let original = root.subtree(path).unwrap();
+            if !(constraint.repair(root, &violation, rng) && self.derive_repair(root, path)) {
+                root.replace_subtree(path, original);
+                let symbol = root.at(path, |node| String::from(node.symbol())).unwrap();
+                let subtree = self.generate_unconstrained(&symbol, path.len(), rng)?;
+                root.replace_subtree(path, subtree);
+                regenerations += 1;
+                last_violation = Some(violation);
+            }
+        }
+        Err(GrammarError::Unsatisfied {
+            violation: last_violation.map_or_else(String::new, |violation| violation.message),
+            attempts: MAX_REGENERATIONS,
+        })
+    }
+
+    /// Replaces the repaired subtree at the path with a derivation of its text,
+    /// returns false when the grammar can't derive the text within `max_depth`
+    fn derive_repair(&self, root: &mut Node, path: &[usize]) -> bool {
+        let (symbol, text) =
+            match root.at(path, |node| (String::from(node.symbol()), node.to_string())) {
+                Some(repaired) => repaired,
+                None => return false,
+            };
+        let mut parsers = self.parsers.lock().unwrap();
+        let parser = parsers
+            .entry(symbol.clone())
+            .or_insert_with(|| EarleyParser::new(&self.grammar, &symbol));
+        match parser.parse(&text).map(|tree| tree.to_node()) {
+            Ok(subtree)
+                if self
+                    .max_depth
+                    .map_or(true, |max| path.len() + subtree.depth() <= max) =>
+            {
+                root.replace_subtree(path, subtree).is_some()
+            }
+            _ => false,
+        }
+    }
+
+    /// An endless iterator over inputs generated from the start symbol
+    ///
+    /// The iterator panics when an input can't be generated, see `try_fuzz`
+    pub fn inputs(&self) -> Inputs<'_, T> {
+        Inputs { fuzzer: self }
+    }
+
+    /// An endless iterator over derivation trees generated from the start symbol
+    ///
+    /// The iterator panics when a tree can't be generated, see `try_fuzz_tree`
+    pub fn trees(&self) -> Trees<'_, T> {
+        Trees { fuzzer: self }
+    }
+
+    /// Writes `n` inputs generated from the start symbol, each followed by a newline,
+    /// an input is written as soon as it's generated
+    ///
+    /// An input that can't be generated is reported as an `io::ErrorKind::Other` error
+    pub fn write_to<W: Write>(&self, out: &mut W, n: usize) -> io::Result<()> {
+        for _ in 0..n {
+            let input = self
+                .try_fuzz()
+                .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+            writeln!(out, "{}", input)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the sequence of strategies and checks that they expanded every nonterminal
+    pub(crate) fn try_expand_tree_fully(&self, root: &mut Node) -> Result<(), GrammarError> {
+        self.try_expand_tree(root)?;
+        fully_expanded(root)?;
+        let rng: &mut dyn RngCore = &mut **self.rng();
+        self.apply_hooks(root, 0, rng)
+    }
+
+    /// Applies the hooks of the expansions of a complete tree, children before their parents,
+    /// a subtree rejected by a post-hook is regenerated at the depth of its node and its hooks applied again
+    fn apply_hooks(
+        &self,
+        node: &mut Node,
+        depth: usize,
+        rng: &mut dyn RngCore,
+    ) -> Result<(), GrammarError> {
+        let symbol = String::from(node.symbol());
+        for _ in 0..MAX_REGENERATIONS {
+            match node {
+                Node::EN(_, children) => {
+                    for child in children.roots.iter_mut() {
+                        self.apply_hooks(child.get_mut(), depth + 1, rng)?;
+                    }
+                }
+                _ => return Ok(()),
+            }
+            let opts = match self.expansion_opts(node) {
+                Some(opts) => opts,
+                None => return Ok(()),
+            };
+            if let Some(text) = opts.pre_hook().and_then(|pre| pre.generate(rng)) {
+                *node = Node::new_expanded(&symbol, Children::from_text(&text));
+            }
+            match opts.post_hook().map(|post| post.check(&node.to_string())) {
+                None | Some(Verdict::Accept) => return Ok(()),
+                Some(Verdict::Replace(text)) => {
+                    *node = Node::new_expanded(&symbol, Children::from_text(&text));
+                    return Ok(());
+                }
+                Some(Verdict::Reject) => {
+                    let mut tree = DerivationTree::at_depth(&symbol, depth);
+                    self.expand_with_rng(&mut tree, rng)?;
+                    *node = tree.to_node();
+                    fully_expanded(node)?;
+                }
+            }
+        }
+        Err(GrammarError::Rejected {
+            symbol,
+            attempts: MAX_REGENERATIONS,
+        })
+    }
+
+    /// The options of the expansion of the grammar that an expanded node follows
+    fn expansion_opts(&self, node: &Node) -> Option<&T> {
+        let expansion = node.expansion_string()?;
+        self.grammar
+            .get(node.symbol())?
+            .iter()
+            .find(|alternative| alternative.string == expansion)?
+            .opts
+            .as_ref()
+    }
+}
+
+impl<T: ExpansionHooks + Sync> GrammarFuzzer<T> {
+    /// Generates `n` inputs from the start symbol on `threads` threads
+    ///
+    /// Panics when an input can't be generated, see `try_generate_parallel`
+    pub fn generate_parallel(&self, n: usize, master_seed: u64, threads: usize) -> Vec<String> {
+        self.try_generate_parallel(n, master_seed, threads)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Generates `n` inputs from the start symbol on `threads` threads, in the order of their index
+    ///
+    /// The input at index `i` makes its random choices with its own generator seeded with
+    /// `derive_seed(master_seed, i)`, the same input is generated by `reseed` with that seed and `fuzz`.
+    /// So the inputs don't depend on the number of threads, as long as the strategies don't keep state
+    /// between inputs like the coverage strategies do. The random choices of the fuzzer are not changed.
+    pub fn try_generate_parallel(
+        &self,
+        n: usize,
+        master_seed: u64,
+        threads: usize,
+    ) -> Result<Vec<String>, GrammarError> {
+        self.validate_entry_point(&self.start_symbol)?;
+        let threads = threads.clamp(1, n.max(1));
+        let chunk = (n + threads - 1) / threads;
+        let chunks: Vec<Result<Vec<String>, GrammarError>> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|worker| {
+                    scope.spawn(move || {
+                        (worker * chunk..n.min((worker + 1) * chunk))
+                            .map(|idx| {
+                                let mut rng = StdRng::seed_from_u64(derive_seed(master_seed, idx));
+                                let tree = self.generate(&self.start_symbol, &mut rng)?;
+                                Ok(tree.to_string())
+                            })
+                            .collect()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| {
+                    worker
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                })
+                .collect()
+        });
+
+        let mut inputs = Vec::with_capacity(n);
+        for chunk in chunks {
+            inputs.extend(chunk?);
         }
+        Ok(inputs)
+    }
+}
+
+/// The seed of the input at `index` of a parallel generation, see `GrammarFuzzer::try_generate_parallel`
+///
+/// The indices are mixed with SplitMix64 so that neighbouring inputs get unrelated seeds
+pub fn derive_seed(master_seed: u64, index: usize) -> u64 {
+    let mut z = master_seed.wrapping_add(
+        (index as u64)
+            .wrapping_add(1)
+            .wrapping_mul(0x9E37_79B9_7F4A_7C15),
+    );
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Reports the first unexpanded nonterminal left in the tree
+fn fully_expanded(root: &Node) -> Result<(), GrammarError> {
+    if !root.any_possible_expansions() {
+        return Ok(());
+    }
+    let symbol = root.nonterminal_paths().iter().find_map(|path| {
+        root.at(path, |node| match node {
+            Node::N(symbol) => Some(symbol.clone()),
+            _ => None,
+        })?
+    });
+    Err(GrammarError::NoExpansion(symbol.unwrap_or_default()))
+}
+
+/// Builds a `GrammarFuzzer` that owns its strategies
+///
+/// ```
+/// use grammar_fuzzer::{CloseStrategy, Grammar, GrammarFuzzer, GrowthStrategy};
+/// use std::collections::HashMap;
+///
+/// fn digits_fuzzer() -> GrammarFuzzer<()> {
+///     let expansions: HashMap<_, _> = [
+///         ("<start>", vec!["<digit><start>", "<digit>"]),
+///         ("<digit>", vec!["0", "1"]),
+///     ]
+///     .iter()
+///     .cloned()
+///     .collect();
+///     GrammarFuzzer::builder(Grammar::from(&expansions))
+///         .then(GrowthStrategy::new(5, 100))
+///         .then(CloseStrategy::new())
+///         .build()
+/// }
+///
+/// assert!(digits_fuzzer().fuzz().len() >= 5);
+/// ```
+pub struct GrammarFuzzerBuilder<T> {
+    grammar: Grammar<T>,
+    steps: Vec<Box<dyn Strategy<T>>>,
+    constraints: Vec<Box<dyn Constraint>>,
+    rng: Option<Box<dyn RngCore + Send>>,
+    start_symbol: String,
+    max_depth: Option<usize>,
+}
+
+impl<T> GrammarFuzzerBuilder<T> {
+    /// Appends a strategy to the sequence of strategies that expand each tree in turn,
+    /// use an `Arc` to keep access to a strategy, ex: to read the report of a `CoverageStrategy`
+    pub fn then<S: Strategy<T> + 'static>(mut self, strategy: S) -> Self {
+        self.steps.push(Box::new(strategy));
+        self
+    }
+
+    /// Adds a constraint that every generated tree satisfies, see the `constraints` module
+    pub fn constraint<C: Constraint + 'static>(mut self, constraint: C) -> Self {
+        self.constraints.push(Box::new(constraint));
+        self
+    }
+
+    /// Seeds the random choices of the fuzzer, they are seeded from the operating system by default
+    pub fn seed(self, seed: u64) -> Self {
+        self.rng(StdRng::seed_from_u64(seed))
+    }
+
+    /// Makes all the random choices of the fuzzer with `rng`
+    pub fn rng<R: RngCore + Send + 'static>(mut self, rng: R) -> Self {
+        self.rng = Some(Box::new(rng));
+        self
+    }
+
+    /// The symbol that `fuzz` and `fuzz_tree` expand, it's `<start>` by default
+    pub fn start_symbol(mut self, symbol: &str) -> Self {
+        self.start_symbol = String::from(symbol);
+        self
+    }
+
+    /// Bounds the derivation depth of the trees, see `GrammarFuzzer::with_max_depth`
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    pub fn build(self) -> GrammarFuzzer<T> {
+        let rng = match self.rng {
+            Some(rng) => rng,
+            None => Box::new(StdRng::from_entropy()),
+        };
+        let mut fuzzer = GrammarFuzzer::with_rng(self.grammar, self.steps, rng)
+            .with_start_symbol(&self.start_symbol);
+        fuzzer.max_depth = self.max_depth;
+        fuzzer.constraints = self.constraints;
+        fuzzer
+    }
+}
+
+/// An endless iterator over generated inputs, see `GrammarFuzzer::inputs`
+pub struct Inputs<'f, T> {
+    fuzzer: &'f GrammarFuzzer<T>,
+}
+
+impl<'f, T: ExpansionHooks> Iterator for Inputs<'f, T> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.fuzzer.fuzz())
+    }
+}
+
+/// An endless iterator over generated derivation trees, see `GrammarFuzzer::trees`
+pub struct Trees<'f, T> {
+    fuzzer: &'f GrammarFuzzer<T>,
+}
+
+impl<'f, T: ExpansionHooks> Iterator for Trees<'f, T> {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.fuzzer.fuzz_tree())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constraints::{DefinedBefore, LengthOf, Violation};
+    use crate::coverage::CoverageStrategy;
+    use crate::grammar::{Expansion, ExpansionOpts};
+    use crate::length::LengthStrategy;
+    use crate::mutation::Mutator;
+    use crate::strategy::{CloseStrategy, DepthStrategy, GrowthStrategy, RandomStrategy};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn sample_grammar() -> Grammar<()> {
+        let expansions: HashMap<_, _> = [
+            ("<start>", vec!["<values>"]),
+            ("<values>", vec!["<values>, <int>", "<int>"]),
+            ("<int>", vec!["<digit><int>", "<digit>"]),
+            (
+                "<digit>",
+                vec!["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"],
+            ),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+
+        Grammar::from(&expansions)
+    }
+
+    fn sample(fuzzer: &GrammarFuzzer<()>, n: usize) -> Vec<String> {
+        (0..n).map(|_| fuzzer.fuzz()).collect()
+    }
+
+    fn random_fuzzer(seed: u64) -> GrammarFuzzer<()> {
+        GrammarFuzzer::builder(sample_grammar())
+            .then(RandomStrategy::new(10, 100))
+            .then(CloseStrategy::new())
+            .seed(seed)
+            .build()
+    }
+
+    #[test]
+    fn test_same_seed_same_output() {
+        let strategies = || -> Vec<Box<dyn Strategy<()>>> {
+            vec![
+                Box::new(GrowthStrategy::new(0, 10)),
+                Box::new(RandomStrategy::new(10, 100)),
+                Box::new(CloseStrategy::new()),
+            ]
+        };
+
+        let first = GrammarFuzzer::with_seed(sample_grammar(), strategies(), 7);
+        let second = GrammarFuzzer::with_seed(sample_grammar(), strategies(), 7);
+
+        assert_eq!(sample(&first, 20), sample(&second, 20));
+    }
+
+    #[test]
+    fn test_try_expand_tree_undefined_nonterminal() {
+        let fuzzer = GrammarFuzzer::builder(sample_grammar())
+            .then(CloseStrategy::new())
+            .seed(0)
+            .build();
+        let mut node = Node::new_nonterminal("<float>");
+
+        assert_eq!(
+            fuzzer.try_expand_tree(&mut node),
+            Err(GrammarError::UndefinedNonterminals(vec![String::from(
+                "<float>"
+            )]))
+        );
+    }
+
+    #[test]
+    fn test_fuzz_entry_points() {
+        let fuzzer = random_fuzzer(2);
+        assert_eq!(fuzzer.start_symbol(), "<start>");
+        assert_eq!(fuzzer.fuzz_tree().symbol(), "<start>");
+
+        let fuzzer = fuzzer.with_start_symbol("<int>");
+        for _ in 0..10 {
+            assert!(fuzzer.fuzz().chars().all(|c| c.is_ascii_digit()));
+            assert_eq!(fuzzer.fuzz_from("<digit>").len(), 1);
+        }
+        assert_eq!(
+            fuzzer.try_fuzz_from("<float>"),
+            Err(GrammarError::UndefinedNonterminals(vec![String::from(
+                "<float>"
+            )]))
+        );
+
+        // without the close strategy the trees are left unfinished
+        let fuzzer = GrammarFuzzer::builder(sample_grammar())
+            .then(GrowthStrategy::new(5, 100))
+            .seed(2)
+            .build();
+        assert!(matches!(
+            fuzzer.try_fuzz(),
+            Err(GrammarError::NoExpansion(_))
+        ));
+    }
+
+    #[test]
+    fn test_iterators() {
+        let fuzzer = random_fuzzer(4);
+
+        let expected = sample(&fuzzer, 5);
+        fuzzer.reseed(4);
+        assert_eq!(fuzzer.inputs().take(5).collect::<Vec<_>>(), expected);
+        fuzzer.reseed(4);
+        let trees: Vec<String> = fuzzer.trees().take(5).map(|t| t.to_string()).collect();
+        assert_eq!(trees, expected);
+
+        let mut seen = HashSet::new();
+        let unique: Vec<String> = fuzzer
+            .inputs()
+            .filter(|input| seen.insert(input.clone()))
+            .take(20)
+            .collect();
+        assert_eq!(unique.iter().collect::<HashSet<_>>().len(), 20);
+
+        fuzzer.reseed(4);
+        let mut out = Vec::new();
+        fuzzer.write_to(&mut out, 5).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), expected.join("\n") + "\n");
+    }
+
+    #[test]
+    fn test_generate_parallel() {
+        let fuzzer = GrammarFuzzer::builder(sample_grammar())
+            .then(GrowthStrategy::new(5, 10))
+            .then(RandomStrategy::new(10, 100))
+            .then(CloseStrategy::new())
+            .seed(0)
+            .build();
+
+        let inputs = fuzzer.generate_parallel(50, 9, 1);
+        assert_eq!(inputs.len(), 50);
+        assert_eq!(fuzzer.generate_parallel(50, 9, 4), inputs);
+        assert_eq!(fuzzer.generate_parallel(50, 9, 64), inputs);
+        assert_ne!(fuzzer.generate_parallel(50, 10, 4), inputs);
+        assert!(fuzzer.generate_parallel(0, 9, 4).is_empty());
+
+        fuzzer.reseed(derive_seed(9, 17));
+        assert_eq!(fuzzer.fuzz(), inputs[17]);
+
+        let fuzzer = fuzzer.with_start_symbol("<float>");
+        assert_eq!(
+            fuzzer.try_generate_parallel(10, 9, 2),
+            Err(GrammarError::UndefinedNonterminals(vec![String::from(
+                "<float>"
+            )]))
+        );
+    }
+
+    #[test]
+    fn test_builder() {
+        let coverage = Arc::new(CoverageStrategy::new(10, 100));
+        let fuzzer = GrammarFuzzer::builder(sample_grammar())
+            .then(Arc::clone(&coverage))
+            .then(Box::new(CloseStrategy::new()) as Box<dyn Strategy<()>>)
+            .start_symbol("<int>")
+            .seed(6)
+            .build();
+        assert_eq!(fuzzer.start_symbol(), "<int>");
+        for _ in 0..10 {
+            assert!(fuzzer.fuzz().chars().all(|c| c.is_ascii_digit()));
+        }
+        // the shared strategy kept track of the expansions chosen by the fuzzer
+        assert!(coverage
+            .covered()
+            .contains(&(String::from("<int>"), String::from("<digit>"))));
+
+        let seeded = |seed| {
+            GrammarFuzzer::builder(sample_grammar())
+                .then(RandomStrategy::new(10, 100))
+                .then(CloseStrategy::new())
+                .seed(seed)
+                .build()
+        };
+        assert_eq!(sample(&seeded(8), 10), sample(&seeded(8), 10));
+    }
+
+    #[test]
+    fn test_max_depth() {
+        // Growth alone would never close the lists, the limit makes it close them in time
+        let fuzzer = GrammarFuzzer::builder(sample_grammar())
+            .then(GrowthStrategy::new(1000, 1000))
+            .then(CloseStrategy::new())
+            .max_depth(7)
+            .seed(4)
+            .build();
+        assert_eq!(fuzzer.max_depth(), Some(7));
+        for tree in fuzzer.trees().take(10) {
+            assert_eq!(tree.depth(), 7);
+        }
+
+        let fuzzer = GrammarFuzzer::builder(sample_grammar())
+            .then(DepthStrategy::new(6, 1000))
+            .then(CloseStrategy::new())
+            .seed(4)
+            .build();
+        let depths: Vec<usize> = fuzzer.trees().take(50).map(|tree| tree.depth()).collect();
+        assert!(depths.iter().all(|depth| *depth <= 6));
+        assert!(depths.contains(&6));
+
+        // <start>, <values>, <int>, <digit> and the digit need a depth of 4
+        let fuzzer = random_fuzzer(0).with_max_depth(3);
+        assert_eq!(
+            fuzzer.try_fuzz(),
+            Err(GrammarError::NoDerivationOfDepth {
+                symbol: String::from("<start>"),
+                depth: 3
+            })
+        );
+    }
+
+    fn hooked_fuzzer(
+        start: ExpansionOpts,
+        byte: ExpansionOpts,
+        seed: u64,
+    ) -> GrammarFuzzer<ExpansionOpts> {
+        let digits = (0..10)
+            .map(|d| Expansion::new(&d.to_string(), None))
+            .collect();
+        let expansions = vec![
+            (
+                "<start>",
+                vec![Expansion::new("<byte>:<check>", Some(start))],
+            ),
+            (
+                "<byte>",
+                vec![Expansion::new("<digit><digit><digit>", Some(byte))],
+            ),
+            ("<check>", vec![Expansion::new("<digit>", None)]),
+            ("<digit>", digits),
+        ]
+        .into_iter()
+        .map(|(symbol, alternatives)| (String::from(symbol), alternatives))
+        .collect();
+        GrammarFuzzer::builder(Grammar::new(expansions))
+            .then(CloseStrategy::new())
+            .seed(seed)
+            .build()
+    }
+
+    #[test]
+    fn test_hooks() {
+        // the check digit is rewritten from the byte once the byte has been checked
+        let start = ExpansionOpts::default().with_post(|text| {
+            let byte: u32 = text.split(':').next().unwrap().parse().unwrap();
+            Verdict::Replace(format!("{}:{}", byte, byte % 7))
+        });
+        let byte = ExpansionOpts::default().with_post(|text| match text.parse::<u32>() {
+            Ok(byte) if byte < 256 => Verdict::Accept,
+            _ => Verdict::Reject,
+        });
+        let fuzzer = hooked_fuzzer(start, byte.clone(), 0);
+        for input in fuzzer.inputs().take(20) {
+            let (byte, check) = input.split_at(input.find(':').unwrap());
+            let byte: u32 = byte.parse().unwrap();
+            assert!(byte < 256);
+            assert_eq!(check, format!(":{}", byte % 7));
+        }
+
+        let id =
+            ExpansionOpts::default().with_pre(|rng| Some(format!("<{}>", rng.gen_range(0, 256))));
+        let inputs = |seed| -> Vec<String> {
+            let fuzzer = hooked_fuzzer(id.clone(), byte.clone(), seed);
+            fuzzer.inputs().take(10).collect()
+        };
+        assert_eq!(inputs(1), inputs(1));
+        for input in inputs(1) {
+            let byte: u32 = input[1..input.len() - 1].parse().unwrap();
+            assert!(byte < 256);
+        }
+
+        let reject = ExpansionOpts::default().with_post(|_| Verdict::Reject);
+        assert_eq!(
+            hooked_fuzzer(ExpansionOpts::default(), reject, 0).try_fuzz(),
+            Err(GrammarError::Rejected {
+                symbol: String::from("<byte>"),
+                attempts: MAX_REGENERATIONS
+            })
+        );
+
+        // a rejected <list> is regenerated within the depth left below its node
+        let one = ExpansionOpts::default().with_post(|text| {
+            if text.starts_with('1') {
+                Verdict::Accept
+            } else {
+                Verdict::Reject
+            }
+        });
+        let expansions = vec![
+            ("<start>", vec![Expansion::new("[<list>]", None)]),
+            ("<list>", vec![Expansion::new("<items>", Some(one))]),
+            (
+                "<items>",
+                vec![
+                    Expansion::new("<digit><items>", None),
+                    Expansion::new("<digit>", None),
+                ],
+            ),
+            (
+                "<digit>",
+                vec![Expansion::new("0", None), Expansion::new("1", None)],
+            ),
+        ]
+        .into_iter()
+        .map(|(symbol, alternatives)| (String::from(symbol), alternatives))
+        .collect();
+        let fuzzer = GrammarFuzzer::builder(Grammar::new(expansions))
+            .then(GrowthStrategy::new(1000, 1000))
+            .then(CloseStrategy::new())
+            .max_depth(8)
+            .seed(3)
+            .build();
+        for tree in fuzzer.trees().take(50) {
+            assert!(tree.depth() <= 8);
+            assert!(tree.to_string().starts_with("[1"));
+        }
+    }
+
+    #[test]
+    fn test_options_without_hooks() {
+        struct Tag;
+
+        impl ExpansionHooks for Tag {}
+
+        let expansions = vec![
+            (
+                "<int>",
+                vec![
+                    Expansion::new("<digit><int>", Some(Tag)),
+                    Expansion::new("<digit>", Some(Tag)),
+                ],
+            ),
+            (
+                "<digit>",
+                vec![Expansion::new("0", None), Expansion::new("1", None)],
+            ),
+        ]
+        .into_iter()
+        .map(|(symbol, alternatives)| (String::from(symbol), alternatives))
+        .collect();
+        let fuzzer = GrammarFuzzer::builder(Grammar::new(expansions))
+            .then(RandomStrategy::new(5, 100))
+            .then(CloseStrategy::new())
+            .start_symbol("<int>")
+            .seed(1)
+            .build();
+        let tree = fuzzer.fuzz_tree();
+        assert!(fuzzer.inputs().take(5).all(|input| !input.is_empty()));
+        assert!(Mutator::new(&fuzzer).mutate(&tree, &[]).is_ok());
+        assert_eq!(fuzzer.generate_parallel(5, 1, 2).len(), 5);
+    }
+
+    struct Never;
+
+    impl Constraint for Never {
+        fn check(&self, _tree: &Node) -> Option<Violation> {
+            Some(Violation {
+                path: Vec::new(),
+                message: String::from("never"),
+            })
+        }
+    }
+
+    #[test]
+    fn test_constraints() {
+        let expansions: HashMap<_, _> = [
+            ("<start>", vec!["<length>:<payload>"]),
+            ("<length>", vec!["<digit><length>", "<digit>"]),
+            (
+                "<digit>",
+                vec!["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"],
+            ),
+            ("<payload>", vec!["<char><payload>", ""]),
+            ("<char>", vec!["a", "b"]),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let fuzzer = GrammarFuzzer::builder(Grammar::from(&expansions))
+            .then(RandomStrategy::new(10, 100))
+            .then(CloseStrategy::new())
+            .constraint(LengthOf::new("<start>", "<length>", "<payload>"))
+            .seed(5)
+            .build();
+        let holds = |input: &str| {
+            let (length, payload) = input.split_at(input.find(':').unwrap());
+            length.parse::<usize>().unwrap() == payload.len() - 1
+        };
+        let mutator = Mutator::new(&fuzzer);
+        // the repaired length fields are derivations of <length>
+        let parser = EarleyParser::new(&Grammar::from(&expansions), "<start>");
+        for tree in fuzzer.trees().take(20) {
+            assert!(holds(&tree.to_string()));
+            assert_eq!(parser.parse(&tree.to_string()).unwrap().to_node(), tree);
+            assert!(holds(&mutator.mutate(&tree, &[]).unwrap().to_string()));
+        }
+
+        // a single digit can't hold the longer lengths, those payloads are regenerated
+        let mut single_digit = expansions.clone();
+        single_digit.insert("<length>", vec!["<digit>"]);
+        single_digit.insert(
+            "<payload>",
+            vec!["<char><char><char><char><char><payload>", ""],
+        );
+        let fuzzer = GrammarFuzzer::builder(Grammar::from(&single_digit))
+            .then(RandomStrategy::new(10, 100))
+            .then(CloseStrategy::new())
+            .constraint(LengthOf::new("<start>", "<length>", "<payload>"))
+            .max_depth(12)
+            .seed(5)
+            .build();
+        for tree in fuzzer.trees().take(20) {
+            assert!(holds(&tree.to_string()));
+            assert_eq!(tree.to_string().find(':'), Some(1));
+            assert!(tree.depth() <= 12);
+        }
+
+        // a long program has many more undefined uses than regenerations, they're all repaired
+        let program: HashMap<_, _> = [
+            ("<start>", vec!["<decl>;<stmts>"]),
+            ("<stmts>", vec!["<stmt>;<stmts>", "<stmt>"]),
+            ("<stmt>", vec!["<decl>", "<use>"]),
+            ("<decl>", vec!["let <id>"]),
+            ("<use>", vec!["print <id>"]),
+            ("<id>", vec!["x", "y", "z"]),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let length = LengthStrategy::new(&Grammar::from(&program), 1400..=1600, 100_000);
+        let fuzzer = GrammarFuzzer::builder(Grammar::from(&program))
+            .then(length)
+            .then(CloseStrategy::new())
+            .constraint(DefinedBefore::new("<decl>", "<use>", "<id>"))
+            .seed(0)
+            .build();
+        for input in fuzzer.inputs().take(3) {
+            assert!(input.len() >= 1400);
+        }
+
+        let fuzzer = random_fuzzer(0).with_constraint(Never);
+        assert_eq!(
+            fuzzer.try_fuzz(),
+            Err(GrammarError::Unsatisfied {
+                violation: String::from("never"),
+                attempts: MAX_REGENERATIONS
+            })
+        );
+    }
+
+    #[test]
+    fn test_expand_derivation_tree() {
+        let fuzzer = random_fuzzer(3);
+        let mut tree = DerivationTree::new("<start>");
+        fuzzer.expand_derivation_tree(&mut tree);
+
+        assert!(!tree.any_possible_expansions());
+        assert!(!tree.to_node().any_possible_expansions());
+        assert!(tree
+            .to_string()
+            .split(", ")
+            .all(|int| !int.is_empty() && int.chars().all(|c| c.is_ascii_digit())));
+    }
+
+    #[test]
+    fn test_expand_tree_uniform() {
+        let fuzzer = GrammarFuzzer::with_seed(sample_grammar(), Vec::new(), 5);
+        for size in 1..10 {
+            let mut node = Node::new_nonterminal("<start>");
+            fuzzer.expand_tree_uniform(&mut node, Measure::Length, size);
+            assert!(!node.any_possible_expansions());
+            assert_eq!(node.to_string().len(), size);
+        }
+
+        // "," needs at least 4 characters, ex: "0, 1"
+        let mut node = Node::new_nonterminal("<start>");
+        fuzzer.expand_tree_uniform(&mut node, Measure::Length, 3);
+        assert!(!node.to_string().contains(','));
+
+        assert_eq!(
+            fuzzer.try_expand_tree_uniform(&mut Node::new_nonterminal("<start>"), Measure::Size, 2),
+            Err(GrammarError::NoDerivationOfSize {
+                symbol: String::from("<start>"),
+                size: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_reseed_replays_output() {
+        let fuzzer = random_fuzzer(1);
+
+        let expected = sample(&fuzzer, 5);
+        fuzzer.reseed(1);
+
+        assert_eq!(sample(&fuzzer, 5), expected);
     }
 }
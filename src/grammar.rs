@@ -22,15 +22,20 @@
 //! Grammar::from(&expansios);
 //! ```
 
+use super::attributes::AttributeRules;
+use super::error::GrammarError;
+use super::hooks::{PostHook, PreHook, Verdict};
 use super::parser::{self, Token};
 use super::shared::add_to_set;
 
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::ops::Deref;
 
 /// A nonterminal symbol can be replaced by an expansion `string`
-#[derive(Eq)]
+#[derive(Clone, Eq)]
 pub struct Expansion<T> {
     /// An expansion string, a sequence of terminal and nonterminal tokens
     pub string: String,
@@ -59,18 +64,64 @@ impl<T> fmt::Debug for Expansion<T> {
     }
 }
 
+/// The built-in expansion options, they are written as `{"prob": 0.3}` in JSON grammars
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExpansionOpts {
+    /// The probability of choosing the expansion,
+    /// expansions without a probability share the probability left by the others
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prob: Option<f64>,
+    /// Produces the text of the expansion, see the `hooks` module
+    #[serde(skip)]
+    pub pre: Option<PreHook>,
+    /// Checks the text of the expanded subtree, see the `hooks` module
+    #[serde(skip)]
+    pub post: Option<PostHook>,
+}
+
+impl ExpansionOpts {
+    pub fn with_pre<F>(mut self, generate: F) -> Self
+    where
+        F: Fn(&mut dyn RngCore) -> Option<String> + Send + Sync + 'static,
+    {
+        self.pre = Some(PreHook::new(generate));
+        self
+    }
+
+    pub fn with_post<F>(mut self, check: F) -> Self
+    where
+        F: Fn(&str) -> Verdict + Send + Sync + 'static,
+    {
+        self.post = Some(PostHook::new(check));
+        self
+    }
+}
+
 /// The set of alternative expansions for a nonterminal symbol
 pub type Alternatives<T> = Vec<Expansion<T>>;
 
 /// A mapping between nonterminal tokens and their alternative expansions
 pub type Expansions<T> = HashMap<String, Alternatives<T>>;
 
-/// A trivial wrapper around Expansions
-#[derive(Debug, Eq, PartialEq)]
+/// A wrapper around Expansions that caches the expansion costs
+#[derive(Debug)]
 pub struct Grammar<T> {
     expansions: Expansions<T>,
+    costs: Costs,
+    attributes: AttributeRules,
+    /// Set by the loader, the expansions without EBNF operators by symbol,
+    /// the terminals of the other expansions are escaped
+    verbatim: Option<HashSet<(String, String)>>,
 }
 
+impl<T> PartialEq for Grammar<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.expansions == other.expansions
+    }
+}
+
+impl<T> Eq for Grammar<T> {}
+
 impl<T> Deref for Grammar<T> {
     type Target = Expansions<T>;
 
@@ -125,11 +176,64 @@ impl From<&HashMap<String, Vec<String>>> for Grammar<()> {
 
 impl<T> Grammar<T> {
     pub fn new(expansions: Expansions<T>) -> Self {
-        Grammar { expansions }
+        let costs = Costs::from(&expansions);
+        Grammar {
+            expansions,
+            costs,
+            attributes: AttributeRules::new(),
+            verbatim: None,
+        }
+    }
+
+    /// Checks that the symbols are nonterminals and that every expansion-string
+    /// can be split into terminal and nonterminal tokens
+    pub fn try_new(expansions: Expansions<T>) -> Result<Self, GrammarError> {
+        for (symbol, alternatives) in expansions.iter() {
+            match parser::try_tokens(symbol).as_deref() {
+                Ok([Token::Nonterminal(_)]) => (),
+                _ => return Err(GrammarError::InvalidNonterminal(symbol.clone())),
+            }
+            for expansion in alternatives {
+                if let Err(position) = parser::try_tokens(&expansion.string) {
+                    return Err(GrammarError::MalformedToken {
+                        expansion: expansion.string.clone(),
+                        position,
+                    });
+                }
+            }
+        }
+        Ok(Grammar::new(expansions))
+    }
+
+    /// Attaches the attribute rules of the nonterminals, see the `attributes` module
+    pub fn with_attributes(mut self, attributes: AttributeRules) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    /// The attribute rules of the nonterminals, a grammar without attributes has empty rules
+    pub fn attributes(&self) -> &AttributeRules {
+        &self.attributes
+    }
+
+    /// Marks a loaded grammar, `ebnf_to_bnf` keeps the verbatim expansions as they are
+    /// and removes the escapes of the other ones
+    pub(crate) fn with_verbatim(mut self, verbatim: HashSet<(String, String)>) -> Self {
+        self.verbatim = Some(verbatim);
+        self
+    }
+
+    /// The verbatim expansions of a loaded grammar, None for the other grammars
+    pub(crate) fn verbatim(&self) -> Option<&HashSet<(String, String)>> {
+        self.verbatim.as_ref()
     }
 
     /// The minimum of the potential expansion costs
+    /// with an empty `seen` set, the cost is looked up in the precomputed cost table
     pub fn symbol_cost(&self, symbol: &str, seen: &HashSet<&str>) -> f64 {
+        if seen.is_empty() {
+            return self.costs.symbol(symbol);
+        }
         self[symbol]
             .iter()
             .map(|expansion| self.expansion_cost(expansion, &add_to_set(seen, symbol)))
@@ -151,28 +255,93 @@ impl<T> Grammar<T> {
             .iter()
             .map(|token| self.symbol_cost(token, seen))
             .sum();
-        let cost: f64 = expansion_cost + step_cost;
-        cost
+        expansion_cost + step_cost
+    }
+
+    /// The precomputed costs of the alternative expansions of a symbol, in the same order as the alternatives
+    pub fn expansion_costs(&self, symbol: &str) -> &[f64] {
+        self.costs.expansions(symbol)
+    }
+
+    /// The precomputed costs of the alternative expansions of a symbol when the symbol can't appear again
+    /// below the expansion, the expansions that recurse into the symbol cost infinity
+    pub fn acyclic_expansion_costs(&self, symbol: &str) -> &[f64] {
+        self.costs.acyclic_expansions(symbol)
+    }
+
+    /// The minimum depth of a derivation tree that turns the symbol into terminals,
+    /// a symbol with an expansion without nonterminals has depth 1, an undefined symbol has an infinite depth
+    pub fn symbol_depth(&self, symbol: &str) -> f64 {
+        self.costs.symbol_depth(symbol)
+    }
+
+    /// The minimum depths of the derivation trees that start with each alternative expansion of a symbol,
+    /// in the same order as the alternatives
+    pub fn expansion_depths(&self, symbol: &str) -> &[f64] {
+        self.costs.expansion_depths(symbol)
     }
 
     /// Looks for unreachable nonterminals, reachable nonterminals and unavoidable cycles
+    ///
+    /// Unreachable nonterminals don't make the grammar invalid, use `validate` to reject them
     pub fn is_valid_grammar(&self, start_symbol: Option<&str>) -> bool {
+        match self.validate(start_symbol) {
+            Ok(()) => true,
+            Err(GrammarError::UnreachableNonterminals(_)) => {
+                self.find_unavoidable_cycle().is_empty()
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Reports the undefined nonterminals, the unreachable nonterminals or
+    /// the nonterminals in unavoidable cycles, in that order
+    pub fn validate(&self, start_symbol: Option<&str>) -> Result<(), GrammarError> {
         let start_symbol = start_symbol.unwrap_or("<start>");
         let reachable_nonterminals = self.find_reachable_nonterminals(start_symbol);
         let defined_nonterminals: HashSet<&str> = self.keys().map(|t| t.as_str()).collect();
-        let unreachable_nonterminals = &defined_nonterminals - &reachable_nonterminals;
         let undefined_nonterminals = &reachable_nonterminals - &defined_nonterminals;
-        let cycle = self.find_unavoidable_cycle();
+        if !undefined_nonterminals.is_empty() {
+            return Err(GrammarError::UndefinedNonterminals(sorted(
+                undefined_nonterminals,
+            )));
+        }
+        let unreachable_nonterminals = &defined_nonterminals - &reachable_nonterminals;
         if !unreachable_nonterminals.is_empty() {
-            println!("unreachable nonterminals: {:?}", unreachable_nonterminals);
+            return Err(GrammarError::UnreachableNonterminals(sorted(
+                unreachable_nonterminals,
+            )));
         }
+        let cycle = self.find_unavoidable_cycle();
+        if !cycle.is_empty() {
+            return Err(GrammarError::UnavoidableCycle(sorted(cycle)));
+        }
+        Ok(())
+    }
+
+    /// Checks the part of the grammar that can be reached from an entry point: the entry point
+    /// and the nonterminals it reaches must be defined and must not be in unavoidable cycles,
+    /// the nonterminals it doesn't reach are not checked
+    pub fn validate_entry_point(&self, symbol: &str) -> Result<(), GrammarError> {
+        let reachable_nonterminals = self.find_reachable_nonterminals(symbol);
+        let undefined_nonterminals: HashSet<&str> = reachable_nonterminals
+            .iter()
+            .filter(|token| !self.contains_key(**token))
+            .cloned()
+            .collect();
         if !undefined_nonterminals.is_empty() {
-            println!("undefined nonterminals: {:?}", undefined_nonterminals);
+            return Err(GrammarError::UndefinedNonterminals(sorted(
+                undefined_nonterminals,
+            )));
         }
+        let cycle: HashSet<&str> = reachable_nonterminals
+            .into_iter()
+            .filter(|token| self.symbol_cost(token, &HashSet::new()) == f64::INFINITY)
+            .collect();
         if !cycle.is_empty() {
-            println!("tokens in unavoidable cycles: {:?}", cycle);
+            return Err(GrammarError::UnavoidableCycle(sorted(cycle)));
         }
-        undefined_nonterminals.is_empty() & cycle.is_empty()
+        Ok(())
     }
 
     /// Returns reachable nonterminal symbols from a start symbol
@@ -211,8 +380,264 @@ impl<T> Grammar<T> {
     }
 }
 
+// ----------------------------- Probabilities --------------------------------
+
+impl Grammar<ExpansionOpts> {
+    /// The probability of choosing each alternative expansion of a symbol,
+    /// the expansions without a `prob` share the probability left by the others equally
+    /// and when every expansion has a `prob` they are scaled to sum to 1, or made uniform if they are all 0
+    pub fn expansion_probabilities(&self, symbol: &str) -> Vec<f64> {
+        let alternatives = match self.get(symbol) {
+            Some(alternatives) => alternatives,
+            None => return Vec::new(),
+        };
+        let specified: f64 = alternatives.iter().filter_map(|e| e.opts_prob()).sum();
+        let unspecified = alternatives
+            .iter()
+            .filter(|e| e.opts_prob().is_none())
+            .count();
+        if unspecified == 0 && specified > 0.0 {
+            return alternatives
+                .iter()
+                .map(|e| e.opts_prob().unwrap() / specified)
+                .collect();
+        }
+        if unspecified == 0 {
+            return vec![1.0 / alternatives.len() as f64; alternatives.len()];
+        }
+        let shared = (1.0 - specified).max(0.0) / unspecified as f64;
+        alternatives
+            .iter()
+            .map(|e| e.opts_prob().unwrap_or(shared))
+            .collect()
+    }
+
+    /// Checks that every probability is between 0 and 1
+    /// and that the probabilities of the expansions of a symbol sum to at most 1
+    pub fn validate_probabilities(&self) -> Result<(), GrammarError> {
+        let mut invalid: Vec<&str> = self
+            .iter()
+            .filter(|(_, alternatives)| {
+                let probabilities: Vec<f64> =
+                    alternatives.iter().filter_map(|e| e.opts_prob()).collect();
+                let sum: f64 = probabilities.iter().sum();
+                probabilities.iter().any(|p| !(0.0..=1.0).contains(p))
+                    || sum > 1.0 + PROBABILITY_TOLERANCE
+            })
+            .map(|(symbol, _)| symbol.as_str())
+            .collect();
+        invalid.sort_unstable();
+        match invalid.first() {
+            Some(symbol) => Err(GrammarError::InvalidProbabilities(String::from(*symbol))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Rounding errors allowed when summing probabilities, ex: 0.1 + 0.2 + 0.7
+const PROBABILITY_TOLERANCE: f64 = 1e-9;
+
+impl Expansion<ExpansionOpts> {
+    fn opts_prob(&self) -> Option<f64> {
+        self.opts.as_ref().and_then(|opts| opts.prob)
+    }
+}
+
+// -------------------------------- Costs -------------------------------------
+
+/// The cost of a symbol is the minimum number of expansions needed to turn it into terminals,
+/// the cost of an expansion is 1 plus the sum of the costs of its nonterminal symbols
+///
+/// The acyclic cost of an expansion is its cost when the expanded symbol can't appear again below it,
+/// so the expansions that recurse into the symbol cost infinity
+///
+/// The depth of a symbol is the minimum depth of a derivation tree that turns it into terminals,
+/// the depth of an expansion is 1 plus the maximum depth of its nonterminal symbols
+#[derive(Debug)]
+struct Costs {
+    symbols: HashMap<String, f64>,
+    expansions: HashMap<String, Vec<f64>>,
+    acyclic_expansions: HashMap<String, Vec<f64>>,
+    symbol_depths: HashMap<String, f64>,
+    expansion_depths: HashMap<String, Vec<f64>>,
+}
+
+impl Costs {
+    fn symbol(&self, symbol: &str) -> f64 {
+        *self.symbols.get(symbol).unwrap_or(&f64::INFINITY)
+    }
+
+    fn expansions(&self, symbol: &str) -> &[f64] {
+        self.expansions.get(symbol).map_or(&[], |costs| costs)
+    }
+
+    fn acyclic_expansions(&self, symbol: &str) -> &[f64] {
+        self.acyclic_expansions
+            .get(symbol)
+            .map_or(&[], |costs| costs)
+    }
+
+    fn symbol_depth(&self, symbol: &str) -> f64 {
+        *self.symbol_depths.get(symbol).unwrap_or(&f64::INFINITY)
+    }
+
+    fn expansion_depths(&self, symbol: &str) -> &[f64] {
+        self.expansion_depths
+            .get(symbol)
+            .map_or(&[], |depths| depths)
+    }
+}
+
+impl<T> From<&Expansions<T>> for Costs {
+    fn from(expansions: &Expansions<T>) -> Self {
+        // An expansion that can't be tokenized can never be turned into terminals
+        let nonterminals: HashMap<&str, Vec<Option<Vec<&str>>>> = expansions
+            .iter()
+            .map(|(symbol, alternatives)| {
+                let alternatives = alternatives
+                    .iter()
+                    .map(|expansion| try_nonterminal_tokens(&expansion.string))
+                    .collect();
+                (symbol.as_str(), alternatives)
+            })
+            .collect();
+        let cost = |costs: &mut dyn Iterator<Item = f64>| costs.sum::<f64>() + 1.0;
+        let depth = |depths: &mut dyn Iterator<Item = f64>| depths.fold(0.0, f64::max) + 1.0;
+
+        let symbols = fixpoint(&nonterminals, None, cost);
+        let symbol_depths = fixpoint(&nonterminals, None, depth);
+        // A cheapest derivation never repeats a symbol below itself,
+        // so forbidding only the expanded symbol gives the same costs as forbidding every symbol on the path
+        let acyclic_expansions = nonterminals
+            .keys()
+            .map(|symbol| {
+                let symbols = fixpoint(&nonterminals, Some(symbol), cost);
+                (
+                    String::from(*symbol),
+                    expansion_values(&nonterminals[symbol], &symbols, cost),
+                )
+            })
+            .collect();
+
+        Costs {
+            expansions: all_expansion_values(&nonterminals, &symbols, cost),
+            expansion_depths: all_expansion_values(&nonterminals, &symbol_depths, depth),
+            symbols,
+            acyclic_expansions,
+            symbol_depths,
+        }
+    }
+}
+
+/// Lowers the symbol values from infinity until they reach a fixpoint,
+/// symbols that keep an infinite value are in unavoidable cycles
+///
+/// `combine` computes the value of an expansion from the values of its nonterminal symbols,
+/// the `excluded` symbol keeps an infinite value
+fn fixpoint<F>(
+    nonterminals: &HashMap<&str, Vec<Option<Vec<&str>>>>,
+    excluded: Option<&str>,
+    combine: F,
+) -> HashMap<String, f64>
+where
+    F: Fn(&mut dyn Iterator<Item = f64>) -> f64,
+{
+    let mut symbols: HashMap<String, f64> = nonterminals
+        .keys()
+        .map(|symbol| (String::from(*symbol), f64::INFINITY))
+        .collect();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (symbol, alternatives) in nonterminals.iter() {
+            if Some(*symbol) == excluded {
+                continue;
+            }
+            let value = alternatives
+                .iter()
+                .map(|tokens| expansion_value(&symbols, tokens, &combine))
+                .fold(f64::INFINITY, f64::min);
+            if value < symbols[*symbol] {
+                symbols.insert(String::from(*symbol), value);
+                changed = true;
+            }
+        }
+    }
+
+    symbols
+}
+
+/// The values of the alternative expansions of every symbol
+fn all_expansion_values<F>(
+    nonterminals: &HashMap<&str, Vec<Option<Vec<&str>>>>,
+    symbols: &HashMap<String, f64>,
+    combine: F,
+) -> HashMap<String, Vec<f64>>
+where
+    F: Fn(&mut dyn Iterator<Item = f64>) -> f64,
+{
+    nonterminals
+        .iter()
+        .map(|(symbol, alternatives)| {
+            let values = expansion_values(alternatives, symbols, &combine);
+            (String::from(*symbol), values)
+        })
+        .collect()
+}
+
+/// The values of the alternative expansions of a symbol
+fn expansion_values<F>(
+    alternatives: &[Option<Vec<&str>>],
+    symbols: &HashMap<String, f64>,
+    combine: F,
+) -> Vec<f64>
+where
+    F: Fn(&mut dyn Iterator<Item = f64>) -> f64,
+{
+    alternatives
+        .iter()
+        .map(|tokens| expansion_value(symbols, tokens, &combine))
+        .collect()
+}
+
+fn expansion_value<F>(symbols: &HashMap<String, f64>, tokens: &Option<Vec<&str>>, combine: F) -> f64
+where
+    F: Fn(&mut dyn Iterator<Item = f64>) -> f64,
+{
+    tokens.as_ref().map_or(f64::INFINITY, |tokens| {
+        combine(
+            &mut tokens
+                .iter()
+                .map(|token| *symbols.get(*token).unwrap_or(&f64::INFINITY)),
+        )
+    })
+}
+
+/// Returns the symbols as a sorted vector of owned strings
+fn sorted<'a, I: IntoIterator<Item = &'a str>>(symbols: I) -> Vec<String> {
+    let mut symbols: Vec<String> = symbols.into_iter().map(String::from).collect();
+    symbols.sort();
+    symbols
+}
+
+/// Returns the nonterminal symbols in the same order as the input string,
+/// or None when the input can't be tokenized
+fn try_nonterminal_tokens(input: &str) -> Option<Vec<&str>> {
+    let tokens = parser::try_tokens(input).ok()?;
+    Some(
+        tokens
+            .into_iter()
+            .filter_map(|t| match t {
+                Token::Nonterminal(t) => Some(t),
+                Token::Terminal(_) => None,
+            })
+            .collect(),
+    )
+}
+
 /// Returns the nonterminal symbols in the same order as the input string
-fn nonterminal_tokens(input: &str) -> Vec<&str> {
+pub(crate) fn nonterminal_tokens(input: &str) -> Vec<&str> {
     parser::tokens(input)
         .iter()
         .filter(|t| match t {
@@ -299,15 +724,202 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_expansion_costs() {
+        let grammar = sample_grammar();
+        assert_eq!(grammar.expansion_costs("<int>"), &[4.0, 2.0]);
+        assert_eq!(grammar.expansion_costs("<values>"), &[6.0, 3.0]);
+        assert_eq!(grammar.expansion_costs("<float>"), &[] as &[f64]);
+
+        let grammar = invalid_grammar();
+        assert_eq!(grammar.expansion_costs("<int>"), &[f64::INFINITY]);
+        assert_eq!(
+            grammar.symbol_cost("<values>", &HashSet::new()),
+            f64::INFINITY
+        );
+    }
+
+    #[test]
+    fn test_acyclic_expansion_costs() {
+        let grammar = sample_grammar();
+        assert_eq!(
+            grammar.acyclic_expansion_costs("<int>"),
+            &[f64::INFINITY, 2.0]
+        );
+        assert_eq!(grammar.acyclic_expansion_costs("<list>"), &[4.0]);
+        for symbol in grammar.keys() {
+            let seen = [symbol.as_str()].iter().cloned().collect();
+            let costs: Vec<f64> = grammar[symbol]
+                .iter()
+                .map(|expansion| grammar.expansion_cost(expansion, &seen))
+                .collect();
+            assert_eq!(grammar.acyclic_expansion_costs(symbol), costs.as_slice());
+        }
+    }
+
+    #[test]
+    fn test_depths() {
+        let grammar = sample_grammar();
+        assert_eq!(grammar.symbol_depth("<digit>"), 1.0);
+        assert_eq!(grammar.symbol_depth("<list>"), 4.0);
+        assert_eq!(grammar.symbol_depth("<float>"), f64::INFINITY);
+        assert_eq!(grammar.expansion_depths("<int>"), &[3.0, 2.0]);
+        assert_eq!(grammar.expansion_depths("<values>"), &[4.0, 3.0]);
+
+        let grammar = invalid_grammar();
+        assert_eq!(grammar.symbol_depth("<int>"), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_costs_of_a_deep_grammar() {
+        // Recomputing the costs recursively visits 2^depth expansions
+        let depth = 64;
+        let rules: Vec<(String, Vec<String>)> = (0..depth)
+            .map(|i| {
+                let next = format!("<s{}>", i + 1);
+                (format!("<s{}>", i), vec![format!("{}{}", next, next), next])
+            })
+            .chain(vec![(format!("<s{}>", depth), vec![String::from("x")])])
+            .collect();
+        let grammar = Grammar::from(&rules.into_iter().collect::<HashMap<_, _>>());
+
+        assert_eq!(
+            grammar.symbol_cost("<s0>", &HashSet::new()),
+            depth as f64 + 1.0
+        );
+        assert_eq!(
+            grammar.expansion_costs("<s0>"),
+            &[2.0 * depth as f64 + 1.0, depth as f64 + 1.0]
+        );
+    }
+
+    fn probabilistic_grammar(json: &str) -> Grammar<ExpansionOpts> {
+        Grammar::from_json(json).unwrap()
+    }
+
+    #[test]
+    fn test_expansion_probabilities() {
+        let grammar = probabilistic_grammar(
+            r#"{
+                "<digit>": [["0", {"prob": 0.5}], "1", "2"],
+                "<sign>": [["+", {"prob": 0.2}], ["-", {"prob": 0.6}]],
+                "<bit>": ["0", "1"]
+            }"#,
+        );
+
+        assert_eq!(
+            grammar.expansion_probabilities("<digit>"),
+            vec![0.5, 0.25, 0.25]
+        );
+        let sign = grammar.expansion_probabilities("<sign>");
+        assert!((sign[0] - 0.25).abs() < 1e-9 && (sign[1] - 0.75).abs() < 1e-9);
+        assert_eq!(grammar.expansion_probabilities("<bit>"), vec![0.5, 0.5]);
+        assert_eq!(
+            grammar.expansion_probabilities("<float>"),
+            Vec::<f64>::new()
+        );
+    }
+
+    #[test]
+    fn test_validate_probabilities() {
+        let valid = probabilistic_grammar(
+            r#"{"<digit>": [["0", {"prob": 0.1}], ["1", {"prob": 0.2}], ["2", {"prob": 0.7}]]}"#,
+        );
+        assert_eq!(valid.validate_probabilities(), Ok(()));
+
+        let above_one = probabilistic_grammar(
+            r#"{"<bit>": ["0", "1"], "<digit>": [["0", {"prob": 0.6}], ["1", {"prob": 0.6}]]}"#,
+        );
+        assert_eq!(
+            above_one.validate_probabilities(),
+            Err(GrammarError::InvalidProbabilities(String::from("<digit>")))
+        );
+
+        let negative = probabilistic_grammar(r#"{"<bit>": [["0", {"prob": -0.5}], "1"]}"#);
+        assert_eq!(
+            negative.validate_probabilities(),
+            Err(GrammarError::InvalidProbabilities(String::from("<bit>")))
+        );
+    }
+
     #[test]
     fn is_valid_grammar() {
         let grammar = sample_grammar();
         assert_eq!(grammar.is_valid_grammar(Some("<list>")), true);
+        // unreachable nonterminals are not fatal
+        assert!(grammar.is_valid_grammar(Some("<values>")));
 
         let invalid_grammar = invalid_grammar();
         assert_eq!(invalid_grammar.is_valid_grammar(Some("<list>")), false);
     }
 
+    #[test]
+    fn test_validate() {
+        assert_eq!(sample_grammar().validate(Some("<list>")), Ok(()));
+        assert_eq!(
+            invalid_grammar().validate(Some("<list>")),
+            Err(GrammarError::UnavoidableCycle(vec![
+                String::from("<int>"),
+                String::from("<list>"),
+                String::from("<values>"),
+            ]))
+        );
+        assert_eq!(
+            sample_grammar().validate(Some("<values>")),
+            Err(GrammarError::UnreachableNonterminals(vec![String::from(
+                "<list>"
+            )]))
+        );
+
+        let expansions: HashMap<_, _> = [("<list>", vec!["[<values>]"])].iter().cloned().collect();
+        assert_eq!(
+            Grammar::from(&expansions).validate(Some("<list>")),
+            Err(GrammarError::UndefinedNonterminals(vec![String::from(
+                "<values>"
+            )]))
+        );
+    }
+
+    #[test]
+    fn test_validate_entry_point() {
+        assert_eq!(sample_grammar().validate_entry_point("<values>"), Ok(()));
+        assert_eq!(
+            sample_grammar().validate_entry_point("<start>"),
+            Err(GrammarError::UndefinedNonterminals(vec![String::from(
+                "<start>"
+            )]))
+        );
+        assert_eq!(
+            invalid_grammar().validate_entry_point("<values>"),
+            Err(GrammarError::UnavoidableCycle(vec![
+                String::from("<int>"),
+                String::from("<values>"),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_try_new() {
+        let expansions = |symbol: &str, expansion: &str| -> Expansions<()> {
+            vec![(String::from(symbol), vec![Expansion::new(expansion, None)])]
+                .into_iter()
+                .collect()
+        };
+
+        assert!(Grammar::try_new(expansions("<digit>", "0")).is_ok());
+        assert_eq!(
+            Grammar::try_new(expansions("digit", "0")),
+            Err(GrammarError::InvalidNonterminal(String::from("digit")))
+        );
+        assert_eq!(
+            Grammar::try_new(expansions("<lt>", "a < b")),
+            Err(GrammarError::MalformedToken {
+                expansion: String::from("a < b"),
+                position: 2
+            })
+        );
+    }
+
     #[test]
     fn test_find_reachable_nonterminals() {
         let grammar = sample_grammar();
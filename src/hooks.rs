@@ -0,0 +1,186 @@
+//! Functions attached to the expansions of a grammar, following the
+//! ["Fuzzing with Generators"](https://www.fuzzingbook.org/html/GeneratorGrammarFuzzer.html) chapter of The Fuzzing Book
+//!
+//! A pre-hook produces the text of an expansion programmatically, ex: a random 32-bit integer or a UUID,
+//! and a post-hook checks the text of the expanded subtree and accepts it, rejects it or rewrites it,
+//! ex: a valid port number or a matching checksum.
+//! The fuzzer applies the hooks once a tree is complete, children before their parents,
+//! and regenerates a subtree rejected by its post-hook with its strategies.
+//!
+//! The hooks are carried by the `ExpansionOpts` of the expansions, they are not part of JSON grammars.
+//! A fuzzer over other options needs an `ExpansionHooks` impl for them, its default methods give no hooks.
+//!
+//! ## Example
+//!
+//! ```
+//! use grammar_fuzzer::hooks::Verdict;
+//! use grammar_fuzzer::{CloseStrategy, Expansion, ExpansionOpts, Expansions, Grammar};
+//! use grammar_fuzzer::{GrammarFuzzer, RandomStrategy};
+//!
+//! let id = ExpansionOpts::default().with_pre(|rng| Some(format!("{:08x}", rng.next_u32())));
+//! let port = ExpansionOpts::default().with_post(|text| match text.parse::<u32>() {
+//!     Ok(port) if (1..=65535).contains(&port) => Verdict::Accept,
+//!     _ => Verdict::Reject,
+//! });
+//! let digits = (0..10).map(|d| Expansion::new(&d.to_string(), None)).collect();
+//! let expansions: Expansions<ExpansionOpts> = vec![
+//!     ("<start>", vec![Expansion::new("<id>@localhost:<port>", None)]),
+//!     ("<id>", vec![Expansion::new("<digit>", Some(id))]),
+//!     ("<port>", vec![Expansion::new("<digits>", Some(port))]),
+//!     (
+//!         "<digits>",
+//!         vec![Expansion::new("<digit><digits>", None), Expansion::new("<digit>", None)],
+//!     ),
+//!     ("<digit>", digits),
+//! ]
+//! .into_iter()
+//! .map(|(symbol, alternatives)| (String::from(symbol), alternatives))
+//! .collect();
+//!
+//! let fuzzer = GrammarFuzzer::builder(Grammar::new(expansions))
+//!     .then(RandomStrategy::new(5, 20))
+//!     .then(CloseStrategy::new())
+//!     .seed(0)
+//!     .build();
+//! for input in fuzzer.inputs().take(10) {
+//!     let (id, port) = input.split_at(8);
+//!     assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+//!     assert!(port["@localhost:".len()..].parse::<u16>().unwrap() > 0);
+//! }
+//! ```
+
+use super::grammar::ExpansionOpts;
+use rand::RngCore;
+use std::fmt;
+use std::sync::Arc;
+
+/// What a post-hook decides about the text of an expanded subtree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// Keeps the subtree
+    Accept,
+    /// Regenerates the subtree
+    Reject,
+    /// Replaces the subtree with the text
+    Replace(String),
+}
+
+type Generate = dyn Fn(&mut dyn RngCore) -> Option<String> + Send + Sync;
+
+/// Produces the text of an expansion, None keeps the expansion of the grammar
+#[derive(Clone)]
+pub struct PreHook(Arc<Generate>);
+
+impl PreHook {
+    /// The function makes its random choices with the generator of the fuzzer, so that seeding the fuzzer
+    /// makes the generated text reproducible
+    pub fn new<F>(generate: F) -> Self
+    where
+        F: Fn(&mut dyn RngCore) -> Option<String> + Send + Sync + 'static,
+    {
+        PreHook(Arc::new(generate))
+    }
+
+    pub fn generate(&self, rng: &mut dyn RngCore) -> Option<String> {
+        (self.0)(rng)
+    }
+}
+
+/// Checks the text of an expanded subtree
+#[derive(Clone)]
+pub struct PostHook(Arc<dyn Fn(&str) -> Verdict + Send + Sync>);
+
+impl PostHook {
+    pub fn new<F>(check: F) -> Self
+    where
+        F: Fn(&str) -> Verdict + Send + Sync + 'static,
+    {
+        PostHook(Arc::new(check))
+    }
+
+    pub fn check(&self, text: &str) -> Verdict {
+        (self.0)(text)
+    }
+}
+
+/// Hooks are compared by identity, two clones of a hook are equal
+impl PartialEq for PreHook {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl PartialEq for PostHook {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for PreHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PreHook")
+    }
+}
+
+impl fmt::Debug for PostHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PostHook")
+    }
+}
+
+/// The hooks of expansion options, the fuzzer applies the hooks of the expansions of its trees
+///
+/// Expansions have no hooks by default, options without hooks implement it with an empty impl block,
+/// ex: `impl ExpansionHooks for Tag {}`
+pub trait ExpansionHooks {
+    fn pre_hook(&self) -> Option<&PreHook> {
+        None
+    }
+
+    fn post_hook(&self) -> Option<&PostHook> {
+        None
+    }
+}
+
+impl ExpansionHooks for () {}
+
+impl ExpansionHooks for ExpansionOpts {
+    fn pre_hook(&self) -> Option<&PreHook> {
+        self.pre.as_ref()
+    }
+
+    fn post_hook(&self) -> Option<&PostHook> {
+        self.post.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_hooks() {
+        let opts = ExpansionOpts::default()
+            .with_pre(|rng| Some(format!("{}", rng.next_u32() % 10)))
+            .with_post(|text| match text {
+                "0" => Verdict::Reject,
+                "1" => Verdict::Replace(String::from("one")),
+                _ => Verdict::Accept,
+            });
+        let pre = opts.pre_hook().unwrap();
+        assert_eq!(
+            pre.generate(&mut StdRng::seed_from_u64(3)),
+            pre.generate(&mut StdRng::seed_from_u64(3))
+        );
+        let post = opts.post_hook().unwrap();
+        assert_eq!(post.check("0"), Verdict::Reject);
+        assert_eq!(post.check("1"), Verdict::Replace(String::from("one")));
+        assert_eq!(post.check("2"), Verdict::Accept);
+
+        assert_eq!(opts.clone(), opts);
+        assert_ne!(opts.clone().with_post(|_| Verdict::Accept), opts);
+        assert_eq!(().pre_hook(), None);
+    }
+}
@@ -0,0 +1,174 @@
+//! Reads and writes grammars in the JSON shape of The Fuzzing Book grammars,
+//! a map from nonterminal symbols to a list of expansions, where an expansion is either
+//! an expansion-string or an `[expansion-string, options]` pair
+
+use super::error::GrammarError;
+use super::grammar::{Expansion, Expansions, Grammar};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+impl<T: DeserializeOwned> Grammar<T> {
+    /// Parses a grammar, the options of an expansion are deserialized into `T`,
+    /// an empty options object is the same as no options
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use grammar_fuzzer::Grammar;
+    /// use serde_json::Value;
+    ///
+    /// let grammar: Grammar<Value> = Grammar::from_json(r#"{
+    ///     "<start>": ["<digit>"],
+    ///     "<digit>": ["0", ["1", {"prob": 0.9}]]
+    /// }"#).unwrap();
+    ///
+    /// assert_eq!(grammar["<digit>"][0].opts, None);
+    /// assert_eq!(grammar["<digit>"][1].opts.as_ref().unwrap()["prob"], 0.9);
+    /// ```
+    pub fn from_json(input: &str) -> Result<Self, GrammarError> {
+        let json: Map<String, Value> = serde_json::from_str(input).map_err(json_error)?;
+        let mut expansions = Expansions::new();
+        for (symbol, alternatives) in json {
+            let alternatives = match alternatives {
+                Value::Array(alternatives) => alternatives,
+                _ => {
+                    return Err(GrammarError::Json(format!(
+                        "{}: expected a list of expansions",
+                        symbol
+                    )))
+                }
+            };
+            let alternatives = alternatives
+                .into_iter()
+                .enumerate()
+                .map(|(idx, alternative)| {
+                    expansion(alternative).map_err(|message| {
+                        GrammarError::Json(format!("{}: expansion {}: {}", symbol, idx, message))
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+            expansions.insert(symbol, alternatives);
+        }
+        Grammar::try_new(expansions)
+    }
+}
+
+impl<T: Serialize> Grammar<T> {
+    /// Writes the grammar with its symbols sorted,
+    /// expansions with options become `[expansion-string, options]` pairs
+    pub fn to_json(&self) -> Result<String, GrammarError> {
+        let mut json = Map::new();
+        for (symbol, alternatives) in self.iter() {
+            let alternatives = alternatives
+                .iter()
+                .map(|expansion| match &expansion.opts {
+                    None => Ok(Value::String(expansion.string.clone())),
+                    Some(opts) => Ok(Value::Array(vec![
+                        Value::String(expansion.string.clone()),
+                        serde_json::to_value(opts).map_err(json_error)?,
+                    ])),
+                })
+                .collect::<Result<_, _>>()?;
+            json.insert(symbol.clone(), Value::Array(alternatives));
+        }
+        serde_json::to_string_pretty(&json).map_err(json_error)
+    }
+}
+
+/// Converts `"expansion"` or `["expansion", {options}]` into an Expansion
+fn expansion<T: DeserializeOwned>(alternative: Value) -> Result<Expansion<T>, String> {
+    match alternative {
+        Value::String(string) => Ok(Expansion::new(&string, None)),
+        Value::Array(mut pair) if pair.len() == 2 => {
+            let opts = pair.pop().unwrap();
+            let string = match pair.pop().unwrap() {
+                Value::String(string) => string,
+                _ => return Err(String::from("expected an expansion-string")),
+            };
+            let opts = match opts {
+                Value::Object(ref map) if map.is_empty() => None,
+                opts => Some(serde_json::from_value(opts).map_err(|e| e.to_string())?),
+            };
+            Ok(Expansion::new(&string, opts))
+        }
+        _ => Err(String::from(
+            "expected an expansion-string or an [expansion-string, options] pair",
+        )),
+    }
+}
+
+fn json_error(error: serde_json::Error) -> GrammarError {
+    GrammarError::Json(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Opts {
+        prob: f64,
+    }
+
+    #[test]
+    fn test_from_json() {
+        let result: Grammar<()> =
+            Grammar::from_json(r#"{"<start>": ["<digit>"], "<digit>": ["0", "1", ["2", {}]]}"#)
+                .unwrap();
+        let expected: HashMap<&str, Vec<&str>> = [
+            ("<start>", vec!["<digit>"]),
+            ("<digit>", vec!["0", "1", "2"]),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+
+        assert_eq!(result, Grammar::from(&expected));
+    }
+
+    #[test]
+    fn test_from_json_options() {
+        let grammar: Grammar<Opts> =
+            Grammar::from_json(r#"{"<digit>": [["0", {"prob": 0.25}], "1"]}"#).unwrap();
+
+        assert_eq!(grammar["<digit>"][0].opts, Some(Opts { prob: 0.25 }));
+        assert_eq!(grammar["<digit>"][1].opts, None);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let input = r#"{"<start>": [["<digit>", {"prob": 1.0}]], "<digit>": ["0", "1"]}"#;
+        let grammar: Grammar<Opts> = Grammar::from_json(input).unwrap();
+        let output = grammar.to_json().unwrap();
+        let round_trip: Grammar<Opts> = Grammar::from_json(&output).unwrap();
+
+        assert_eq!(round_trip, grammar);
+        assert_eq!(round_trip["<start>"][0].opts, Some(Opts { prob: 1.0 }));
+    }
+
+    #[test]
+    fn test_from_json_errors() {
+        let error = |input| Grammar::<Value>::from_json(input).unwrap_err();
+
+        assert_eq!(
+            error(r#"{"<start>": "<digit>"}"#),
+            GrammarError::Json(String::from("<start>: expected a list of expansions"))
+        );
+        assert_eq!(
+            error(r#"{"<start>": ["<digit>", 1]}"#),
+            GrammarError::Json(String::from(
+                "<start>: expansion 1: expected an expansion-string or an [expansion-string, options] pair"
+            ))
+        );
+        assert_eq!(
+            error(r#"{"<start>": ["a < b"]}"#),
+            GrammarError::MalformedToken {
+                expansion: String::from("a < b"),
+                position: 2
+            }
+        );
+    }
+}
@@ -0,0 +1,515 @@
+//! Steers the fuzzer towards inputs whose length falls in a window, ex: payloads of about 4 KiB
+//!
+//! The minimal yield length of a symbol is the length of the shortest input it derives,
+//! its expected yield length is the average length of its inputs when every alternative is equally likely.
+//! While a tree is expanded, the length of its terminals plus the minimal lengths of its unexpanded
+//! nonterminals is the length of the shortest input it can still become, the `LengthStrategy` grows it
+//! until it reaches the window and then closes the tree without making it any longer.
+//!
+//! ## Example
+//!
+//! ```
+//! use grammar_fuzzer::{CloseStrategy, Grammar, GrammarFuzzer, LengthStrategy};
+//! use std::collections::HashMap;
+//! use std::sync::Arc;
+//!
+//! let expansions: HashMap<_, _> = [
+//!     ("<start>", vec!["[<items>]"]),
+//!     ("<items>", vec!["<item>,<items>", "<item>"]),
+//!     ("<item>", vec!["a", "bb", "(<items>)"]),
+//! ]
+//! .iter()
+//! .cloned()
+//! .collect();
+//! let grammar = Grammar::from(&expansions);
+//!
+//! let length = Arc::new(LengthStrategy::new(&grammar, 100..=120, 10_000));
+//! let fuzzer = GrammarFuzzer::builder(grammar)
+//!     .then(Arc::clone(&length))
+//!     .then(CloseStrategy::new())
+//!     .seed(0)
+//!     .build();
+//! for input in fuzzer.inputs().take(10) {
+//!     assert!((100..=120).contains(&input.len()));
+//! }
+//!
+//! let report = length.report();
+//! assert_eq!(report.hits, 10);
+//! assert_eq!(report.hit_rate(), 1.0);
+//! ```
+
+use super::derivation_tree::{DerivationTree, Node, NodeId};
+use super::grammar::Grammar;
+use super::parser::{self, Token};
+use super::shared::min_idx;
+use super::strategy::Strategy;
+use rand::seq::SliceRandom;
+use rand::RngCore;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::sync::Mutex;
+
+/// Expected lengths above the limit are considered infinite, ex: for grammars whose recursion grows faster than it ends
+const EXPECTED_LENGTH_LIMIT: f64 = 1e12;
+/// The expected lengths are approximated until they change less than the tolerance
+const EXPECTED_LENGTH_TOLERANCE: f64 = 1e-6;
+const MAX_ITERATIONS: usize = 10_000;
+
+/// The terminals of an expansion and its nonterminal symbols, None when it can't be tokenized
+type Parts<'a> = Option<(usize, Vec<&'a str>)>;
+
+// -------------------------------- Lengths -----------------------------------
+
+/// The minimal and expected yield lengths, in bytes, of the symbols and the expansions of a grammar
+///
+/// Symbols that can't be turned into terminals have infinite lengths
+#[derive(Debug, Clone)]
+pub struct YieldLengths {
+    min: HashMap<String, f64>,
+    expected: HashMap<String, f64>,
+    expansion_min: HashMap<String, Vec<f64>>,
+    expansion_expected: HashMap<String, Vec<f64>>,
+}
+
+impl YieldLengths {
+    pub fn new<T>(grammar: &Grammar<T>) -> Self {
+        let parts: HashMap<&str, Vec<Parts>> = grammar
+            .iter()
+            .map(|(symbol, alternatives)| {
+                let alternatives = alternatives
+                    .iter()
+                    .map(|expansion| expansion_parts(&expansion.string))
+                    .collect();
+                (symbol.as_str(), alternatives)
+            })
+            .collect();
+
+        let min = min_lengths(&parts);
+        let expected = expected_lengths(&parts, &min);
+        YieldLengths {
+            expansion_min: expansion_lengths(&parts, &min),
+            expansion_expected: expansion_lengths(&parts, &expected),
+            min,
+            expected,
+        }
+    }
+
+    /// The length of the shortest input derived from the symbol
+    pub fn min_length(&self, symbol: &str) -> f64 {
+        *self.min.get(symbol).unwrap_or(&f64::INFINITY)
+    }
+
+    /// The average length of the inputs derived from the symbol when every alternative is equally likely
+    pub fn expected_length(&self, symbol: &str) -> f64 {
+        *self.expected.get(symbol).unwrap_or(&f64::INFINITY)
+    }
+
+    /// The minimal lengths of the alternative expansions of a symbol, in the same order as the alternatives
+    pub fn expansion_min_lengths(&self, symbol: &str) -> &[f64] {
+        self.expansion_min
+            .get(symbol)
+            .map_or(&[], |lengths| lengths)
+    }
+
+    /// The expected lengths of the alternative expansions of a symbol, in the same order as the alternatives
+    pub fn expansion_expected_lengths(&self, symbol: &str) -> &[f64] {
+        self.expansion_expected
+            .get(symbol)
+            .map_or(&[], |lengths| lengths)
+    }
+}
+
+fn expansion_parts(expansion: &str) -> Parts<'_> {
+    let tokens = parser::try_tokens(expansion).ok()?;
+    let mut terminals = 0;
+    let mut nonterminals = Vec::new();
+    for token in tokens {
+        match token {
+            Token::Terminal(t) => terminals += t.len(),
+            Token::Nonterminal(t) => nonterminals.push(t),
+        }
+    }
+    Some((terminals, nonterminals))
+}
+
+/// The length of an expansion given the lengths of the symbols
+fn expansion_length(parts: &Parts, lengths: &HashMap<String, f64>) -> f64 {
+    parts
+        .as_ref()
+        .map_or(f64::INFINITY, |(terminals, nonterminals)| {
+            nonterminals
+                .iter()
+                .map(|symbol| *lengths.get(*symbol).unwrap_or(&f64::INFINITY))
+                .sum::<f64>()
+                + *terminals as f64
+        })
+}
+
+fn expansion_lengths(
+    parts: &HashMap<&str, Vec<Parts>>,
+    lengths: &HashMap<String, f64>,
+) -> HashMap<String, Vec<f64>> {
+    parts
+        .iter()
+        .map(|(symbol, alternatives)| {
+            let values = alternatives
+                .iter()
+                .map(|parts| expansion_length(parts, lengths))
+                .collect();
+            (String::from(*symbol), values)
+        })
+        .collect()
+}
+
+/// Lowers the lengths from infinity until they reach a fixpoint, like the costs of the grammar
+fn min_lengths(parts: &HashMap<&str, Vec<Parts>>) -> HashMap<String, f64> {
+    let mut lengths: HashMap<String, f64> = parts
+        .keys()
+        .map(|symbol| (String::from(*symbol), f64::INFINITY))
+        .collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (symbol, alternatives) in parts.iter() {
+            let length = alternatives
+                .iter()
+                .map(|parts| expansion_length(parts, &lengths))
+                .fold(f64::INFINITY, f64::min);
+            if length < lengths[*symbol] {
+                lengths.insert(String::from(*symbol), length);
+                changed = true;
+            }
+        }
+    }
+    lengths
+}
+
+/// Raises the lengths from 0 until they change less than the tolerance,
+/// the lengths that keep growing and the lengths of symbols without a minimal length are infinite
+fn expected_lengths(
+    parts: &HashMap<&str, Vec<Parts>>,
+    min: &HashMap<String, f64>,
+) -> HashMap<String, f64> {
+    let mut lengths: HashMap<String, f64> = parts
+        .keys()
+        .map(|symbol| {
+            let length = if min[*symbol].is_finite() {
+                0.0
+            } else {
+                f64::INFINITY
+            };
+            (String::from(*symbol), length)
+        })
+        .collect();
+    let mut growing: Vec<&str> = parts.keys().cloned().collect();
+    for _ in 0..MAX_ITERATIONS {
+        growing.clear();
+        for (symbol, alternatives) in parts.iter() {
+            let length = if lengths[*symbol].is_infinite() {
+                f64::INFINITY
+            } else {
+                alternatives
+                    .iter()
+                    .map(|parts| expansion_length(parts, &lengths))
+                    .sum::<f64>()
+                    / alternatives.len() as f64
+            };
+            let length = if length > EXPECTED_LENGTH_LIMIT {
+                f64::INFINITY
+            } else {
+                length
+            };
+            if length.is_finite() && length - lengths[*symbol] > EXPECTED_LENGTH_TOLERANCE {
+                growing.push(symbol);
+            }
+            lengths.insert(String::from(*symbol), length);
+        }
+        if growing.is_empty() {
+            break;
+        }
+    }
+    for symbol in growing {
+        lengths.insert(String::from(symbol), f64::INFINITY);
+    }
+    lengths
+}
+
+// -------------------------------- Report ------------------------------------
+
+/// How many of the inputs generated by the fuzzer fell in the length window, the lengths are
+/// those of the final inputs, after the hooks and the constraints
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LengthReport {
+    pub inputs: usize,
+    pub hits: usize,
+    pub too_short: usize,
+    pub too_long: usize,
+}
+
+impl LengthReport {
+    /// The fraction of the inputs in the window, 0 before any input
+    pub fn hit_rate(&self) -> f64 {
+        if self.inputs == 0 {
+            0.0
+        } else {
+            self.hits as f64 / self.inputs as f64
+        }
+    }
+}
+
+// -------------------------------- Length ------------------------------------
+
+/// Grows the tree with expansions that make its shortest completion longer until the shortest completion
+/// reaches the window, then closes it with the expansions that keep it as short as it is
+///
+/// Expansions that would make the shortest completion longer than the window are never chosen,
+/// and while growing the expansions whose expected length fits in the window are preferred.
+/// It expands every nonterminal unless it passes the expansions limit, the strategy that follows it
+/// may then make the input longer than the window.
+pub struct LengthStrategy {
+    lengths: YieldLengths,
+    min_length: usize,
+    max_length: usize,
+    max_steps: usize,
+    report: Mutex<LengthReport>,
+}
+
+impl LengthStrategy {
+    /// The yield lengths are estimated from the grammar that the fuzzer uses,
+    /// the window is in bytes of the generated input
+    pub fn new<T>(grammar: &Grammar<T>, window: RangeInclusive<usize>, max_steps: usize) -> Self {
+        LengthStrategy {
+            lengths: YieldLengths::new(grammar),
+            min_length: *window.start(),
+            max_length: *window.end(),
+            max_steps,
+            report: Mutex::new(LengthReport::default()),
+        }
+    }
+
+    pub fn lengths(&self) -> &YieldLengths {
+        &self.lengths
+    }
+
+    /// The lengths of the inputs completed so far
+    pub fn report(&self) -> LengthReport {
+        self.report.lock().unwrap().clone()
+    }
+
+    /// Forgets the inputs completed so far
+    pub fn reset(&self) {
+        *self.report.lock().unwrap() = LengthReport::default();
+    }
+
+    /// The length of the terminals of the tree plus the minimal lengths of its unexpanded nonterminals
+    fn shortest_completion(&self, tree: &DerivationTree) -> f64 {
+        let mut length = 0.0;
+        let mut stack = vec![tree.root()];
+        while let Some(id) = stack.pop() {
+            if tree.is_terminal(id) {
+                length += tree.symbol(id).len() as f64;
+            } else if let Some(symbol) = tree.open_symbol(id) {
+                length += self.lengths.min_length(symbol);
+            } else {
+                stack.extend(tree.children(id));
+            }
+        }
+        length
+    }
+}
+
+impl<T> Strategy<T> for LengthStrategy {
+    /// Continue until passing the expansions limit
+    fn cont(&self, _tree: &DerivationTree, num_steps: usize) -> bool {
+        num_steps < self.max_steps
+    }
+
+    /// Choose a random expansion that grows the shortest completion within the window,
+    /// or the cheapest expansion that keeps it unchanged once it reached the window
+    fn choose(
+        &self,
+        grammar: &Grammar<T>,
+        tree: &DerivationTree,
+        leaf: NodeId,
+        rng: &mut dyn RngCore,
+    ) -> Option<String> {
+        let symbol = tree.open_symbol(leaf)?;
+        let expansions = &grammar[symbol];
+        let min = self.lengths.min_length(symbol);
+        let mins = self.lengths.expansion_min_lengths(symbol);
+        let expected = self.lengths.expansion_expected_lengths(symbol);
+        // The shortest completion without the leaf
+        let rest = self.shortest_completion(tree) - min;
+        let max_length = self.max_length as f64;
+
+        if rest + min < self.min_length as f64 {
+            let fitting: Vec<usize> = (0..expansions.len())
+                .filter(|idx| rest + mins[*idx] <= max_length)
+                .collect();
+            let growing: Vec<usize> = fitting
+                .iter()
+                .cloned()
+                .filter(|idx| mins[*idx] > min)
+                .collect();
+            let preferred: Vec<usize> = growing
+                .iter()
+                .cloned()
+                .filter(|idx| rest + expected[*idx] <= max_length)
+                .collect();
+            if let Some(idx) = [preferred, growing, fitting]
+                .iter()
+                .find_map(|candidates| candidates.choose(rng))
+            {
+                return Some(expansions[*idx].string.clone());
+            }
+        }
+
+        // Closing, the cheapest of the expansions with the minimal length
+        let closing: Vec<usize> = (0..expansions.len())
+            .filter(|idx| mins[*idx] <= min)
+            .collect();
+        if closing.is_empty() {
+            return None;
+        }
+        let costs = grammar.expansion_costs(symbol);
+        let closing_costs: Vec<f64> = closing.iter().map(|idx| costs[*idx]).collect();
+        let idx = closing[min_idx(&closing_costs, rng)];
+        Some(expansions[idx].string.clone())
+    }
+
+    /// Records the length of the generated input
+    fn generated(&self, tree: &Node) {
+        let length = tree.to_string().len();
+        let mut report = self.report.lock().unwrap();
+        report.inputs += 1;
+        if length < self.min_length {
+            report.too_short += 1;
+        } else if length > self.max_length {
+            report.too_long += 1;
+        } else {
+            report.hits += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fuzzer::GrammarFuzzer;
+    use crate::grammar::{Expansion, ExpansionOpts};
+    use crate::hooks::Verdict;
+    use crate::shared::grammar;
+    use crate::strategy::CloseStrategy;
+    use std::sync::Arc;
+
+    fn list_grammar() -> Grammar<()> {
+        grammar(&[
+            ("<start>", vec!["[<items>]"]),
+            ("<items>", vec!["<item>,<items>", "<item>"]),
+            ("<item>", vec!["<digit>", "\"<digit><digit>\"", "(<items>)"]),
+            ("<digit>", vec!["0", "1", "2"]),
+        ])
+    }
+
+    #[test]
+    fn test_yield_lengths() {
+        let lengths = YieldLengths::new(&grammar(&[
+            ("<int>", vec!["<digit><int>", "<digit>"]),
+            ("<digit>", vec!["0", "12"]),
+            ("<tree>", vec!["<tree><tree>", "a"]),
+            ("<loop>", vec!["<loop>"]),
+        ]));
+        assert_eq!(lengths.min_length("<digit>"), 1.0);
+        assert_eq!(lengths.min_length("<int>"), 1.0);
+        assert_eq!(lengths.expansion_min_lengths("<int>"), &[2.0, 1.0]);
+        assert_eq!(lengths.min_length("<loop>"), f64::INFINITY);
+
+        // E(<int>) = (1.5 + E(<int>)) / 2 + 1.5 / 2
+        assert_eq!(lengths.expected_length("<digit>"), 1.5);
+        assert!((lengths.expected_length("<int>") - 3.0).abs() < 1e-3);
+        // Every <tree> is as likely to end as to double
+        assert_eq!(lengths.expected_length("<tree>"), f64::INFINITY);
+        assert_eq!(lengths.expected_length("<loop>"), f64::INFINITY);
+        assert_eq!(lengths.expected_length("<undefined>"), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_length_strategy() {
+        // The lengths of the expansions of the list differ by 2 or 3 bytes, so a window of 2 bytes is always reached
+        for window in [40..=50, 300..=310, 1000..=1001].iter() {
+            let length = Arc::new(LengthStrategy::new(
+                &list_grammar(),
+                window.clone(),
+                100_000,
+            ));
+            let fuzzer = GrammarFuzzer::builder(list_grammar())
+                .then(Arc::clone(&length))
+                .then(CloseStrategy::new())
+                .seed(1)
+                .build();
+            for input in fuzzer.inputs().take(20) {
+                assert!(window.contains(&input.len()), "{}", input.len());
+            }
+            let report = length.report();
+            assert_eq!(report.inputs, 20);
+            assert_eq!(report.hit_rate(), 1.0);
+
+            length.reset();
+            assert_eq!(length.report(), LengthReport::default());
+        }
+    }
+
+    #[test]
+    fn test_length_report() {
+        // the grammar has no input longer than 3 bytes
+        let digits = grammar(&[
+            ("<start>", vec!["<digit>", "<digit><digit><digit>"]),
+            ("<digit>", vec!["0"]),
+        ]);
+        let length = Arc::new(LengthStrategy::new(&digits, 10..=20, 100));
+        let fuzzer = GrammarFuzzer::builder(digits)
+            .then(Arc::clone(&length))
+            .seed(0)
+            .build();
+        for input in fuzzer.inputs().take(5) {
+            assert_eq!(input, "000");
+        }
+        let report = length.report();
+        assert_eq!((report.inputs, report.too_short, report.hits), (5, 5, 0));
+        assert_eq!(report.hit_rate(), 0.0);
+
+        // the rejected subtrees are not inputs and the lengths are those of the rewritten inputs
+        let opts = ExpansionOpts::default().with_post(|text| match text {
+            "0" => Verdict::Reject,
+            _ => Verdict::Replace(text.repeat(10)),
+        });
+        let expansions = vec![
+            ("<start>", vec![Expansion::new("<digit>", Some(opts))]),
+            (
+                "<digit>",
+                vec![Expansion::new("0", None), Expansion::new("1", None)],
+            ),
+        ]
+        .into_iter()
+        .map(|(symbol, alternatives)| (String::from(symbol), alternatives))
+        .collect();
+        let hooked = Grammar::new(expansions);
+        let length = Arc::new(LengthStrategy::new(&hooked, 10..=20, 100));
+        let fuzzer = GrammarFuzzer::builder(hooked)
+            .then(Arc::clone(&length))
+            .seed(0)
+            .build();
+        for input in fuzzer.inputs().take(5) {
+            assert_eq!(input, "1111111111");
+        }
+        assert_eq!(
+            length.report(),
+            LengthReport {
+                inputs: 5,
+                hits: 5,
+                too_short: 0,
+                too_long: 0
+            }
+        );
+    }
+}
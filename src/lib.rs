@@ -5,7 +5,7 @@
 //!
 //! ```
 //! use grammar_fuzzer::extensions::ebnf_to_bnf;
-//! use grammar_fuzzer::{CloseStrategy, GrowthStrategy, RandomStrategy, Strategy};
+//! use grammar_fuzzer::{CloseStrategy, GrowthStrategy, RandomStrategy};
 //! use grammar_fuzzer::{Grammar, GrammarFuzzer, Node};
 //! use std::collections::HashMap;
 //!
@@ -35,39 +35,66 @@
 //! }
 //!
 //! fn main() {
-//!     // Fuzzer
-//!     let expansion = GrowthStrategy::new(0, 1000);
-//!     let random = RandomStrategy::new(40, 8000);
-//!     let close = CloseStrategy::new();
-//!     let strategies: Vec<&dyn Strategy<()>> = vec![&expansion, &random, &close];
-//!
 //!     let ebnf_json_grammar = json_grammar();
 //!     let json_grammar = ebnf_to_bnf(&ebnf_json_grammar);
 //!     assert_eq!(json_grammar.is_valid_grammar(None), true);
 //!
-//!     let fuzzer = GrammarFuzzer::new(json_grammar, &strategies);
+//!     // Fuzzer
+//!     let fuzzer = GrammarFuzzer::builder(json_grammar)
+//!         .then(GrowthStrategy::new(0, 1000))
+//!         .then(RandomStrategy::new(40, 8000))
+//!         .then(CloseStrategy::new())
+//!         .build();
 //!
 //!     // Sample
 //!     for _ in 0..40 {
-//!         let mut node = Node::N(String::from("<start>"));
-//!         fuzzer.expand_tree(&mut node);
+//!         let node: Node = fuzzer.fuzz_tree();
 //!         println!("{}\n", node);
 //!     }
 //! }
 //!
 //! ```
 
+mod json;
 mod parser;
 mod shared;
 
+pub mod attributes;
+pub mod constraints;
+pub mod coverage;
 pub mod derivation_tree;
+pub mod earley;
+pub mod enumeration;
+pub mod error;
 pub mod extensions;
 pub mod fuzzer;
 pub mod grammar;
+pub mod hooks;
+pub mod length;
+pub mod loader;
+pub mod mutation;
+pub mod reduction;
+pub mod runner;
 pub mod strategy;
+pub mod uniform;
 
-pub use derivation_tree::{Children, Node};
-pub use extensions::ebnf_to_bnf;
-pub use fuzzer::GrammarFuzzer;
-pub use grammar::{Alternatives, Expansion, Expansions, Grammar};
-pub use strategy::{CloseStrategy, GrowthStrategy, RandomStrategy, Strategy};
+pub use attributes::{AttributeRules, Attributes, NodeAttributes};
+pub use constraints::{Constraint, DefinedBefore, LengthOf, Violation};
+pub use coverage::{CoverageReport, CoverageStrategy, KPathStrategy};
+pub use derivation_tree::{Children, DerivationTree, Node, NodeId, Path};
+pub use earley::EarleyParser;
+pub use enumeration::{Bound, Enumerator};
+pub use error::{GrammarError, RunnerError};
+pub use extensions::{ebnf_to_bnf, try_ebnf_to_bnf};
+pub use fuzzer::{GrammarFuzzer, Inputs, Trees};
+pub use grammar::{Alternatives, Expansion, ExpansionOpts, Expansions, Grammar};
+pub use hooks::{ExpansionHooks, PostHook, PreHook, Verdict};
+pub use length::{LengthReport, LengthStrategy, YieldLengths};
+pub use loader::{load_grammar, parse_grammar};
+pub use mutation::{Mutation, Mutator};
+pub use reduction::Reducer;
+pub use runner::{InputMode, Outcome, Runner, Target};
+pub use strategy::{
+    CloseStrategy, DepthStrategy, GrowthStrategy, ProbabilisticStrategy, RandomStrategy, Strategy,
+};
+pub use uniform::{Measure, UniformSampler};
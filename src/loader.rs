@@ -0,0 +1,426 @@
+//! Loads grammars from a plain-text BNF/EBNF file format
+//!
+//! - a rule is `<symbol> ::= alternative | alternative`, it may span multiple lines
+//! - an alternative is a sequence of nonterminals and quoted terminals, whitespace between them is ignored
+//! - terminals are quoted with `"` or `'`, the escapes `\n \t \r \\ \" \'` are supported
+//! - the characters `\()*+?` of terminals are escaped in the expansion-strings with EBNF operators,
+//!   `ebnf_to_bnf` reads them as terminals and removes the escapes. It keeps the other expansions as they are
+//! - nonterminals and parenthesized groups can be followed by the EBNF operators `*+?`
+//! - `#` starts a comment that runs until the end of the line
+//! - a symbol that is defined more than once gets the alternatives of every definition
+//!
+//! ## Example
+//!
+//! ```
+//! use grammar_fuzzer::loader::parse_grammar;
+//! use grammar_fuzzer::ebnf_to_bnf;
+//!
+//! let ebnf_grammar = parse_grammar(r#"
+//!     <start>  ::= "[" (<int> ", ")* <int> "]"    # a list of integers
+//!     <int>    ::= <digit>+
+//!     <digit>  ::= "0" | "1" | "2" | "3" | "4"
+//!                | "5" | "6" | "7" | "8" | "9"
+//! "#).unwrap();
+//!
+//! let bnf_grammar = ebnf_to_bnf(&ebnf_grammar);
+//! assert!(bnf_grammar.is_valid_grammar(None));
+//! ```
+
+use super::error::GrammarError;
+use super::grammar::{Expansion, Expansions, Grammar};
+use super::parser;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Parses the rules of a grammar file
+pub fn parse_grammar(input: &str) -> Result<Grammar<()>, GrammarError> {
+    let mut scanner = Scanner::new(input);
+    let mut expansions = Expansions::new();
+    let mut verbatim = HashSet::new();
+    loop {
+        scanner.skip_trivia();
+        if scanner.peek().is_none() {
+            break;
+        }
+        let (symbol, alternatives) = rule(&mut scanner)?;
+        for (alternative, ebnf) in alternatives {
+            if !ebnf {
+                verbatim.insert((symbol.clone(), alternative.clone()));
+            }
+            expansions
+                .entry(symbol.clone())
+                .or_insert_with(Vec::new)
+                .push(Expansion::new(&alternative, None));
+        }
+    }
+    Ok(Grammar::try_new(expansions)?.with_verbatim(verbatim))
+}
+
+/// Reads and parses a grammar file
+pub fn load_grammar<P: AsRef<Path>>(path: P) -> Result<Grammar<()>, GrammarError> {
+    let path = path.as_ref();
+    let input = fs::read_to_string(path)
+        .map_err(|error| GrammarError::Io(format!("{}: {}", path.display(), error)))?;
+    parse_grammar(&input)
+}
+
+impl FromStr for Grammar<()> {
+    type Err = GrammarError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        parse_grammar(input)
+    }
+}
+
+// -------------------------------- Rules -------------------------------------
+
+/// `<symbol> ::= alternative | alternative`
+fn rule(scanner: &mut Scanner) -> Result<(String, Vec<(String, bool)>), GrammarError> {
+    let symbol = nonterminal(scanner)?;
+    scanner.skip_trivia();
+    if !scanner.eat("::=") {
+        return Err(scanner.error("expected `::=`"));
+    }
+    let mut alternatives = vec![sequence(scanner, false)?];
+    while scanner.eat("|") {
+        alternatives.push(sequence(scanner, false)?);
+    }
+    Ok((symbol, alternatives))
+}
+
+/// A sequence of items rendered as an expansion-string and whether it has EBNF operators,
+/// only then its terminals are escaped. It ends before `|`, before the start of the next rule or,
+/// inside a group, before `)`
+fn sequence(scanner: &mut Scanner, in_group: bool) -> Result<(String, bool), GrammarError> {
+    let mut expansion = String::new();
+    let mut raw = String::new();
+    let mut ebnf = false;
+    loop {
+        scanner.skip_trivia();
+        match scanner.peek() {
+            None | Some('|') if !in_group => break,
+            None => return Err(scanner.error("expected `)`")),
+            Some('|') => return Err(scanner.error("alternatives aren't supported inside groups")),
+            Some(')') if in_group => break,
+            Some('<') if !in_group && scanner.at_rule_start() => break,
+            Some('<') => {
+                let symbol = nonterminal(scanner)?;
+                let op = operator(scanner);
+                ebnf |= !op.is_empty();
+                let item = format!("{}{}", symbol, op);
+                expansion.push_str(&item);
+                raw.push_str(&item);
+            }
+            Some('(') => {
+                let start = scanner.position();
+                scanner.bump();
+                let (content, _) = sequence(scanner, true)?;
+                scanner.bump();
+                let op = operator(scanner);
+                if op.is_empty() {
+                    return Err(start.error("expected one of `*+?` after the group"));
+                }
+                if content.is_empty() {
+                    return Err(start.error("empty group"));
+                }
+                expansion.push_str(&format!("({}){}", content, op));
+                ebnf = true;
+            }
+            Some('"') | Some('\'') => {
+                let start = scanner.position();
+                let text = quoted(scanner)?;
+                if text.contains(['<', '>']) {
+                    return Err(start.error("terminals can't contain `<` or `>`"));
+                }
+                if !operator(scanner).is_empty() {
+                    return Err(start.error("EBNF operators only apply to nonterminals and groups"));
+                }
+                expansion.push_str(&parser::escape(&text));
+                raw.push_str(&text);
+            }
+            Some(c) => return Err(scanner.error(&format!("unexpected character {:?}", c))),
+        }
+    }
+    // the content of a group is part of an expansion with EBNF operators
+    if ebnf || in_group {
+        Ok((expansion, true))
+    } else {
+        Ok((raw, false))
+    }
+}
+
+/// `<symbol>`
+fn nonterminal(scanner: &mut Scanner) -> Result<String, GrammarError> {
+    let start = scanner.position();
+    if !scanner.eat("<") {
+        return Err(scanner.error("expected a nonterminal"));
+    }
+    let mut symbol = String::from("<");
+    loop {
+        match scanner.peek() {
+            Some('>') if symbol.len() > 1 => break,
+            Some(c) if !c.is_whitespace() && c != '<' && c != '>' => {
+                symbol.push(c);
+                scanner.bump();
+            }
+            _ => return Err(start.error("malformed nonterminal")),
+        }
+    }
+    scanner.bump();
+    symbol.push('>');
+    Ok(symbol)
+}
+
+/// An optional EBNF operator
+fn operator(scanner: &mut Scanner) -> &'static str {
+    for op in &["*", "+", "?"] {
+        if scanner.eat(op) {
+            return op;
+        }
+    }
+    ""
+}
+
+/// `"terminal"` or `'terminal'`
+fn quoted(scanner: &mut Scanner) -> Result<String, GrammarError> {
+    let start = scanner.position();
+    let quote = scanner.bump().unwrap();
+    let mut text = String::new();
+    loop {
+        match scanner.bump() {
+            None | Some('\n') => return Err(start.error("unterminated terminal")),
+            Some(c) if c == quote => break,
+            Some('\\') => {
+                let escape = scanner.position();
+                match scanner.bump() {
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some('r') => text.push('\r'),
+                    Some(c) if c == '\\' || c == '"' || c == '\'' => text.push(c),
+                    _ => return Err(escape.error("unknown escape sequence")),
+                }
+            }
+            Some(c) => text.push(c),
+        }
+    }
+    Ok(text)
+}
+
+// -------------------------------- Scanner -----------------------------------
+
+/// A line and column in the grammar file, both start at 1
+#[derive(Debug, Clone, Copy)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+impl Position {
+    fn error(self, message: &str) -> GrammarError {
+        GrammarError::Syntax {
+            line: self.line,
+            column: self.column,
+            message: String::from(message),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Scanner<'a> {
+    rest: &'a str,
+    position: Position,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(input: &'a str) -> Self {
+        Scanner {
+            rest: input,
+            position: Position { line: 1, column: 1 },
+        }
+    }
+
+    fn position(&self) -> Position {
+        self.position
+    }
+
+    fn error(&self, message: &str) -> GrammarError {
+        self.position.error(message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest.chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.rest = &self.rest[c.len_utf8()..];
+        if c == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+        Some(c)
+    }
+
+    /// Consumes `token` if the input starts with it
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest.starts_with(token) {
+            token.chars().for_each(|_| {
+                self.bump();
+            });
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Skips whitespace, newlines and comments
+    fn skip_trivia(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '#' {
+                while !matches!(self.peek(), None | Some('\n')) {
+                    self.bump();
+                }
+            } else if c.is_whitespace() {
+                self.bump();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Looks ahead for `<symbol> ::=`
+    fn at_rule_start(&self) -> bool {
+        let mut lookahead = self.clone();
+        if nonterminal(&mut lookahead).is_err() {
+            return false;
+        }
+        lookahead.skip_trivia();
+        lookahead.rest.starts_with("::=")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extensions::{ebnf_to_bnf, try_ebnf_to_bnf};
+    use crate::shared::grammar;
+
+    #[test]
+    fn test_parse_grammar() {
+        let result = parse_grammar(
+            r#"
+            # arithmetic expressions
+            <start> ::= <expr>
+            <expr>  ::= <expr> " + " <term>   # left recursive
+                      | <term>
+            <term>  ::= '(' <expr> ')' | <digit>+
+            <digit> ::= "0" | "1" | ""
+            "#,
+        );
+        let expected = grammar(&[
+            ("<start>", vec!["<expr>"]),
+            ("<expr>", vec!["<expr> + <term>", "<term>"]),
+            ("<term>", vec!["(<expr>)", "<digit>+"]),
+            ("<digit>", vec!["0", "1", ""]),
+        ]);
+
+        assert_eq!(result, Ok(expected));
+        assert!(ebnf_to_bnf(&result.unwrap()).is_valid_grammar(None));
+    }
+
+    #[test]
+    fn test_parse_grammar_groups_and_escapes() {
+        let result: Result<Grammar<()>, _> = r#"
+            <start> ::= "[" (<value> ", ")* <value>? "]\n"
+            <value> ::= "\"a\"" | (('b')+)?
+            "#
+        .parse();
+        let expected = grammar(&[
+            ("<start>", vec!["[(<value>, )*<value>?]\n"]),
+            ("<value>", vec!["\"a\"", "((b)+)?"]),
+        ]);
+
+        assert_eq!(result, Ok(expected));
+        assert!(ebnf_to_bnf(&result.unwrap()).is_valid_grammar(None));
+    }
+
+    #[test]
+    fn test_terminals_survive_ebnf_to_bnf() {
+        let result = parse_grammar(
+            r#"
+            <start> ::= '(' <start> | 'x' | '(a)+' | <digit> "+" <start> | ('?')*
+            <digit> ::= "0" | "\\"
+            "#,
+        );
+        // only the expansion with an EBNF operator is escaped
+        let loaded = grammar(&[
+            (
+                "<start>",
+                vec!["(<start>", "x", "(a)+", "<digit>+<start>", "(\\?)*"],
+            ),
+            ("<digit>", vec!["0", "\\"]),
+        ]);
+        assert_eq!(result, Ok(loaded));
+        let expected = grammar(&[
+            (
+                "<start>",
+                vec!["(<start>", "x", "(a)+", "<digit>+<start>", "<symbol-1>"],
+            ),
+            ("<digit>", vec!["0", "\\"]),
+            ("<symbol>", vec!["?"]),
+            ("<symbol-1>", vec!["", "<symbol><symbol-1>"]),
+        ]);
+
+        assert_eq!(try_ebnf_to_bnf(&result.unwrap()), Ok(expected));
+    }
+
+    #[test]
+    fn test_merges_repeated_definitions() {
+        let result = parse_grammar("<a> ::= 'x'\n<a> ::= 'y' | <a>");
+        assert_eq!(result, Ok(grammar(&[("<a>", vec!["x", "y", "<a>"])])));
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let error = |line, column, message: &str| {
+            Err(GrammarError::Syntax {
+                line,
+                column,
+                message: String::from(message),
+            })
+        };
+
+        assert_eq!(
+            parse_grammar("# comment\n<a> = 'x'"),
+            error(2, 5, "expected `::=`")
+        );
+        assert_eq!(
+            parse_grammar("<a> ::= 'x\n"),
+            error(1, 9, "unterminated terminal")
+        );
+        assert_eq!(
+            parse_grammar("<a> ::=\n  ('x' | 'y')*"),
+            error(2, 8, "alternatives aren't supported inside groups")
+        );
+        assert_eq!(
+            parse_grammar("<a> ::= (<b>)"),
+            error(1, 9, "expected one of `*+?` after the group")
+        );
+        assert_eq!(
+            parse_grammar("<a> ::= 'x'*"),
+            error(1, 9, "EBNF operators only apply to nonterminals and groups")
+        );
+        assert_eq!(
+            parse_grammar("<a> ::= 'a < b'"),
+            error(1, 9, "terminals can't contain `<` or `>`")
+        );
+        assert_eq!(
+            parse_grammar("<a> ::= x"),
+            error(1, 9, "unexpected character 'x'")
+        );
+    }
+}
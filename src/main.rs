@@ -0,0 +1,393 @@
+//! `grammar-fuzzer` generates inputs from a grammar file
+//!
+//! The grammar is read from a `.json` file in the shape of The Fuzzing Book grammars,
+//! or from a text file in the BNF/EBNF format of the `loader` module.
+//! Its EBNF operators are converted to BNF and it's validated before generating any input.
+//!
+//! ```txt
+//! grammar-fuzzer json.ebnf --count 100 --seed 42 --pipeline growth,random,close --output corpus/
+//! ```
+
+use grammar_fuzzer::fuzzer::derive_seed;
+use grammar_fuzzer::{
+    load_grammar, try_ebnf_to_bnf, CloseStrategy, CoverageStrategy, DepthStrategy, Expansion,
+    ExpansionOpts, Expansions, Grammar, GrammarError, GrammarFuzzer, GrowthStrategy, KPathStrategy,
+    ProbabilisticStrategy, RandomStrategy, Strategy,
+};
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "\
+Generates inputs from a grammar file
+
+USAGE:
+    grammar-fuzzer <GRAMMAR> [OPTIONS]
+
+ARGS:
+    <GRAMMAR>    a BNF/EBNF grammar file, or a JSON grammar when it ends with .json
+
+OPTIONS:
+    -s, --start <SYMBOL>          the start symbol [default: <start>]
+    -n, --count <N>               the number of inputs to generate [default: 1]
+        --seed <SEED>             seeds the random choices to make the inputs reproducible
+    -j, --threads <N>             the number of threads that generate inputs, the inputs
+                                  of a seed are the same for any number of threads, coverage
+                                  and kpath need a single thread [default: 1]
+    -p, --pipeline <STRATEGIES>   the comma separated strategies that expand each input in turn,
+                                  any of growth, random, probabilistic, coverage, kpath, depth
+                                  and close [default: growth,random,close]
+        --min-nonterminals <N>    the unexpanded nonterminals that growth aims for [default: 10]
+        --max-nonterminals <N>    the unexpanded nonterminals that random, probabilistic, coverage
+                                  and kpath stop at [default: 100]
+        --max-steps <N>           the expansions after which a strategy stops [default: 1000]
+        --k <K>                   the length of the paths covered by kpath [default: 2]
+        --max-depth <N>           bounds the derivation depth of every input, depth randomly
+                                  expands the inputs up to it
+    -0, --null                    separates the inputs with NUL instead of newlines
+    -o, --output <DIR>            writes each input to its own file in the directory
+    -h, --help                    prints this message
+";
+
+/// Where the generated inputs go
+#[derive(Debug, PartialEq)]
+enum Output {
+    /// Standard output, one input per line
+    Lines,
+    /// Standard output, each input followed by a NUL byte
+    Null,
+    /// A file per input in the directory
+    Directory(PathBuf),
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    grammar: PathBuf,
+    start: String,
+    count: usize,
+    seed: Option<u64>,
+    threads: usize,
+    pipeline: Vec<String>,
+    min_nonterminals: usize,
+    max_nonterminals: usize,
+    max_steps: usize,
+    k: usize,
+    max_depth: Option<usize>,
+    output: Output,
+}
+
+/// Parses the command-line arguments, without the program name,
+/// returns None when the usage was requested
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Option<Options>, String> {
+    let mut grammar = None;
+    let mut options = Options {
+        grammar: PathBuf::new(),
+        start: String::from("<start>"),
+        count: 1,
+        seed: None,
+        threads: 1,
+        pipeline: vec![
+            String::from("growth"),
+            String::from("random"),
+            String::from("close"),
+        ],
+        min_nonterminals: 10,
+        max_nonterminals: 100,
+        max_steps: 1000,
+        k: 2,
+        max_depth: None,
+        output: Output::Lines,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("{} expects a value", arg))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-s" | "--start" => options.start = value()?,
+            "-n" | "--count" => options.count = number(&arg, &value()?)?,
+            "--seed" => options.seed = Some(number(&arg, &value()?)?),
+            "-j" | "--threads" => options.threads = number(&arg, &value()?)?,
+            "-p" | "--pipeline" => {
+                options.pipeline = value()?.split(',').map(String::from).collect()
+            }
+            "--min-nonterminals" => options.min_nonterminals = number(&arg, &value()?)?,
+            "--max-nonterminals" => options.max_nonterminals = number(&arg, &value()?)?,
+            "--max-steps" => options.max_steps = number(&arg, &value()?)?,
+            "--k" => options.k = number(&arg, &value()?)?,
+            "--max-depth" => options.max_depth = Some(number(&arg, &value()?)?),
+            "-0" | "--null" => options.output = Output::Null,
+            "-o" | "--output" => options.output = Output::Directory(PathBuf::from(value()?)),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ if grammar.is_none() => grammar = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    options.grammar = grammar.ok_or("missing the grammar file")?;
+    if options.k == 0 {
+        return Err(String::from("--k must be at least 1"));
+    }
+    // the coverage strategies choose by what the inputs generated before covered,
+    // which depends on how the threads interleave
+    let stateful = options
+        .pipeline
+        .iter()
+        .map(|name| name.trim())
+        .find(|name| ["coverage", "kpath"].contains(name));
+    match stateful {
+        Some(name) if options.threads > 1 => {
+            return Err(format!("the {} strategy needs a single thread", name))
+        }
+        _ => (),
+    }
+    Ok(Some(options))
+}
+
+fn number<N: std::str::FromStr>(option: &str, value: &str) -> Result<N, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a number, got {:?}", option, value))
+}
+
+/// Reads a grammar file, converts it to BNF and validates the part reachable from the start symbol
+fn load(path: &Path, start: &str) -> Result<Grammar<ExpansionOpts>, GrammarError> {
+    let grammar = if path
+        .extension()
+        .map_or(false, |extension| extension == "json")
+    {
+        let input = fs::read_to_string(path)
+            .map_err(|error| GrammarError::Io(format!("{}: {}", path.display(), error)))?;
+        Grammar::from_json(&input)?
+    } else {
+        // the loaded grammar is converted before the options are added, it knows which terminals are escaped
+        let expansions: Expansions<ExpansionOpts> = try_ebnf_to_bnf(&load_grammar(path)?)?
+            .iter()
+            .map(|(symbol, alternatives)| {
+                let alternatives = alternatives
+                    .iter()
+                    .map(|expansion| Expansion::new(&expansion.string, None))
+                    .collect();
+                (symbol.clone(), alternatives)
+            })
+            .collect();
+        Grammar::new(expansions)
+    };
+    let grammar = try_ebnf_to_bnf(&grammar)?;
+    grammar.validate_entry_point(start)?;
+    grammar.validate_probabilities()?;
+    Ok(grammar)
+}
+
+/// Builds the strategies of the pipeline in order
+fn strategies(options: &Options) -> Result<Vec<Box<dyn Strategy<ExpansionOpts>>>, String> {
+    options
+        .pipeline
+        .iter()
+        .map(|name| -> Result<Box<dyn Strategy<ExpansionOpts>>, String> {
+            let max = options.max_nonterminals;
+            let steps = options.max_steps;
+            match name.trim() {
+                "growth" => Ok(Box::new(GrowthStrategy::new(
+                    options.min_nonterminals,
+                    steps,
+                ))),
+                "random" => Ok(Box::new(RandomStrategy::new(max, steps))),
+                "probabilistic" => Ok(Box::new(ProbabilisticStrategy::new(max, steps))),
+                "coverage" => Ok(Box::new(CoverageStrategy::new(max, steps))),
+                "kpath" => Ok(Box::new(KPathStrategy::new(options.k, max, steps))),
+                "depth" => match options.max_depth {
+                    Some(max_depth) => Ok(Box::new(DepthStrategy::new(max_depth, steps))),
+                    None => Err(String::from("the depth strategy expects --max-depth")),
+                },
+                "close" => Ok(Box::new(CloseStrategy::new())),
+                name => Err(format!("unknown strategy {:?}", name)),
+            }
+        })
+        .collect()
+}
+
+/// Generates the inputs and writes them to the output
+fn run(options: &Options) -> Result<(), String> {
+    let grammar = load(&options.grammar, &options.start).map_err(|error| error.to_string())?;
+    let seed = options.seed.unwrap_or_else(|| {
+        // an unseeded run can be reproduced with the seed it reports
+        let seed = rand::random();
+        eprintln!("seed: {}", seed);
+        seed
+    });
+    let mut fuzzer = GrammarFuzzer::with_seed(grammar, strategies(options)?, seed)
+        .with_start_symbol(&options.start);
+    if let Some(max_depth) = options.max_depth {
+        fuzzer = fuzzer.with_max_depth(max_depth);
+    }
+
+    if let Output::Directory(dir) = &options.output {
+        fs::create_dir_all(dir).map_err(|error| format!("{}: {}", dir.display(), error))?;
+    }
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    generate(
+        &fuzzer,
+        options.count,
+        seed,
+        options.threads,
+        |idx, input| match &options.output {
+            Output::Lines => writeln!(out, "{}", input),
+            Output::Null => write!(out, "{}\0", input),
+            Output::Directory(dir) => fs::write(dir.join(format!("{:06}", idx)), input),
+        },
+    )?;
+    out.flush().map_err(|error| error.to_string())
+}
+
+/// The number of inputs generated in parallel before they are written
+const BATCH_SIZE: usize = 10_000;
+
+/// Generates the inputs from the start symbol in batches and passes them to `emit` with their index,
+/// the pipeline has to expand every nonterminal
+fn generate<F>(
+    fuzzer: &GrammarFuzzer<ExpansionOpts>,
+    count: usize,
+    seed: u64,
+    threads: usize,
+    mut emit: F,
+) -> Result<(), String>
+where
+    F: FnMut(usize, String) -> io::Result<()>,
+{
+    for (batch, first) in (0..count).step_by(BATCH_SIZE).enumerate() {
+        let inputs = fuzzer
+            .try_generate_parallel(
+                BATCH_SIZE.min(count - first),
+                derive_seed(seed, batch),
+                threads,
+            )
+            .map_err(|error| match error {
+                GrammarError::NoExpansion(_) => format!("{}, end the pipeline with close", error),
+                error => error.to_string(),
+            })?;
+        for (idx, input) in inputs.into_iter().enumerate() {
+            emit(first + idx, input).map_err(|error| error.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(message) = run(&options) {
+        eprintln!("error: {}", message);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> impl Iterator<Item = String> + '_ {
+        line.split_whitespace().map(String::from)
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(args("json.ebnf -n 5 --seed 42 -p random,close -0"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(options.grammar, PathBuf::from("json.ebnf"));
+        assert_eq!(options.start, "<start>");
+        assert_eq!(options.count, 5);
+        assert_eq!(options.seed, Some(42));
+        assert_eq!(options.pipeline, vec!["random", "close"]);
+        assert_eq!(options.output, Output::Null);
+
+        let options = parse_args(args("json.ebnf --max-depth 5 -p depth,close"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(options.max_depth, Some(5));
+        assert_eq!(strategies(&options).map(|pipeline| pipeline.len()), Ok(2));
+        let options = parse_args(args("json.ebnf -p depth")).unwrap().unwrap();
+        assert!(strategies(&options).is_err());
+
+        assert_eq!(parse_args(args("json.ebnf --help")), Ok(None));
+        assert!(parse_args(args("")).is_err());
+        assert!(parse_args(args("json.ebnf -n")).is_err());
+        assert!(parse_args(args("json.ebnf -n many")).is_err());
+        assert!(parse_args(args("json.ebnf --unknown")).is_err());
+        assert!(parse_args(args("a.ebnf b.ebnf")).is_err());
+        assert!(parse_args(args("json.ebnf -j 4 -p coverage,close")).is_err());
+        assert!(parse_args(args("json.ebnf -j 1 -p kpath,close")).is_ok());
+    }
+
+    #[test]
+    fn test_generate() {
+        let dir = std::env::temp_dir().join(format!("grammar-fuzzer-cli-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("list.ebnf");
+        fs::write(
+            &path,
+            r#"<list> ::= "[" (<digit> ",")* "]"  <digit> ::= "0" | "1""#,
+        )
+        .unwrap();
+
+        let options = parse_args(args(&format!("{} -s <list> --seed 7", path.display())))
+            .unwrap()
+            .unwrap();
+        let grammar = load(&options.grammar, &options.start).unwrap();
+        let fuzzer = GrammarFuzzer::with_seed(grammar, strategies(&options).unwrap(), 7)
+            .with_start_symbol("<list>");
+        let collect = |count, threads| {
+            let mut inputs = Vec::new();
+            generate(&fuzzer, count, 7, threads, |idx, input| {
+                assert_eq!(idx, inputs.len());
+                inputs.push(input);
+                Ok(())
+            })
+            .map(|_| inputs)
+        };
+        let inputs = collect(10, 1).unwrap();
+        assert_eq!(inputs.len(), 10);
+        assert!(inputs
+            .iter()
+            .all(|input| input.starts_with('[') && input.ends_with(']')));
+        assert_eq!(collect(10, 3).unwrap(), inputs);
+
+        let options = parse_args(args(&format!("{} -s <list> -p growth", path.display())))
+            .unwrap()
+            .unwrap();
+        let grammar = load(&path, "<list>").unwrap();
+        let fuzzer =
+            GrammarFuzzer::new(grammar, strategies(&options).unwrap()).with_start_symbol("<list>");
+        assert!(generate(&fuzzer, 1, 7, 1, |_, _| Ok(())).is_err());
+
+        assert!(load(&path, "<start>").is_err());
+
+        // an entry point below the root leaves the rest of the grammar unreachable
+        let options = parse_args(args(&format!("{} -s <digit> --seed 7", path.display())))
+            .unwrap()
+            .unwrap();
+        let grammar = load(&options.grammar, &options.start).unwrap();
+        let fuzzer = GrammarFuzzer::with_seed(grammar, strategies(&options).unwrap(), 7)
+            .with_start_symbol(&options.start);
+        let mut inputs = Vec::new();
+        generate(&fuzzer, 5, 7, 1, |_, input| {
+            inputs.push(input);
+            Ok(())
+        })
+        .unwrap();
+        assert!(inputs.iter().all(|input| input == "0" || input == "1"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
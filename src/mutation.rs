@@ -0,0 +1,494 @@
+//! Mutates existing derivation trees instead of generating them from scratch,
+//! in the spirit of the structural mutations of Nautilus and Grammarinator
+//!
+//! Every operator only replaces subtrees with subtrees of the same nonterminal symbol
+//! that are expanded following the grammar, so the mutated trees stay valid under the grammar.
+//!
+//! ## Example
+//!
+//! ```
+//! use grammar_fuzzer::{CloseStrategy, EarleyParser, Grammar, GrammarFuzzer, Mutator, RandomStrategy};
+//! use std::collections::HashMap;
+//!
+//! let expansions: HashMap<_, _> = [
+//!     ("<start>", vec!["[<items>]"]),
+//!     ("<items>", vec!["<item>,<items>", "<item>"]),
+//!     ("<item>", vec!["a", "b", "c"]),
+//! ]
+//! .iter()
+//! .cloned()
+//! .collect();
+//! let grammar = Grammar::from(&expansions);
+//! let parser = EarleyParser::new(&grammar, "<start>");
+//!
+//! let fuzzer = GrammarFuzzer::builder(Grammar::from(&expansions))
+//!     .then(RandomStrategy::new(5, 20))
+//!     .then(CloseStrategy::new())
+//!     .seed(0)
+//!     .build();
+//! let mutator = Mutator::new(&fuzzer);
+//!
+//! let mut tree = parser.parse("[a,b,c]").unwrap().to_node();
+//! for _ in 0..10 {
+//!     tree = mutator.mutate(&tree, &[]).unwrap();
+//!     assert!(parser.parse(&tree.to_string()).is_ok());
+//! }
+//! ```
+
+use super::derivation_tree::{Children, Node, Path};
+use super::error::GrammarError;
+use super::fuzzer::GrammarFuzzer;
+use super::hooks::ExpansionHooks;
+use super::parser::{self, Token};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+/// The mutation operators
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mutation {
+    /// Replaces a subtree with a new expansion of its nonterminal symbol
+    Regenerate,
+    /// Expands a nonterminal node with another of its alternatives, keeping the children that still fit
+    SwapAlternative,
+    /// Replaces a subtree with a smaller subtree of the same symbol, either a nested subtree or an epsilon expansion,
+    /// ex: removes elements of a repetition or an optional part
+    Delete,
+    /// Repeats the part of a subtree that leads to a nested subtree of the same symbol,
+    /// ex: duplicates an element of a repetition
+    Duplicate,
+    /// Replaces a subtree with a subtree of the same symbol from a donor tree
+    Splice,
+}
+
+const MUTATIONS: [Mutation; 5] = [
+    Mutation::Regenerate,
+    Mutation::SwapAlternative,
+    Mutation::Delete,
+    Mutation::Duplicate,
+    Mutation::Splice,
+];
+
+/// Applies mutation operators to derivation trees, the new subtrees are expanded and
+/// the random choices are made by the fuzzer
+pub struct Mutator<'f, T> {
+    fuzzer: &'f GrammarFuzzer<T>,
+}
+
+impl<'f, T: ExpansionHooks> Mutator<'f, T> {
+    pub fn new(fuzzer: &'f GrammarFuzzer<T>) -> Self {
+        Mutator { fuzzer }
+    }
+
+    /// Applies a random operator that can change the tree, the donors are used by `Mutation::Splice`,
+    /// the constraints of the fuzzer are then satisfied again
+    ///
+    /// The tree is returned unchanged when no operator applies, ex: a tree without nonterminals
+    pub fn mutate(&self, tree: &Node, donors: &[Node]) -> Result<Node, GrammarError> {
+        let mut mutations = MUTATIONS.to_vec();
+        mutations.shuffle(&mut *self.fuzzer.rng());
+        for mutation in mutations {
+            let donor = donors.choose(&mut *self.fuzzer.rng());
+            if let Some(mut mutant) = self.apply(mutation, tree, donor)? {
+                self.fuzzer.try_satisfy_constraints(&mut mutant)?;
+                return Ok(mutant);
+            }
+        }
+        Ok(tree.clone())
+    }
+
+    /// Applies an operator, returns None when the operator can't be applied to the tree
+    pub fn apply(
+        &self,
+        mutation: Mutation,
+        tree: &Node,
+        donor: Option<&Node>,
+    ) -> Result<Option<Node>, GrammarError> {
+        match mutation {
+            Mutation::Regenerate => self.regenerate(tree),
+            Mutation::SwapAlternative => self.swap_alternative(tree),
+            Mutation::Delete => Ok(self.delete(tree)),
+            Mutation::Duplicate => Ok(self.duplicate(tree)),
+            Mutation::Splice => Ok(donor.and_then(|donor| self.splice(tree, donor))),
+        }
+    }
+
+    /// Replaces a random nonterminal subtree with a new expansion of its symbol
+    pub fn regenerate(&self, tree: &Node) -> Result<Option<Node>, GrammarError> {
+        let path = match self.choose(tree.nonterminal_paths()) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let mut subtree = tree
+            .at(&path, |node| Node::new_nonterminal(node.symbol()))
+            .unwrap();
+        self.fuzzer.try_expand_tree_fully(&mut subtree)?;
+        Ok(Some(with_subtree(tree, &path, subtree)))
+    }
+
+    /// Expands a random expanded node with another alternative of its symbol,
+    /// the old children are reused for the nonterminals of the new alternative when their symbols match
+    pub fn swap_alternative(&self, tree: &Node) -> Result<Option<Node>, GrammarError> {
+        let grammar = self.fuzzer.grammar();
+        let candidates: Vec<(Path, Vec<String>)> = tree
+            .nonterminal_paths()
+            .into_iter()
+            .filter_map(|path| {
+                let others = tree.at(&path, |node| {
+                    let current = node.expansion_string()?;
+                    let others: Vec<String> = grammar
+                        .get(node.symbol())?
+                        .iter()
+                        .map(|expansion| expansion.string.clone())
+                        .filter(|expansion| *expansion != current)
+                        .collect();
+                    Some(others)
+                })??;
+                if others.is_empty() {
+                    None
+                } else {
+                    Some((path, others))
+                }
+            })
+            .collect();
+        let (path, others) = match self.choose(candidates) {
+            Some(candidate) => candidate,
+            None => return Ok(None),
+        };
+        let expansion = self.choose(others).unwrap();
+
+        let node = tree.subtree(&path).unwrap();
+        let mut old_children: Vec<Option<Node>> = match &node {
+            Node::EN(_, children) => children
+                .iter()
+                .map(|child| Some(child.borrow().clone()))
+                .collect(),
+            _ => Vec::new(),
+        };
+        let tokens = parser::tokens(&expansion);
+        let children = if tokens.is_empty() {
+            Children::epsilon()
+        } else {
+            let roots = tokens
+                .iter()
+                .map(|token| match token {
+                    Token::Terminal(t) => Node::new_terminal(t),
+                    Token::Nonterminal(t) => old_children
+                        .iter_mut()
+                        .find(
+                            |child| matches!(child, Some(c) if c.symbol() == *t && !is_terminal(c)),
+                        )
+                        .and_then(Option::take)
+                        .unwrap_or_else(|| Node::new_nonterminal(t)),
+                })
+                .map(RefCell::new)
+                .collect();
+            Children { roots }
+        };
+        let mut subtree = Node::new_expanded(node.symbol(), children);
+        self.fuzzer.try_expand_tree_fully(&mut subtree)?;
+        Ok(Some(with_subtree(tree, &path, subtree)))
+    }
+
+    /// Replaces a random subtree with one of its nested subtrees of the same symbol or,
+    /// when the symbol has an epsilon alternative, with an epsilon expansion
+    pub fn delete(&self, tree: &Node) -> Option<Node> {
+        let grammar = self.fuzzer.grammar();
+        let nesting = nesting_nodes(tree);
+        // the nodes that can be replaced with an epsilon expansion, by their index in `visit` order
+        let mut deletable = Vec::new();
+        let mut idx = 0;
+        visit(tree, &mut |_, node| {
+            let has_epsilon = grammar.get(node.symbol()).map_or(false, |alternatives| {
+                alternatives.iter().any(|e| e.string.is_empty())
+            });
+            if has_epsilon && node.expansion_string().map_or(true, |e| !e.is_empty()) {
+                deletable.push(idx);
+            }
+            idx += 1;
+        });
+        let candidate = self.choose((0..nesting.len() + deletable.len()).collect())?;
+        if candidate < nesting.len() {
+            let (outer, inner) = self.nested_pair(tree, nesting[candidate]);
+            return Some(with_subtree(tree, &outer, tree.subtree(&inner).unwrap()));
+        }
+        let path = path_at(tree, deletable[candidate - nesting.len()]);
+        let epsilon = tree
+            .at(&path, |node| {
+                Node::new_expanded(node.symbol(), Children::epsilon())
+            })
+            .unwrap();
+        Some(with_subtree(tree, &path, epsilon))
+    }
+
+    /// Picks a subtree with a nested subtree of the same symbol and replaces the nested subtree
+    /// with a copy of the outer one, repeating the nodes between them
+    pub fn duplicate(&self, tree: &Node) -> Option<Node> {
+        let (outer, inner) = self.nested_pair(tree, self.choose(nesting_nodes(tree))?);
+        let outer_subtree = tree.subtree(&outer).unwrap();
+        let mut repeated = outer_subtree.clone();
+        repeated.replace_subtree(&inner[outer.len()..], outer_subtree);
+        Some(with_subtree(tree, &outer, repeated))
+    }
+
+    /// Replaces a random subtree with a subtree of the same symbol from the donor
+    pub fn splice(&self, tree: &Node, donor: &Node) -> Option<Node> {
+        let donor_paths = donor.nonterminal_paths();
+        let candidates: Vec<(Path, &Path)> = tree
+            .nonterminal_paths()
+            .into_iter()
+            .flat_map(|path| {
+                let matching: Vec<&Path> = tree
+                    .at(&path, |node| {
+                        donor_paths
+                            .iter()
+                            .filter(|donor_path| {
+                                donor.at(donor_path, |d| d.symbol() == node.symbol() && d != node)
+                                    == Some(true)
+                            })
+                            .collect()
+                    })
+                    .unwrap();
+                matching
+                    .into_iter()
+                    .map(move |donor_path| (path.clone(), donor_path))
+            })
+            .collect();
+        let (path, donor_path) = self.choose(candidates)?;
+        Some(with_subtree(
+            tree,
+            &path,
+            donor.subtree(donor_path).unwrap(),
+        ))
+    }
+
+    /// The paths of the node at an index in `visit` order and of a random nested node of the same symbol,
+    /// the index has to be one of `nesting_nodes`
+    fn nested_pair(&self, tree: &Node, outer: usize) -> (Path, Path) {
+        let outer = path_at(tree, outer);
+        let inner = tree
+            .at(&outer, |node| {
+                let mut rng = self.fuzzer.rng();
+                // picks one of the nested nodes uniformly without collecting them
+                let mut seen = 0;
+                let mut inner = None;
+                visit(node, &mut |path, nested| {
+                    if !path.is_empty() && nested.symbol() == node.symbol() {
+                        seen += 1;
+                        if rng.gen_range(0, seen) == 0 {
+                            inner = Some(path.to_vec());
+                        }
+                    }
+                });
+                inner
+            })
+            .flatten()
+            .expect("the outer node has a nested node of its symbol");
+        let inner = outer.iter().chain(inner.iter()).cloned().collect();
+        (outer, inner)
+    }
+
+    fn choose<E>(&self, mut candidates: Vec<E>) -> Option<E> {
+        if candidates.is_empty() {
+            return None;
+        }
+        let idx = self.fuzzer.rng().gen_range(0, candidates.len());
+        Some(candidates.swap_remove(idx))
+    }
+}
+
+/// A copy of the tree with the subtree at the path replaced
+fn with_subtree(tree: &Node, path: &[usize], subtree: Node) -> Node {
+    let mut tree = tree.clone();
+    tree.replace_subtree(path, subtree);
+    tree
+}
+
+fn is_terminal(node: &Node) -> bool {
+    matches!(node, Node::T(_))
+}
+
+/// Calls `f` with the path and the node of every Node::N and Node::EN in a tree, parents before their children
+fn visit<F: FnMut(&[usize], &Node)>(tree: &Node, f: &mut F) {
+    fn walk<F: FnMut(&[usize], &Node)>(node: &Node, path: &mut Path, f: &mut F) {
+        match node {
+            Node::T(_) => (),
+            Node::N(_) => f(path, node),
+            Node::EN(_, children) => {
+                f(path, node);
+                for (idx, child) in children.iter().enumerate() {
+                    path.push(idx);
+                    walk(&child.borrow(), path, f);
+                    path.pop();
+                }
+            }
+        }
+    }
+
+    walk(tree, &mut Vec::new(), f)
+}
+
+/// The path of the node at an index in `visit` order
+fn path_at(tree: &Node, index: usize) -> Path {
+    let mut idx = 0;
+    let mut found = Vec::new();
+    visit(tree, &mut |path, _| {
+        if idx == index {
+            found = path.to_vec();
+        }
+        idx += 1;
+    });
+    found
+}
+
+/// The indices in `visit` order of the nodes with a nested node of the same symbol,
+/// found in one walk that merges the symbols below every node
+fn nesting_nodes(tree: &Node) -> Vec<usize> {
+    fn walk(node: &Node, idx: &mut usize, nesting: &mut Vec<usize>) -> HashSet<String> {
+        match node {
+            Node::T(_) => HashSet::new(),
+            Node::N(symbol) => {
+                *idx += 1;
+                std::iter::once(symbol.clone()).collect()
+            }
+            Node::EN(symbol, children) => {
+                let own = *idx;
+                *idx += 1;
+                let mut below = HashSet::new();
+                for child in children.iter() {
+                    let mut symbols = walk(&child.borrow(), idx, nesting);
+                    if symbols.len() > below.len() {
+                        std::mem::swap(&mut symbols, &mut below);
+                    }
+                    below.extend(symbols);
+                }
+                if below.contains(symbol) {
+                    nesting.push(own);
+                }
+                below.insert(symbol.clone());
+                below
+            }
+        }
+    }
+
+    let mut nesting = Vec::new();
+    walk(tree, &mut 0, &mut nesting);
+    nesting
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::earley::EarleyParser;
+    use crate::grammar::Grammar;
+    use crate::strategy::{CloseStrategy, RandomStrategy};
+    use std::collections::HashMap;
+
+    fn list_grammar() -> Grammar<()> {
+        let expansions: HashMap<_, _> = [
+            ("<start>", vec!["[<items>]"]),
+            ("<items>", vec!["<item>,<items>", "<item>", ""]),
+            ("<item>", vec!["a", "b", "(<items>)"]),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        Grammar::from(&expansions)
+    }
+
+    fn check_valid(parser: &EarleyParser, tree: &Node) {
+        assert!(!tree.any_possible_expansions());
+        assert!(parser.parse(&tree.to_string()).is_ok(), "{}", tree);
+    }
+
+    #[test]
+    fn test_mutations_stay_valid() {
+        let fuzzer = GrammarFuzzer::builder(list_grammar())
+            .then(RandomStrategy::new(5, 20))
+            .then(CloseStrategy::new())
+            .seed(0)
+            .build();
+        let mutator = Mutator::new(&fuzzer);
+        let grammar = list_grammar();
+        let parser = EarleyParser::new(&grammar, "<start>");
+        let donor = parser.parse("[(a,b),b]").unwrap().to_node();
+
+        for mutation in MUTATIONS.iter() {
+            let mut tree = parser.parse("[a,(b,a),b]").unwrap().to_node();
+            for _ in 0..8 {
+                if let Some(mutant) = mutator.apply(*mutation, &tree, Some(&donor)).unwrap() {
+                    check_valid(&parser, &mutant);
+                    tree = mutant;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_delete_and_duplicate() {
+        let fuzzer = GrammarFuzzer::with_seed(list_grammar(), Vec::new(), 1);
+        let mutator = Mutator::new(&fuzzer);
+        let grammar = list_grammar();
+        let parser = EarleyParser::new(&grammar, "<start>");
+        let tree = parser.parse("[a,b]").unwrap().to_node();
+
+        for _ in 0..10 {
+            let deleted = mutator.delete(&tree).unwrap();
+            assert!(["[a]", "[b]", "[]", "[a,]"].contains(&deleted.to_string().as_str()));
+            let duplicated = mutator.duplicate(&tree).unwrap();
+            assert_eq!(duplicated.to_string(), "[a,a,b]");
+        }
+        assert_eq!(
+            mutator.duplicate(&parser.parse("[a]").unwrap().to_node()),
+            None
+        );
+
+        // every <items> of a long list is nested in the ones before it
+        let input = format!("[{}a]", "a,".repeat(999));
+        let tree = parser.parse(&input).unwrap().to_node();
+        let deleted = mutator.delete(&tree).unwrap().to_string();
+        assert!(deleted.len() < input.len());
+        assert!(parser.parse(&deleted).is_ok());
+        let duplicated = mutator.duplicate(&tree).unwrap().to_string();
+        assert!(duplicated.len() > input.len());
+        assert!(parser.parse(&duplicated).is_ok());
+    }
+
+    #[test]
+    fn test_splice_and_swap_alternative() {
+        let fuzzer = GrammarFuzzer::builder(list_grammar())
+            .then(CloseStrategy::new())
+            .seed(2)
+            .build();
+        let mutator = Mutator::new(&fuzzer);
+        let grammar = list_grammar();
+        let parser = EarleyParser::new(&grammar, "<start>");
+        let tree = parser.parse("[a]").unwrap().to_node();
+        let donor = parser.parse("[b]").unwrap().to_node();
+
+        // [a] and [b] only differ in <item>, the other subtrees of the donor are equal to the ones they'd replace
+        let spliced = mutator.splice(&tree, &donor).unwrap();
+        assert_eq!(spliced.to_string(), "[b]");
+
+        for _ in 0..10 {
+            let mutant = mutator.swap_alternative(&tree).unwrap().unwrap();
+            check_valid(&parser, &mutant);
+            assert_ne!(mutant, tree);
+        }
+    }
+
+    #[test]
+    fn test_unexpanded_mutants_are_errors() {
+        let fuzzer = GrammarFuzzer::with_seed(list_grammar(), Vec::new(), 0);
+        let mutator = Mutator::new(&fuzzer);
+        let tree = Node::new_expanded("<start>", Children::from("[<items>]"));
+
+        // the only nonterminals are the unexpanded <items> and the <start> that contains it
+        let error = mutator.regenerate(&tree).unwrap_err();
+        assert!(
+            error == GrammarError::NoExpansion(String::from("<start>"))
+                || error == GrammarError::NoExpansion(String::from("<items>"))
+        );
+    }
+}
@@ -1,7 +1,10 @@
 use nom::{
     branch::alt,
-    bytes::complete::{is_a, tag, take, take_while1},
-    multi::{many0, many_till},
+    bytes::complete::{is_a, is_not, tag, take, take_while1},
+    character::complete::{char, one_of},
+    combinator::recognize,
+    multi::{many0, many1, many_till},
+    sequence::pair,
     IResult,
 };
 
@@ -33,16 +36,71 @@ fn token(input: &str) -> IResult<&str, Token> {
 
 /// Returns a sequence of terminal an nonterminal tokens
 pub fn tokens(input: &str) -> Vec<Token> {
+    match try_tokens(input) {
+        Ok(tokens) => tokens,
+        Err(position) => panic!("malformed token at position {} of {:?}", position, input),
+    }
+}
+
+/// Returns a sequence of terminal an nonterminal tokens,
+/// or the position of the first character that isn't part of a token
+pub fn try_tokens(input: &str) -> Result<Vec<Token<'_>>, usize> {
     // it should consume the whole input
-    let (input, tokens) = many0(token)(input).unwrap();
-    assert_eq!(input.is_empty(), true);
-    tokens
+    match many0(token)(input) {
+        Ok(("", tokens)) => Ok(tokens),
+        Ok((rest, _)) => Err(input.len() - rest.len()),
+        Err(_) => Err(0),
+    }
+}
+
+// -------------------------------- Escapes -----------------------------------
+
+/// The characters that a backslash escapes in EBNF expansions, so they are read as terminals
+const ESCAPABLE: &str = "\\()*+?";
+
+/// Escapes the characters that EBNF would read as groups or operators
+pub fn escape(terminal: &str) -> String {
+    let mut escaped = String::with_capacity(terminal.len());
+    for c in terminal.chars() {
+        if ESCAPABLE.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Removes the escapes, a backslash that doesn't escape anything is kept
+pub fn unescape(expansion: &str) -> String {
+    let mut unescaped = String::with_capacity(expansion.len());
+    let mut chars = expansion.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(next) if c == '\\' && ESCAPABLE.contains(*next) => {
+                unescaped.push(*next);
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+fn escaped_char(input: &str) -> IResult<&str, &str> {
+    recognize(pair(char('\\'), one_of(ESCAPABLE)))(input)
+}
+
+/// A single character, or an escaped character with its backslash
+fn any_char(input: &str) -> IResult<&str, &str> {
+    alt((escaped_char, take(1usize)))(input)
 }
 
 // ----------------------------- Expressions ----------------------------------
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct ParenthesizedExpression<'a> {
+    /// The byte offset of the expression in the input string
+    pub position: usize,
     pub token: &'a str,
     pub op: &'a str,
     pub content: &'a str,
@@ -50,11 +108,13 @@ pub struct ParenthesizedExpression<'a> {
 
 fn parenthesized_expression(input: &str) -> IResult<&str, ParenthesizedExpression> {
     let (_input, _) = tag("(")(input)?;
-    let (_input, content) = take_while1(|c| !"()".contains(c))(_input)?;
+    let (_input, content) =
+        recognize(many1(alt((escaped_char, is_not("()\\"), tag("\\")))))(_input)?;
     let (_input, _) = tag(")")(_input)?;
     let (_input, op) = is_a("+*?")(_input)?;
     let len = '('.len_utf8() + content.len() + ')'.len_utf8() + op.len();
     let parenthesized_expression = ParenthesizedExpression {
+        position: 0,
         token: &input[..len],
         op,
         content,
@@ -63,14 +123,55 @@ fn parenthesized_expression(input: &str) -> IResult<&str, ParenthesizedExpressio
     Ok((_input, parenthesized_expression))
 }
 
-/// Returns the next paranthesized expression in the input string
+/// Returns the next paranthesized expression in the input string, escaped parentheses are skipped
 pub fn next_parenthesized_expression(input: &str) -> Option<ParenthesizedExpression> {
-    match many_till(take(1usize), parenthesized_expression)(input) {
-        Ok((_, (_, pe))) => Some(pe),
+    match many_till(any_char, parenthesized_expression)(input) {
+        Ok((_, (skipped, pe))) => Some(ParenthesizedExpression {
+            position: skipped.iter().map(|s| s.len()).sum(),
+            ..pe
+        }),
         Err(_) => None,
     }
 }
 
+/// Returns the position of the first parenthesis of an operator group that is never closed or never opened,
+/// escaped parentheses are skipped and the other parentheses are terminals, ex: a lone `(`
+///
+/// A `)` followed by one of `*+?` closes a group, an unclosed `(` opens one when an operator after it
+/// follows neither a nonterminal nor a group, as if the `)` before the operator was missing
+pub fn unbalanced_parenthesis(input: &str) -> Option<usize> {
+    let mut open = Vec::new();
+    let mut last_stray_operator = None;
+    let mut escaped = false;
+    let mut previous = None;
+    let mut chars = input.char_indices().peekable();
+    while let Some((position, c)) = chars.next() {
+        if escaped {
+            escaped = false;
+            previous = None;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '(' => open.push(position),
+            ')' => {
+                let closes_group = matches!(chars.peek(), Some((_, '*' | '+' | '?')));
+                if open.pop().is_none() && closes_group {
+                    return Some(position);
+                }
+            }
+            '*' | '+' | '?' if !matches!(previous, Some('>' | ')' | '*' | '+' | '?')) => {
+                last_stray_operator = Some(position)
+            }
+            _ => (),
+        }
+        previous = Some(c);
+    }
+    let last_stray_operator = last_stray_operator?;
+    open.into_iter()
+        .find(|position| *position < last_stray_operator)
+}
+
 // -------------------------------- Extended ----------------------------------
 
 #[derive(Debug, PartialEq, Eq)]
@@ -97,7 +198,7 @@ fn extended_nonterminal(input: &str) -> IResult<&str, ExtendedNonterminal> {
 
 /// Returns the next extended nonterminal
 pub fn next_extended_nonterminal(input: &str) -> Option<ExtendedNonterminal> {
-    match many_till(take(1usize), extended_nonterminal)(input) {
+    match many_till(any_char, extended_nonterminal)(input) {
         Ok((_, (_, en))) => Some(en),
         Err(_) => None,
     }
@@ -119,10 +220,44 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_try_tokens() {
+        assert_eq!(
+            try_tokens("<digit>"),
+            Ok(vec![Token::Nonterminal("<digit>")])
+        );
+        assert_eq!(try_tokens("1 < <digit>"), Err(2));
+    }
+
+    #[test]
+    fn test_unbalanced_parenthesis() {
+        assert_eq!(unbalanced_parenthesis("(<value>, )*<value>"), None);
+        assert_eq!(unbalanced_parenthesis("[(<value>, *<value>]"), Some(1));
+        assert_eq!(unbalanced_parenthesis("<value>)*"), Some(7));
+        assert_eq!(unbalanced_parenthesis("\\(<value>"), None);
+        // parentheses without operators are terminals
+        assert_eq!(unbalanced_parenthesis("("), None);
+        assert_eq!(unbalanced_parenthesis("f(<value>"), None);
+        assert_eq!(unbalanced_parenthesis("<value>) (<value>)?"), None);
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(escape("(a)+\\n"), "\\(a\\)\\+\\\\n");
+        assert_eq!(unescape(&escape("(a)+\\n")), "(a)+\\n");
+        assert_eq!(unescape("\\n"), "\\n");
+        assert_eq!(
+            next_parenthesized_expression("\\(a\\)+ (\\(<a>)?").map(|pe| (pe.position, pe.content)),
+            Some((7, "\\(<a>"))
+        );
+        assert_eq!(next_extended_nonterminal("<a>\\+"), None);
+    }
+
     #[test]
     fn test_next_parenthesized_expression() {
         let result = next_parenthesized_expression("[(<value>, )*<value>]");
         let expected = Some(ParenthesizedExpression {
+            position: 1,
             token: "(<value>, )*",
             op: "*",
             content: "<value>, ",
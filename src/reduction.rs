@@ -0,0 +1,272 @@
+//! Shrinks a failing input by reducing its derivation tree, following the
+//! hierarchical delta debugging (HDD) approach of Misherghi and Su
+//!
+//! The tree is traversed level by level from the root. Every nonterminal subtree is replaced
+//! with a smaller subtree of the same symbol: the minimal-cost expansion of the symbol,
+//! one of its nested subtrees of the same symbol, ex: the rest of a repetition,
+//! or an expansion with another alternative that keeps the children that still fit, ex: a repetition without its last element.
+//! A replacement is kept when the predicate still holds for the resulting input,
+//! and the traversal is repeated until no replacement is kept.
+//! Since subtrees are only replaced by subtrees of the same symbol, every candidate input is in the grammar.
+//!
+//! ## Example
+//!
+//! ```
+//! use grammar_fuzzer::{EarleyParser, Grammar, Reducer};
+//! use std::collections::HashMap;
+//!
+//! let expansions: HashMap<_, _> = [
+//!     ("<start>", vec!["[<items>]"]),
+//!     ("<items>", vec!["<item>,<items>", "<item>", ""]),
+//!     ("<item>", vec!["a", "b", "(<items>)"]),
+//! ]
+//! .iter()
+//! .cloned()
+//! .collect();
+//! let grammar = Grammar::from(&expansions);
+//! let parser = EarleyParser::new(&grammar, "<start>");
+//!
+//! // The "target" fails on nested lists
+//! let tree = parser.parse("[a,((b),a),b]").unwrap().to_node();
+//! let reducer = Reducer::new(&grammar);
+//! let reduced = reducer.reduce(&tree, |input| input.contains("(("));
+//! assert_eq!(reduced.to_string(), "[(())]");
+//! ```
+
+use super::derivation_tree::{Children, Node, Path};
+use super::grammar::Grammar;
+use super::parser::{self, Token};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+/// Reduces derivation trees while a predicate holds for their inputs
+pub struct Reducer<'a, T> {
+    grammar: &'a Grammar<T>,
+    /// The minimal-cost tree of every symbol that can be turned into terminals
+    minimal: HashMap<String, Node>,
+}
+
+impl<'a, T> Reducer<'a, T> {
+    pub fn new(grammar: &'a Grammar<T>) -> Self {
+        let mut minimal = HashMap::new();
+        for symbol in grammar.keys() {
+            if let Some(tree) = minimal_tree(grammar, symbol) {
+                minimal.insert(symbol.clone(), tree);
+            }
+        }
+        Reducer { grammar, minimal }
+    }
+
+    /// Returns the smallest tree found for which the predicate holds,
+    /// trees are compared by the length of their input and then by their number of nodes
+    ///
+    /// The predicate is called once per distinct candidate input, it should return true when the input
+    /// still triggers the failure. When it doesn't hold for the tree, the tree is returned unchanged.
+    pub fn reduce<P>(&self, tree: &Node, mut predicate: P) -> Node
+    where
+        P: FnMut(&str) -> bool,
+    {
+        let mut best = tree.clone();
+        let mut best_size = size(&best);
+        let mut tested = HashSet::new();
+        tested.insert(best.to_string());
+        if !predicate(&best.to_string()) {
+            return best;
+        }
+
+        let mut progress = true;
+        while progress {
+            progress = false;
+            let mut paths = level_order(&best);
+            let mut idx = 0;
+            while idx < paths.len() {
+                for candidate in self.candidates(&best, &paths[idx]) {
+                    let input = candidate.to_string();
+                    let candidate_size = (input.len(), candidate.num_nodes());
+                    if candidate_size < best_size
+                        && tested.insert(input.clone())
+                        && predicate(&input)
+                    {
+                        best = candidate;
+                        best_size = candidate_size;
+                        paths = level_order(&best);
+                        progress = true;
+                        break;
+                    }
+                }
+                idx += 1;
+            }
+        }
+        best
+    }
+
+    /// The trees with the subtree at the path replaced by a smaller subtree of the same symbol, smallest first
+    fn candidates(&self, tree: &Node, path: &[usize]) -> Vec<Node> {
+        let node = tree.subtree(path).unwrap();
+        let mut replacements: Vec<Node> = node
+            .nonterminal_paths()
+            .iter()
+            .filter(|nested| !nested.is_empty())
+            .map(|nested| node.subtree(nested).unwrap())
+            .filter(|nested| nested.symbol() == node.symbol())
+            .collect();
+        if let Some(minimal) = self.minimal.get(node.symbol()) {
+            replacements.push(minimal.clone());
+        }
+        if let (Node::EN(symbol, children), Some(alternatives)) =
+            (&node, self.grammar.get(node.symbol()))
+        {
+            replacements.extend(
+                alternatives
+                    .iter()
+                    .filter_map(|expansion| self.reexpand(symbol, children, &expansion.string)),
+            );
+        }
+
+        let mut candidates: Vec<Node> = replacements
+            .into_iter()
+            .filter(|replacement| *replacement != node)
+            .map(|replacement| {
+                let mut candidate = tree.clone();
+                candidate.replace_subtree(path, replacement);
+                candidate
+            })
+            .collect();
+        candidates.sort_by_key(size);
+        candidates
+    }
+
+    /// Expands the symbol with the expansion-string, the old children are reused for the nonterminals
+    /// when their symbols match and the other nonterminals get their minimal-cost trees
+    fn reexpand(&self, symbol: &str, children: &Children, expansion: &str) -> Option<Node> {
+        let mut old_children: Vec<Option<Node>> = children
+            .iter()
+            .map(|child| match &*child.borrow() {
+                Node::T(_) => None,
+                child => Some(child.clone()),
+            })
+            .collect();
+        let tokens = parser::tokens(expansion);
+        if tokens.is_empty() {
+            return Some(Node::new_expanded(symbol, Children::epsilon()));
+        }
+        let mut roots = Vec::new();
+        for token in tokens {
+            let child = match token {
+                Token::Terminal(t) => Node::new_terminal(t),
+                Token::Nonterminal(t) => match old_children
+                    .iter_mut()
+                    .find(|child| matches!(child, Some(c) if c.symbol() == t))
+                {
+                    Some(child) => child.take().unwrap(),
+                    None => self.minimal.get(t)?.clone(),
+                },
+            };
+            roots.push(RefCell::new(child));
+        }
+        Some(Node::new_expanded(symbol, Children { roots }))
+    }
+}
+
+/// The length of the input and the number of nodes of a tree
+fn size(tree: &Node) -> (usize, usize) {
+    (tree.to_string().len(), tree.num_nodes())
+}
+
+/// The paths of the nonterminal nodes, the nodes closer to the root first
+fn level_order(tree: &Node) -> Vec<Path> {
+    let mut paths = tree.nonterminal_paths();
+    paths.sort_by_key(|path| path.len());
+    paths
+}
+
+/// Expands the symbol with the first alternative that minimizes the cost, like the `CloseStrategy`
+/// but without random tie-breaking so that reductions are reproducible,
+/// None when the symbol can't be turned into terminals
+fn minimal_tree<T>(grammar: &Grammar<T>, symbol: &str) -> Option<Node> {
+    let alternatives = grammar.get(symbol)?;
+    let costs = grammar.expansion_costs(symbol);
+    let (idx, cost) = costs.iter().enumerate().fold(
+        None,
+        |min: Option<(usize, f64)>, (idx, cost)| match min {
+            Some((_, min_cost)) if min_cost <= *cost => min,
+            _ => Some((idx, *cost)),
+        },
+    )?;
+    if cost.is_infinite() {
+        return None;
+    }
+
+    // The nonterminals of a minimal-cost expansion have smaller costs, so the recursion ends
+    let mut children = Children::from(alternatives[idx].string.as_str());
+    for child in children.roots.iter_mut() {
+        let child = child.get_mut();
+        if let Node::N(nonterminal) = child {
+            *child = minimal_tree(grammar, nonterminal)?;
+        }
+    }
+    Some(Node::new_expanded(symbol, children))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::earley::EarleyParser;
+
+    fn expr_grammar() -> Grammar<()> {
+        let expansions: HashMap<_, _> = [
+            ("<start>", vec!["<expr>"]),
+            ("<expr>", vec!["<term>+<expr>", "<term>-<expr>", "<term>"]),
+            (
+                "<term>",
+                vec!["<factor>*<term>", "<factor>/<term>", "<factor>"],
+            ),
+            ("<factor>", vec!["(<expr>)", "<digit>"]),
+            ("<digit>", vec!["0", "1", "2", "3"]),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        Grammar::from(&expansions)
+    }
+
+    #[test]
+    fn test_minimal_tree() {
+        let grammar = expr_grammar();
+        let tree = minimal_tree(&grammar, "<start>").unwrap();
+        assert_eq!(tree.to_string(), "0");
+        assert!(!tree.any_possible_expansions());
+        assert_eq!(minimal_tree(&grammar, "<undefined>"), None);
+    }
+
+    #[test]
+    fn test_reduce() {
+        let grammar = expr_grammar();
+        let parser = EarleyParser::new(&grammar, "<start>");
+        let tree = parser.parse("(1+2)*3-(2/(3-1+0))*1").unwrap().to_node();
+        let reducer = Reducer::new(&grammar);
+
+        let reduced = reducer.reduce(&tree, |input| input.contains("/("));
+        assert_eq!(reduced.to_string(), "2/(0)");
+        assert!(parser.parse(&reduced.to_string()).is_ok());
+
+        let reduced = reducer.reduce(&tree, |input| input.contains('2'));
+        assert_eq!(reduced.to_string(), "2");
+    }
+
+    #[test]
+    fn test_reduce_without_failure() {
+        let grammar = expr_grammar();
+        let parser = EarleyParser::new(&grammar, "<start>");
+        let tree = parser.parse("1+2").unwrap().to_node();
+        let reducer = Reducer::new(&grammar);
+        let mut calls = 0;
+
+        let reduced = reducer.reduce(&tree, |_| {
+            calls += 1;
+            false
+        });
+        assert_eq!(reduced, tree);
+        assert_eq!(calls, 1);
+    }
+}
@@ -0,0 +1,509 @@
+//! Runs a target program on the generated inputs and keeps the inputs that crash it
+//!
+//! The input is passed to the program through its standard input, a temporary file
+//! whose path replaces the `@@` arguments, or an environment variable.
+//! Each execution is classified as a pass, a crash (the program was killed by a signal),
+//! a timeout or a non-zero exit.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use grammar_fuzzer::{CloseStrategy, Grammar, GrammarFuzzer, RandomStrategy};
+//! use grammar_fuzzer::{InputMode, Runner, Target};
+//! use std::collections::HashMap;
+//! use std::time::Duration;
+//!
+//! let expansions: HashMap<_, _> = [
+//!     ("<start>", vec!["<int>"]),
+//!     ("<int>", vec!["<digit><int>", "<digit>"]),
+//!     ("<digit>", vec!["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"]),
+//! ]
+//! .iter()
+//! .cloned()
+//! .collect();
+//! let fuzzer = GrammarFuzzer::builder(Grammar::from(&expansions))
+//!     .then(RandomStrategy::new(20, 100))
+//!     .then(CloseStrategy::new())
+//!     .build();
+//!
+//! let target = Target::new("./parse-int", &["--file", "@@"])
+//!     .input(InputMode::File)
+//!     .timeout(Duration::from_millis(500));
+//! let runner = Runner::new(&fuzzer, target).save_crashes("crashes");
+//! let report = runner.run(0..1000).unwrap();
+//! println!("{} crashes in {} executions", report.crashes.len(), report.executions);
+//! ```
+
+use super::derivation_tree::Node;
+use super::error::RunnerError;
+use super::fuzzer::GrammarFuzzer;
+use super::hooks::ExpansionHooks;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How the input is passed to the target program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputMode {
+    /// Written to the standard input of the program
+    Stdin,
+    /// Written to a temporary file, its path replaces every `@@` argument
+    /// or is appended to the arguments when there is none
+    File,
+    /// Set as the value of the environment variable
+    Env(String),
+}
+
+/// The result of running the target program on an input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The program exited with status 0
+    Pass,
+    /// The program was terminated by the signal
+    Crash(i32),
+    /// The program didn't exit within the timeout and was killed
+    Timeout,
+    /// The program exited with the non-zero status
+    NonZeroExit(i32),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Pass => write!(f, "pass"),
+            Outcome::Crash(signal) => write!(f, "crash (signal {})", signal),
+            Outcome::Timeout => write!(f, "timeout"),
+            Outcome::NonZeroExit(code) => write!(f, "non-zero exit ({})", code),
+        }
+    }
+}
+
+/// An execution of the target program
+#[derive(Debug, Clone)]
+pub struct Execution {
+    pub outcome: Outcome,
+    /// Everything the program wrote to its standard error
+    pub stderr: Vec<u8>,
+    pub duration: Duration,
+}
+
+/// A program and the way to pass it an input
+#[derive(Debug, Clone)]
+pub struct Target {
+    program: PathBuf,
+    args: Vec<String>,
+    mode: InputMode,
+    timeout: Duration,
+}
+
+/// Numbers the temporary input files of the process
+static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+impl Target {
+    /// A program that reads the input from its standard input and is killed after 1 second
+    pub fn new<P: AsRef<Path>>(program: P, args: &[&str]) -> Self {
+        Target {
+            program: program.as_ref().to_path_buf(),
+            args: args.iter().map(|arg| String::from(*arg)).collect(),
+            mode: InputMode::Stdin,
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// Changes how the input is passed to the program
+    pub fn input(mut self, mode: InputMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Changes how long the program may run before it's killed
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Runs the program on the input and waits until it exits or the timeout passes
+    pub fn run(&self, input: &[u8]) -> io::Result<Execution> {
+        let mut command = Command::new(&self.program);
+        command.stdout(Stdio::null()).stderr(Stdio::piped());
+        let mut file = None;
+        match &self.mode {
+            InputMode::Stdin => {
+                command.args(&self.args).stdin(Stdio::piped());
+            }
+            InputMode::File => {
+                let path = std::env::temp_dir().join(format!(
+                    "grammar-fuzzer-{}-{}",
+                    std::process::id(),
+                    NEXT_FILE.fetch_add(1, Ordering::Relaxed)
+                ));
+                fs::write(&path, input)?;
+                let path_arg = path.to_string_lossy().into_owned();
+                if self.args.iter().any(|arg| arg == "@@") {
+                    command.args(self.args.iter().map(|arg| {
+                        if arg == "@@" {
+                            path_arg.as_str()
+                        } else {
+                            arg.as_str()
+                        }
+                    }));
+                } else {
+                    command.args(&self.args).arg(&path_arg);
+                }
+                command.stdin(Stdio::null());
+                file = Some(path);
+            }
+            InputMode::Env(variable) => {
+                let value = String::from_utf8_lossy(input).into_owned();
+                command
+                    .args(&self.args)
+                    .env(variable, value)
+                    .stdin(Stdio::null());
+            }
+        }
+
+        let result = self.execute(&mut command, input);
+        if let Some(path) = file {
+            fs::remove_file(path)?;
+        }
+        result
+    }
+
+    fn execute(&self, command: &mut Command, input: &[u8]) -> io::Result<Execution> {
+        let started = Instant::now();
+        process_group(command);
+        let mut child = command.spawn()?;
+
+        // The input and the errors go through threads, so that a program
+        // that doesn't read its input or writes a lot of errors doesn't block
+        let writer = child.stdin.take().map(|mut stdin| {
+            let input = input.to_vec();
+            thread::spawn(move || {
+                // the program may exit without reading the whole input
+                let _ = stdin.write_all(&input);
+            })
+        });
+        let mut stderr = child.stderr.take().unwrap();
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let reader = {
+            let output = Arc::clone(&output);
+            thread::spawn(move || {
+                let mut buffer = [0; 4096];
+                while let Ok(read) = stderr.read(&mut buffer) {
+                    if read == 0 {
+                        break;
+                    }
+                    output.lock().unwrap().extend_from_slice(&buffer[..read]);
+                }
+            })
+        };
+
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break Some(status);
+            }
+            if started.elapsed() >= self.timeout {
+                // the processes started by the program are killed with it, so they don't hold the pipes open
+                kill_process_group(&mut child)?;
+                child.wait()?;
+                break None;
+            }
+            thread::sleep(Duration::from_millis(1));
+        };
+        let duration = started.elapsed();
+        if let Some(writer) = writer {
+            let _ = writer.join();
+        }
+        let _ = reader.join();
+        let stderr = output.lock().unwrap().clone();
+
+        Ok(Execution {
+            outcome: match status {
+                Some(status) => outcome(status),
+                None => Outcome::Timeout,
+            },
+            stderr,
+            duration,
+        })
+    }
+}
+
+/// Starts the program in a new process group, so that a timeout kills the processes it started
+#[cfg(unix)]
+fn process_group(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    // setpgid is async-signal-safe, so it can run between the fork and the exec
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setpgid(0, 0) == 0 {
+                Ok(())
+            } else {
+                Err(io::Error::last_os_error())
+            }
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn process_group(_command: &mut Command) {}
+
+/// Kills the process group that the program leads
+#[cfg(unix)]
+fn kill_process_group(child: &mut Child) -> io::Result<()> {
+    // a negative pid signals every process of the group
+    if unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) } == 0 {
+        Ok(())
+    } else {
+        child.kill()
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_process_group(child: &mut Child) -> io::Result<()> {
+    child.kill()
+}
+
+#[cfg(unix)]
+fn outcome(status: ExitStatus) -> Outcome {
+    use std::os::unix::process::ExitStatusExt;
+    match (status.code(), status.signal()) {
+        (Some(0), _) => Outcome::Pass,
+        (_, Some(signal)) => Outcome::Crash(signal),
+        (code, None) => Outcome::NonZeroExit(code.unwrap_or(-1)),
+    }
+}
+
+#[cfg(not(unix))]
+fn outcome(status: ExitStatus) -> Outcome {
+    match status.code() {
+        Some(0) => Outcome::Pass,
+        code => Outcome::NonZeroExit(code.unwrap_or(-1)),
+    }
+}
+
+/// An input that crashed the target program
+#[derive(Debug, Clone)]
+pub struct Crash {
+    /// The seed of the fuzzer that reproduces the input
+    pub seed: u64,
+    pub tree: Node,
+    pub execution: Execution,
+}
+
+/// The outcomes of a run
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub executions: usize,
+    pub passes: usize,
+    pub timeouts: usize,
+    pub non_zero_exits: usize,
+    pub crashes: Vec<Crash>,
+}
+
+/// Generates inputs with a fuzzer and runs the target program on each of them
+pub struct Runner<'f, T> {
+    fuzzer: &'f GrammarFuzzer<T>,
+    target: Target,
+    crashes_dir: Option<PathBuf>,
+}
+
+impl<'f, T: ExpansionHooks> Runner<'f, T> {
+    pub fn new(fuzzer: &'f GrammarFuzzer<T>, target: Target) -> Self {
+        Runner {
+            fuzzer,
+            target,
+            crashes_dir: None,
+        }
+    }
+
+    /// Saves every crash in the directory as `crash-<seed>` with the input,
+    /// `crash-<seed>.tree` with the seed, the outcome and the derivation tree,
+    /// and `crash-<seed>.stderr` with the errors of the program
+    pub fn save_crashes<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.crashes_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Generates an input from the start symbol of the fuzzer for every seed, by reseeding the fuzzer,
+    /// and runs the target program on it
+    pub fn run(&self, seeds: Range<u64>) -> Result<Report, RunnerError> {
+        let mut report = Report::default();
+        for seed in seeds {
+            self.fuzzer.reseed(seed);
+            let tree = self.fuzzer.try_fuzz_tree()?;
+            let input = tree.to_string();
+            let execution = self
+                .target
+                .run(input.as_bytes())
+                .map_err(|error| io_error(&self.target.program, error))?;
+
+            report.executions += 1;
+            match execution.outcome {
+                Outcome::Pass => report.passes += 1,
+                Outcome::Timeout => report.timeouts += 1,
+                Outcome::NonZeroExit(_) => report.non_zero_exits += 1,
+                Outcome::Crash(_) => {
+                    let crash = Crash {
+                        seed,
+                        tree,
+                        execution,
+                    };
+                    if let Some(dir) = &self.crashes_dir {
+                        save_crash(dir, &crash).map_err(|error| io_error(dir, error))?;
+                    }
+                    report.crashes.push(crash);
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Keeps the path of the program or the directory in the message of the error
+fn io_error(path: &Path, error: io::Error) -> RunnerError {
+    let message = format!("{}: {}", path.display(), error);
+    RunnerError::Io(io::Error::new(error.kind(), message))
+}
+
+fn save_crash(dir: &Path, crash: &Crash) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let name = format!("crash-{}", crash.seed);
+    fs::write(dir.join(&name), crash.tree.to_string())?;
+    fs::write(
+        dir.join(format!("{}.stderr", name)),
+        &crash.execution.stderr,
+    )?;
+
+    let mut tree = format!(
+        "# seed: {}\n# outcome: {}\n",
+        crash.seed, crash.execution.outcome
+    );
+    write_tree(&crash.tree, 0, &mut tree);
+    fs::write(dir.join(format!("{}.tree", name)), tree)
+}
+
+/// Writes a node per line, the children indented below their parent and the terminals quoted
+fn write_tree(node: &Node, indent: usize, out: &mut String) {
+    out.push_str(&"  ".repeat(indent));
+    match node {
+        Node::T(terminal) => out.push_str(&format!("{:?}\n", terminal)),
+        Node::N(symbol) => out.push_str(&format!("{}\n", symbol)),
+        Node::EN(symbol, children) => {
+            out.push_str(&format!("{}\n", symbol));
+            for child in children.iter() {
+                write_tree(&child.borrow(), indent + 1, out);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::grammar::Grammar;
+    use crate::strategy::{CloseStrategy, RandomStrategy};
+    use std::collections::HashMap;
+
+    fn shell(script: &str) -> Target {
+        Target::new("sh", &["-c", script, "sh", "@@"])
+    }
+
+    #[test]
+    fn test_outcomes() {
+        let target = shell("read input; test \"$input\" = pass");
+        assert_eq!(target.run(b"pass\n").unwrap().outcome, Outcome::Pass);
+        assert_eq!(
+            target.run(b"fail\n").unwrap().outcome,
+            Outcome::NonZeroExit(1)
+        );
+
+        let target = shell("echo oops >&2; kill -SEGV $$");
+        let execution = target.run(b"").unwrap();
+        assert_eq!(execution.outcome, Outcome::Crash(11));
+        assert_eq!(execution.stderr, b"oops\n");
+
+        let target = shell("sleep 5").timeout(Duration::from_millis(50));
+        let execution = target.run(b"").unwrap();
+        assert_eq!(execution.outcome, Outcome::Timeout);
+        assert!(execution.duration < Duration::from_secs(5));
+
+        // a process started by the program keeps the pipes open until it's killed with the program
+        let target =
+            shell("echo partial >&2; sleep 5 & sleep 5").timeout(Duration::from_millis(50));
+        let started = Instant::now();
+        let execution = target.run(b"").unwrap();
+        assert_eq!(execution.outcome, Outcome::Timeout);
+        assert_eq!(execution.stderr, b"partial\n");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_input_modes() {
+        let target = shell("grep -q abc \"$1\"").input(InputMode::File);
+        assert_eq!(target.run(b"xabcx").unwrap().outcome, Outcome::Pass);
+        assert_eq!(target.run(b"xyz").unwrap().outcome, Outcome::NonZeroExit(1));
+
+        let target = shell("test \"$INPUT\" = abc").input(InputMode::Env(String::from("INPUT")));
+        assert_eq!(target.run(b"abc").unwrap().outcome, Outcome::Pass);
+        assert_eq!(target.run(b"xyz").unwrap().outcome, Outcome::NonZeroExit(1));
+
+        assert!(Target::new("/nonexistent/program", &[]).run(b"").is_err());
+    }
+
+    #[test]
+    fn test_runner_saves_crashes() {
+        let expansions: HashMap<_, _> = [
+            ("<start>", vec!["<digit><start>", "<digit>"]),
+            ("<digit>", vec!["0", "1", "2"]),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let fuzzer = GrammarFuzzer::builder(Grammar::from(&expansions))
+            .then(RandomStrategy::new(5, 10))
+            .then(CloseStrategy::new())
+            .build();
+        let dir =
+            std::env::temp_dir().join(format!("grammar-fuzzer-crashes-{}", std::process::id()));
+
+        // crashes on the inputs with a 2
+        let target = shell("read input; case $input in *2*) kill -ABRT $$;; esac");
+        let runner = Runner::new(&fuzzer, target).save_crashes(&dir);
+        let report = runner.run(0..20).unwrap();
+        assert_eq!(report.executions, 20);
+        assert_eq!(report.passes + report.crashes.len(), 20);
+        assert!(!report.crashes.is_empty());
+
+        for crash in report.crashes.iter() {
+            let input = crash.tree.to_string();
+            assert!(input.contains('2'));
+            let name = format!("crash-{}", crash.seed);
+            assert_eq!(fs::read_to_string(dir.join(&name)).unwrap(), input);
+            let tree = fs::read_to_string(dir.join(format!("{}.tree", name))).unwrap();
+            assert!(tree.starts_with(&format!(
+                "# seed: {}\n# outcome: crash (signal 6)\n<start>\n",
+                crash.seed
+            )));
+
+            // the seed reproduces the input
+            fuzzer.reseed(crash.seed);
+            assert_eq!(fuzzer.fuzz_tree(), crash.tree);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+
+        let runner = Runner::new(&fuzzer, Target::new("/nonexistent/program", &[]));
+        match runner.run(0..1) {
+            Err(RunnerError::Io(error)) => {
+                assert!(error.to_string().starts_with("/nonexistent/program: "))
+            }
+            result => panic!("{:?}", result.map(|report| report.executions)),
+        }
+    }
+}
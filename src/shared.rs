@@ -1,4 +1,5 @@
 use rand::seq::SliceRandom;
+use rand::Rng;
 use std::collections::HashSet;
 
 /// Returns a new set that includes the element
@@ -9,7 +10,7 @@ pub fn add_to_set<'a>(s: &HashSet<&'a str>, element: &'a str) -> HashSet<&'a str
 }
 
 /// Selects randomly from the set of indicies of min value elements
-pub fn min_idx(vs: &Vec<f64>) -> usize {
+pub fn min_idx<R: Rng + ?Sized>(vs: &[f64], rng: &mut R) -> usize {
     assert_eq!(vs.is_empty(), false);
     let min = vs
         .iter()
@@ -20,11 +21,11 @@ pub fn min_idx(vs: &Vec<f64>) -> usize {
         .filter(|(_, v)| **v == min)
         .map(|(i, _)| i)
         .collect();
-    *random_element(&min_idxs, |_| true).unwrap()
+    *random_element(&min_idxs, |_| true, rng).unwrap()
 }
 
 /// Selects randomly from the set of indicies of max value elements
-pub fn max_idx(vs: &Vec<f64>) -> usize {
+pub fn max_idx<R: Rng + ?Sized>(vs: &[f64], rng: &mut R) -> usize {
     assert_eq!(vs.is_empty(), false);
     let max = vs
         .iter()
@@ -35,13 +36,14 @@ pub fn max_idx(vs: &Vec<f64>) -> usize {
         .filter(|(_, v)| **v == max)
         .map(|(i, _)| i)
         .collect();
-    *random_element(&max_idxs, |_| true).unwrap()
+    *random_element(&max_idxs, |_| true, rng).unwrap()
 }
 
 /// Selects a random element satisfying the predicate
-pub fn random_element<T, F>(vs: &Vec<T>, p: F) -> Option<&T>
+pub fn random_element<'a, T, F, R>(vs: &'a [T], p: F, rng: &mut R) -> Option<&'a T>
 where
     F: Fn(&T) -> bool,
+    R: Rng + ?Sized,
 {
     let idxs: Vec<usize> = vs
         .iter()
@@ -52,14 +54,22 @@ where
     // Found out about the SliceRandom trait (https://docs.rs/rand/0.7.3/rand/seq/trait.SliceRandom.html#tymethod.choose) from:
     // https://stackoverflow.com/questions/34215280/how-can-i-randomly-select-one-element-from-a-vector-or-array
     // https://stackoverflow.com/a/42272866
-    let mut rng = rand::thread_rng();
-    let idx = idxs.choose(&mut rng)?;
+    let idx = idxs.choose(rng)?;
     Some(&vs[*idx])
 }
 
+/// A grammar without expansion options from its rules, shared by the tests
+#[cfg(test)]
+pub fn grammar(rules: &[(&str, Vec<&str>)]) -> super::grammar::Grammar<()> {
+    let expansions: std::collections::HashMap<&str, Vec<&str>> = rules.iter().cloned().collect();
+    super::grammar::Grammar::from(&expansions)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
 
     #[test]
     fn test_add_to_set() {
@@ -74,7 +84,8 @@ mod tests {
 
     #[test]
     fn test_min_idx() {
-        let result = min_idx(&vec![1.0, 2.0, 3.0, 4.0, 1.0]);
+        let mut rng = StdRng::seed_from_u64(0);
+        let result = min_idx(&vec![1.0, 2.0, 3.0, 4.0, 1.0], &mut rng);
         let expected_idx = vec![0, 4];
 
         assert_eq!(expected_idx.contains(&result), true);
@@ -82,7 +93,8 @@ mod tests {
 
     #[test]
     fn test_max_idx() {
-        let result = max_idx(&vec![1.0, 2.0, 3.0, 4.0, 4.0]);
+        let mut rng = StdRng::seed_from_u64(0);
+        let result = max_idx(&vec![1.0, 2.0, 3.0, 4.0, 4.0], &mut rng);
         let expected_idx = vec![3, 4];
 
         assert_eq!(expected_idx.contains(&result), true);
@@ -90,10 +102,20 @@ mod tests {
 
     #[test]
     fn test_random_element() {
+        let mut rng = StdRng::seed_from_u64(0);
         let input = vec![1, 2, 3, 4, 5];
-        let result = random_element(&input, |e| *e > 3);
+        let result = random_element(&input, |e| *e > 3, &mut rng);
         let expected_values = vec![Some(&4), Some(&5)];
 
         assert_eq!(expected_values.contains(&result), true);
     }
+
+    #[test]
+    fn test_random_element_is_reproducible() {
+        let input: Vec<usize> = (0..100).collect();
+        let pick =
+            |seed| *random_element(&input, |_| true, &mut StdRng::seed_from_u64(seed)).unwrap();
+
+        assert_eq!(pick(42), pick(42));
+    }
 }
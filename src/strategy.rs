@@ -9,23 +9,96 @@
 //! let expansion = GrowthStrategy::new(0, 1000);
 //! let random = RandomStrategy::new(40, 8000);
 //! let close = CloseStrategy::new();
-//! let strategies: Vec<&dyn Strategy<()>> = vec![&expansion, &random, &close];
+//! let strategies: Vec<Box<dyn Strategy<()>>> =
+//!     vec![Box::new(expansion), Box::new(random), Box::new(close)];
 //! ```
 //!
-use super::derivation_tree::Node;
-use super::grammar::{Alternatives, Grammar};
+use super::derivation_tree::{DerivationTree, Node, NodeId};
+use super::grammar::{ExpansionOpts, Grammar};
 use super::shared::{max_idx, min_idx};
-use rand::Rng;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+use std::sync::Arc;
 
 /// Selects an expansion-string based on the alternatives defined in the grammar for the nonterminal symbol
-pub trait Strategy<T> {
+///
+/// Strategies are shared by the threads of `GrammarFuzzer::generate_parallel`,
+/// a strategy that keeps state has to synchronize it, ex: with a `Mutex`
+pub trait Strategy<T>: Send + Sync {
     /// Defines wheather to continue expanding the derivation tree following the current strategy
-    /// dt_root: is the root node of the derivation-tree
+    /// tree: is the derivation-tree being expanded
     /// num_steps: is how many times the derivation tree was expanded following the current strategy
-    fn cont(&self, dt_root: &Node, num_steps: usize) -> bool;
+    fn cont(&self, tree: &DerivationTree, num_steps: usize) -> bool;
+
+    /// Selects an expansion-string for an unexpanded nonterminal leaf of the derivation tree
+    /// rng: is the source of randomness, seeding it makes the choice reproducible
+    fn choose(
+        &self,
+        grammar: &Grammar<T>,
+        tree: &DerivationTree,
+        leaf: NodeId,
+        rng: &mut dyn RngCore,
+    ) -> Option<String>;
+
+    /// Called on every strategy of the fuzzer after a leaf of the derivation tree was expanded,
+    /// by any of the strategies, so that a strategy can keep track of the generated trees
+    /// node: is the expanded node, expansion: is the chosen expansion-string
+    fn expanded(&self, _tree: &DerivationTree, _node: NodeId, _expansion: &str) {}
+
+    /// Called on every strategy of the fuzzer with each tree it generates, once the hooks have been applied
+    /// and the constraints hold, unlike `expanded` it's not called for the subtrees that are regenerated
+    fn generated(&self, _tree: &Node) {}
+}
+
+/// A boxed strategy, ex: a strategy of a pipeline that is chosen at runtime
+impl<T, S: Strategy<T> + ?Sized> Strategy<T> for Box<S> {
+    fn cont(&self, tree: &DerivationTree, num_steps: usize) -> bool {
+        (**self).cont(tree, num_steps)
+    }
+
+    fn choose(
+        &self,
+        grammar: &Grammar<T>,
+        tree: &DerivationTree,
+        leaf: NodeId,
+        rng: &mut dyn RngCore,
+    ) -> Option<String> {
+        (**self).choose(grammar, tree, leaf, rng)
+    }
+
+    fn expanded(&self, tree: &DerivationTree, node: NodeId, expansion: &str) {
+        (**self).expanded(tree, node, expansion)
+    }
+
+    fn generated(&self, tree: &Node) {
+        (**self).generated(tree)
+    }
+}
 
-    /// Selects an expansion-string for a given nonterminal node
-    fn choose(&self, grammar: &Grammar<T>, node: &Node) -> Option<String>;
+/// A strategy shared with the fuzzer that owns it, ex: to read the report of a `CoverageStrategy`
+impl<T, S: Strategy<T> + ?Sized> Strategy<T> for Arc<S> {
+    fn cont(&self, tree: &DerivationTree, num_steps: usize) -> bool {
+        (**self).cont(tree, num_steps)
+    }
+
+    fn choose(
+        &self,
+        grammar: &Grammar<T>,
+        tree: &DerivationTree,
+        leaf: NodeId,
+        rng: &mut dyn RngCore,
+    ) -> Option<String> {
+        (**self).choose(grammar, tree, leaf, rng)
+    }
+
+    fn expanded(&self, tree: &DerivationTree, node: NodeId, expansion: &str) {
+        (**self).expanded(tree, node, expansion)
+    }
+
+    fn generated(&self, tree: &Node) {
+        (**self).generated(tree)
+    }
 }
 
 // -------------------------------- Random ------------------------------------
@@ -47,21 +120,74 @@ impl RandomStrategy {
 
 impl<T> Strategy<T> for RandomStrategy {
     /// Continue until reaching the expected number of nonterminal nodes or passing the expansions limit
-    fn cont(&self, dt_root: &Node, num_steps: usize) -> bool {
-        dt_root.num_possible_expansions() < self.nonterminals_threshold
-            && num_steps < self.max_steps
+    fn cont(&self, tree: &DerivationTree, num_steps: usize) -> bool {
+        tree.num_possible_expansions() < self.nonterminals_threshold && num_steps < self.max_steps
     }
 
     /// Choose a random expansion
-    fn choose(&self, grammar: &Grammar<T>, node: &Node) -> Option<String> {
-        match node {
-            Node::N(symbol) => {
+    fn choose(
+        &self,
+        grammar: &Grammar<T>,
+        tree: &DerivationTree,
+        leaf: NodeId,
+        rng: &mut dyn RngCore,
+    ) -> Option<String> {
+        match tree.open_symbol(leaf) {
+            Some(symbol) => {
                 let expansions = &grammar[symbol];
-                let rand_idx = rand::thread_rng().gen_range(0, expansions.len());
+                let rand_idx = rng.gen_range(0, expansions.len());
                 let choosen_expansion = expansions[rand_idx].string.clone();
                 Some(choosen_expansion)
             }
-            _ => None,
+            None => None,
+        }
+    }
+}
+
+// ----------------------------- Probabilistic --------------------------------
+
+/// Picks an expansion according to the `prob` of the expansion options,
+/// expansions without a `prob` share the probability left by the others
+pub struct ProbabilisticStrategy {
+    nonterminals_threshold: usize,
+    max_steps: usize,
+}
+
+impl ProbabilisticStrategy {
+    pub fn new(nonterminals_threshold: usize, max_steps: usize) -> Self {
+        ProbabilisticStrategy {
+            nonterminals_threshold,
+            max_steps,
+        }
+    }
+}
+
+impl Strategy<ExpansionOpts> for ProbabilisticStrategy {
+    /// Continue until reaching the expected number of nonterminal nodes or passing the expansions limit
+    fn cont(&self, tree: &DerivationTree, num_steps: usize) -> bool {
+        tree.num_possible_expansions() < self.nonterminals_threshold && num_steps < self.max_steps
+    }
+
+    /// Choose an expansion following the expansion probabilities
+    fn choose(
+        &self,
+        grammar: &Grammar<ExpansionOpts>,
+        tree: &DerivationTree,
+        leaf: NodeId,
+        rng: &mut dyn RngCore,
+    ) -> Option<String> {
+        match tree.open_symbol(leaf) {
+            Some(symbol) => {
+                let expansions = &grammar[symbol];
+                let probabilities = grammar.expansion_probabilities(symbol);
+                let idx = match WeightedIndex::new(&probabilities) {
+                    Ok(distribution) => distribution.sample(rng),
+                    // every probability is 0, ex: the others already sum to 1
+                    Err(_) => rng.gen_range(0, expansions.len()),
+                };
+                Some(expansions[idx].string.clone())
+            }
+            None => None,
         }
     }
 }
@@ -85,22 +211,26 @@ impl GrowthStrategy {
 
 impl<T> Strategy<T> for GrowthStrategy {
     /// Continue until reaching the expected number of nonterminal nodes or passing the expansions limit
-    fn cont(&self, dt_root: &Node, num_steps: usize) -> bool {
-        dt_root.num_possible_expansions() < self.nonterminals_threshold
-            && num_steps < self.max_steps
+    fn cont(&self, tree: &DerivationTree, num_steps: usize) -> bool {
+        tree.num_possible_expansions() < self.nonterminals_threshold && num_steps < self.max_steps
     }
 
     /// Choose an expansion that maximizes the cost
-    fn choose(&self, grammar: &Grammar<T>, node: &Node) -> Option<String> {
-        match node {
-            Node::N(symbol) => {
+    fn choose(
+        &self,
+        grammar: &Grammar<T>,
+        tree: &DerivationTree,
+        leaf: NodeId,
+        rng: &mut dyn RngCore,
+    ) -> Option<String> {
+        match tree.open_symbol(leaf) {
+            Some(symbol) => {
                 let expansions = &grammar[symbol];
-                let costs = costs(grammar, symbol, expansions);
-                let max_idx = max_idx(&costs);
+                let max_idx = max_idx(grammar.acyclic_expansion_costs(symbol), rng);
                 let choosen_expansion = expansions[max_idx].string.clone();
                 Some(choosen_expansion)
             }
-            _ => None,
+            None => None,
         }
     }
 }
@@ -118,32 +248,98 @@ impl CloseStrategy {
 
 impl<T> Strategy<T> for CloseStrategy {
     /// Continue until all the nodes have been expanded
-    fn cont(&self, _dt_root: &Node, _num_steps: usize) -> bool {
+    fn cont(&self, _tree: &DerivationTree, _num_steps: usize) -> bool {
         true
     }
 
     /// Choose an expansion that minimizes the cost
-    fn choose(&self, grammar: &Grammar<T>, node: &Node) -> Option<String> {
-        match node {
-            Node::N(symbol) => {
+    fn choose(
+        &self,
+        grammar: &Grammar<T>,
+        tree: &DerivationTree,
+        leaf: NodeId,
+        rng: &mut dyn RngCore,
+    ) -> Option<String> {
+        match tree.open_symbol(leaf) {
+            Some(symbol) => {
                 let expansions = &grammar[symbol];
-                let costs = costs(grammar, symbol, expansions);
-                let min_idx = min_idx(&costs);
+                let min_idx = min_idx(grammar.acyclic_expansion_costs(symbol), rng);
                 let choosen_expansion = expansions[min_idx].string.clone();
                 Some(choosen_expansion)
             }
-            _ => None,
+            None => None,
         }
     }
 }
 
-// ---------------------------------- Helpers ---------------------------------
+// -------------------------------- Depth -------------------------------------
 
-fn costs<T>(grammar: &Grammar<T>, sym: &str, expansions: &Alternatives<T>) -> Vec<f64> {
-    let seen = [sym].iter().cloned().collect();
-    expansions
+/// Randomly picks from the expansions whose minimum-depth completion keeps the derivation tree
+/// within `max_depth`, the root is at depth 0 and a terminal child of the root at depth 1
+///
+/// Far from the limit every expansion fits, so it chooses like the `RandomStrategy`,
+/// closer to the limit it only chooses the expansions that can still be closed in time.
+/// A strategy that follows it can still go deeper, `GrammarFuzzerBuilder::max_depth` bounds the whole pipeline.
+pub struct DepthStrategy {
+    max_depth: usize,
+    max_steps: usize,
+}
+
+impl DepthStrategy {
+    pub fn new(max_depth: usize, max_steps: usize) -> Self {
+        DepthStrategy {
+            max_depth,
+            max_steps,
+        }
+    }
+}
+
+impl<T> Strategy<T> for DepthStrategy {
+    /// Continue until passing the expansions limit, the depth limit already bounds the size of the tree
+    fn cont(&self, _tree: &DerivationTree, num_steps: usize) -> bool {
+        num_steps < self.max_steps
+    }
+
+    /// Choose a random expansion that fits within the depth left,
+    /// or the shallowest expansion when the leaf is already too deep for any of them
+    fn choose(
+        &self,
+        grammar: &Grammar<T>,
+        tree: &DerivationTree,
+        leaf: NodeId,
+        rng: &mut dyn RngCore,
+    ) -> Option<String> {
+        match tree.open_symbol(leaf) {
+            Some(symbol) => {
+                let expansions = &grammar[symbol];
+                let idx = match expansions_within_depth(grammar, tree, leaf, self.max_depth)
+                    .choose(rng)
+                {
+                    Some(idx) => *idx,
+                    None => min_idx(grammar.expansion_depths(symbol), rng),
+                };
+                Some(expansions[idx].string.clone())
+            }
+            None => None,
+        }
+    }
+}
+
+/// The indices of the alternative expansions of a leaf whose minimum-depth completion
+/// keeps the derivation tree within `max_depth`
+pub(crate) fn expansions_within_depth<T>(
+    grammar: &Grammar<T>,
+    tree: &DerivationTree,
+    leaf: NodeId,
+    max_depth: usize,
+) -> Vec<usize> {
+    let depth = tree.depth(leaf) as f64;
+    grammar
+        .expansion_depths(tree.symbol(leaf))
         .iter()
-        .map(|expansion| grammar.expansion_cost(expansion, &seen))
+        .enumerate()
+        .filter(|(_, expansion_depth)| depth + **expansion_depth <= max_depth as f64)
+        .map(|(idx, _)| idx)
         .collect()
 }
 
@@ -151,7 +347,7 @@ fn costs<T>(grammar: &Grammar<T>, sym: &str, expansions: &Alternatives<T>) -> Ve
 mod strategy_cont_tests {
     use super::*;
     use crate::derivation_tree::{
-        Children,
+        Children, Node,
         Node::{EN, N},
     };
     use std::cell::RefCell;
@@ -177,8 +373,10 @@ mod strategy_cont_tests {
         let nonterminals_threshold = 10;
         let max_steps = 10;
         let below_max_steps = max_steps - 1;
-        let below_nonterminal_threshold = sample_derivation_tree(nonterminals_threshold - 1);
-        let at_nonterminal_threshold = sample_derivation_tree(nonterminals_threshold);
+        let below_nonterminal_threshold =
+            DerivationTree::from(&sample_derivation_tree(nonterminals_threshold - 1));
+        let at_nonterminal_threshold =
+            DerivationTree::from(&sample_derivation_tree(nonterminals_threshold));
         let strategy: &dyn Strategy<()> = &RandomStrategy::new(nonterminals_threshold, max_steps);
 
         assert_eq!(
@@ -200,8 +398,10 @@ mod strategy_cont_tests {
         let nonterminals_threshold = 10;
         let max_steps = 10;
         let below_max_steps = max_steps - 1;
-        let below_nonterminal_threshold = sample_derivation_tree(nonterminals_threshold - 1);
-        let at_nonterminal_threshold = sample_derivation_tree(nonterminals_threshold);
+        let below_nonterminal_threshold =
+            DerivationTree::from(&sample_derivation_tree(nonterminals_threshold - 1));
+        let at_nonterminal_threshold =
+            DerivationTree::from(&sample_derivation_tree(nonterminals_threshold));
         let strategy: &dyn Strategy<()> = &GrowthStrategy::new(nonterminals_threshold, max_steps);
 
         assert_eq!(
@@ -220,7 +420,7 @@ mod strategy_cont_tests {
 
     #[test]
     fn test_close_strategy_cont() {
-        let ten_nonterminal_nodes = sample_derivation_tree(10);
+        let ten_nonterminal_nodes = DerivationTree::from(&sample_derivation_tree(10));
         let strategy: &dyn Strategy<()> = &CloseStrategy::new();
 
         assert_eq!(strategy.cont(&ten_nonterminal_nodes, 9), true);
@@ -230,7 +430,8 @@ mod strategy_cont_tests {
 #[cfg(test)]
 mod strategy_choose_tests {
     use super::*;
-    use crate::derivation_tree::Node;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
     use std::collections::HashMap;
 
     fn sample_grammar() -> Grammar<()> {
@@ -254,38 +455,121 @@ mod strategy_choose_tests {
         let max_steps = 10;
         let strategy: &dyn Strategy<()> = &GrowthStrategy::new(nonterminals_threshold, max_steps);
         let grammar = sample_grammar();
-        let derivation_tree = Node::N(String::from("<int>"));
+        let derivation_tree = DerivationTree::new("<int>");
         let expected_expansions = vec![
             Some(String::from("<int><digit>")),
             Some(String::from("<digit>")),
         ];
         assert_eq!(
-            expected_expansions.contains(&strategy.choose(&grammar, &derivation_tree)),
+            expected_expansions.contains(&strategy.choose(
+                &grammar,
+                &derivation_tree,
+                derivation_tree.root(),
+                &mut StdRng::seed_from_u64(0)
+            )),
             true
         );
     }
 
+    #[test]
+    fn test_probabilistic_strategy_choose() {
+        let strategy = ProbabilisticStrategy::new(10, 10);
+        let grammar: Grammar<ExpansionOpts> =
+            Grammar::from_json(r#"{"<digit>": [["0", {"prob": 0.8}], "1", ["2", {"prob": 0.2}]]}"#)
+                .unwrap();
+        let derivation_tree = DerivationTree::new("<digit>");
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for _ in 0..1000 {
+            let expansion = strategy
+                .choose(&grammar, &derivation_tree, derivation_tree.root(), &mut rng)
+                .unwrap();
+            *counts.entry(expansion).or_insert(0) += 1;
+        }
+
+        assert_eq!(counts.get("1"), None);
+        assert!((700..900).contains(&counts["0"]));
+        assert!((100..300).contains(&counts["2"]));
+    }
+
     #[test]
     fn test_growth_strategy_choose() {
         let nonterminals_threshold = 10;
         let max_steps = 10;
         let strategy: &dyn Strategy<()> = &GrowthStrategy::new(nonterminals_threshold, max_steps);
         let grammar = sample_grammar();
-        let derivation_tree = Node::N(String::from("<int>"));
+        let derivation_tree = DerivationTree::new("<int>");
         assert_eq!(
-            strategy.choose(&grammar, &derivation_tree),
+            strategy.choose(
+                &grammar,
+                &derivation_tree,
+                derivation_tree.root(),
+                &mut StdRng::seed_from_u64(0)
+            ),
             Some(String::from("<int><digit>"))
         );
     }
 
+    #[test]
+    fn test_growth_strategy_prefers_recursion_over_costly_expansions() {
+        let expansions: HashMap<_, _> = [
+            ("<a>", vec!["<a>x", "<d>", "t"]),
+            ("<d>", vec!["<e><e><e><e>"]),
+            ("<e>", vec!["e"]),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let grammar = Grammar::from(&expansions);
+        let strategy: &dyn Strategy<()> = &GrowthStrategy::new(10, 10);
+        let derivation_tree = DerivationTree::new("<a>");
+        assert_eq!(
+            strategy.choose(
+                &grammar,
+                &derivation_tree,
+                derivation_tree.root(),
+                &mut StdRng::seed_from_u64(0)
+            ),
+            Some(String::from("<a>x"))
+        );
+    }
+
     #[test]
     fn test_close_strategy_choose() {
         let strategy: &dyn Strategy<()> = &CloseStrategy::new();
         let grammar = sample_grammar();
-        let derivation_tree = Node::N(String::from("<int>"));
+        let derivation_tree = DerivationTree::new("<int>");
         assert_eq!(
-            strategy.choose(&grammar, &derivation_tree),
+            strategy.choose(
+                &grammar,
+                &derivation_tree,
+                derivation_tree.root(),
+                &mut StdRng::seed_from_u64(0)
+            ),
             Some(String::from("<digit>"))
         );
     }
+
+    #[test]
+    fn test_depth_strategy_choose() {
+        let strategy: &dyn Strategy<()> = &DepthStrategy::new(3, 10);
+        let grammar = sample_grammar();
+        let mut derivation_tree = DerivationTree::new("<int>");
+        let root = derivation_tree.root();
+        let mut rng = StdRng::seed_from_u64(0);
+        // <int><digit> needs a depth of 3 and <digit> a depth of 2, both fit at the root
+        let choices: Vec<_> = (0..20)
+            .map(|_| strategy.choose(&grammar, &derivation_tree, root, &mut rng))
+            .collect();
+        assert!(choices.contains(&Some(String::from("<int><digit>"))));
+        assert!(choices.contains(&Some(String::from("<digit>"))));
+
+        let int = derivation_tree.expand(root, "<int><digit>")[0];
+        for _ in 0..20 {
+            assert_eq!(
+                strategy.choose(&grammar, &derivation_tree, int, &mut rng),
+                Some(String::from("<digit>"))
+            );
+        }
+    }
 }
@@ -0,0 +1,421 @@
+//! Draws derivation trees uniformly at random among all the derivation trees of a given size
+//!
+//! Picking alternatives uniformly, like the `RandomStrategy`, favors the trees that need few choices,
+//! so some trees of the same size are much more likely than others. The sampler counts how many
+//! derivation trees of each size every symbol and expansion has, with big integers since the counts
+//! grow exponentially, and picks each expansion and the size of each child in proportion to those counts.
+//!
+//! ## Example
+//!
+//! ```
+//! use grammar_fuzzer::{Grammar, Measure, UniformSampler};
+//! use rand::rngs::StdRng;
+//! use rand::SeedableRng;
+//! use std::collections::HashMap;
+//!
+//! let expansions: HashMap<_, _> = [
+//!     ("<start>", vec!["<int>"]),
+//!     ("<int>", vec!["<digit><int>", "<digit>"]),
+//!     ("<digit>", vec!["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"]),
+//! ]
+//! .iter()
+//! .cloned()
+//! .collect();
+//! let grammar = Grammar::from(&expansions);
+//!
+//! let mut sampler = UniformSampler::new(&grammar, Measure::Length);
+//! let mut rng = StdRng::seed_from_u64(0);
+//! assert_eq!(sampler.count("<start>", 3).unwrap(), 1000u32.into());
+//!
+//! let tree = sampler.sample("<start>", 3, &mut rng).unwrap();
+//! assert_eq!(tree.to_string().len(), 3);
+//! ```
+
+use super::derivation_tree::{Children, Node};
+use super::error::GrammarError;
+use super::grammar::Grammar;
+use super::parser::{self, Token};
+use num_bigint::{BigUint, RandBigInt};
+use num_traits::{One, Zero};
+use rand::Rng;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+/// How the size of a derivation tree is measured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Measure {
+    /// The number of nonterminal nodes, the same as `Bound::Size`
+    Size,
+    /// The number of characters of the generated string
+    Length,
+}
+
+/// The nonterminal symbols of an expansion and the size that the expansion adds by itself
+struct Parts {
+    expansion: String,
+    children: Vec<String>,
+    fixed: usize,
+}
+
+/// Counts the derivation trees of the grammar it was built with and draws them uniformly at random,
+/// the counts are memoized so drawing more trees is cheap
+pub struct UniformSampler {
+    measure: Measure,
+    parts: HashMap<String, Vec<Parts>>,
+    /// The minimum size of the derivation trees of each symbol, `usize::MAX` when it has none
+    minimums: HashMap<String, usize>,
+    symbols: HashMap<(String, usize), BigUint>,
+    /// The number of ways the children of an expansion, starting at a child, can share a size
+    sequences: HashMap<(String, usize, usize, usize), BigUint>,
+    in_progress: HashSet<(String, usize)>,
+}
+
+impl UniformSampler {
+    pub fn new<T>(grammar: &Grammar<T>, measure: Measure) -> Self {
+        let mut sampler = UniformSampler {
+            measure,
+            parts: HashMap::new(),
+            minimums: HashMap::new(),
+            symbols: HashMap::new(),
+            sequences: HashMap::new(),
+            in_progress: HashSet::new(),
+        };
+        sampler.prepare(grammar);
+        sampler
+    }
+
+    pub fn measure(&self) -> Measure {
+        self.measure
+    }
+
+    /// The number of derivation trees of the symbol with the given size
+    ///
+    /// It fails when a reachable symbol is undefined or when a symbol can derive itself without changing the size,
+    /// ex: `<a> ::= <a><b> | "a"` with `<b> ::= ""` has infinitely many trees of length 1
+    pub fn count(&mut self, symbol: &str, size: usize) -> Result<BigUint, GrammarError> {
+        self.in_progress.clear();
+        self.symbol_count(symbol, size)
+    }
+
+    /// Draws a derivation tree of the symbol with the given size, every such tree is equally likely
+    pub fn sample<R: Rng + ?Sized>(
+        &mut self,
+        symbol: &str,
+        size: usize,
+        rng: &mut R,
+    ) -> Result<Node, GrammarError> {
+        if self.count(symbol, size)?.is_zero() {
+            return Err(GrammarError::NoDerivationOfSize {
+                symbol: String::from(symbol),
+                size,
+            });
+        }
+        Ok(self.sample_symbol(symbol, size, rng))
+    }
+
+    fn minimum(&self, symbol: &str) -> usize {
+        *self.minimums.get(symbol).unwrap_or(&usize::MAX)
+    }
+
+    fn symbol_count(&mut self, symbol: &str, size: usize) -> Result<BigUint, GrammarError> {
+        if !self.parts.contains_key(symbol) {
+            return Err(GrammarError::UndefinedNonterminals(vec![String::from(
+                symbol,
+            )]));
+        }
+        if size < self.minimum(symbol) {
+            return Ok(BigUint::zero());
+        }
+        let key = (String::from(symbol), size);
+        if let Some(count) = self.symbols.get(&key) {
+            return Ok(count.clone());
+        }
+        if !self.in_progress.insert(key.clone()) {
+            return Err(GrammarError::InfiniteDerivations {
+                symbol: String::from(symbol),
+                size,
+            });
+        }
+        let mut count = BigUint::zero();
+        for alternative in 0..self.parts[symbol].len() {
+            let fixed = self.parts[symbol][alternative].fixed;
+            if size >= fixed {
+                count += self.sequence_count(symbol, alternative, 0, size - fixed)?;
+            }
+        }
+        self.in_progress.remove(&key);
+        self.symbols.insert(key, count.clone());
+        Ok(count)
+    }
+
+    /// The number of ways to derive the children of an expansion from `child` onwards with a total size
+    fn sequence_count(
+        &mut self,
+        symbol: &str,
+        alternative: usize,
+        child: usize,
+        size: usize,
+    ) -> Result<BigUint, GrammarError> {
+        let children = &self.parts[symbol][alternative].children;
+        if child == children.len() {
+            return Ok(if size == 0 {
+                BigUint::one()
+            } else {
+                BigUint::zero()
+            });
+        }
+        let key = (String::from(symbol), alternative, child, size);
+        if let Some(count) = self.sequences.get(&key) {
+            return Ok(count.clone());
+        }
+        let child_symbol = children[child].clone();
+        let rest_minimum = children[child + 1..]
+            .iter()
+            .map(|symbol| self.minimum(symbol))
+            .fold(0, usize::saturating_add);
+        let mut count = BigUint::zero();
+        if rest_minimum <= size {
+            for child_size in 0..=size - rest_minimum {
+                let child_count = self.symbol_count(&child_symbol, child_size)?;
+                if child_count.is_zero() {
+                    continue;
+                }
+                let rest =
+                    self.sequence_count(symbol, alternative, child + 1, size - child_size)?;
+                count += child_count * rest;
+            }
+        }
+        self.sequences.insert(key, count.clone());
+        Ok(count)
+    }
+
+    /// Splits the expansions of every symbol into parts and computes the minimum sizes of the symbols
+    fn prepare<T>(&mut self, grammar: &Grammar<T>) {
+        let measure = self.measure;
+        for (symbol, alternatives) in grammar.iter() {
+            let parts = alternatives
+                .iter()
+                .map(|expansion| {
+                    let mut parts = Parts {
+                        expansion: expansion.string.clone(),
+                        children: Vec::new(),
+                        fixed: if measure == Measure::Size { 1 } else { 0 },
+                    };
+                    for token in parser::tokens(&expansion.string) {
+                        match token {
+                            Token::Nonterminal(t) => parts.children.push(String::from(t)),
+                            Token::Terminal(t) if measure == Measure::Length => {
+                                parts.fixed += t.chars().count()
+                            }
+                            Token::Terminal(_) => (),
+                        }
+                    }
+                    parts
+                })
+                .collect();
+            self.parts.insert(symbol.clone(), parts);
+        }
+
+        // Lowers the minimums until they reach a fixpoint
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (symbol, alternatives) in self.parts.iter() {
+                let minimum = alternatives
+                    .iter()
+                    .map(|parts| {
+                        parts
+                            .children
+                            .iter()
+                            .map(|child| *self.minimums.get(child).unwrap_or(&usize::MAX))
+                            .fold(parts.fixed, usize::saturating_add)
+                    })
+                    .min()
+                    .unwrap_or(usize::MAX);
+                if minimum < *self.minimums.get(symbol).unwrap_or(&usize::MAX) {
+                    self.minimums.insert(symbol.clone(), minimum);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    // ------------------------------- Sampling -------------------------------
+    // `count` memoized every nonzero count that the sampling looks up, the missing counts are 0
+
+    fn sample_symbol<R: Rng + ?Sized>(&self, symbol: &str, size: usize, rng: &mut R) -> Node {
+        let alternatives = &self.parts[symbol];
+        let weights: Vec<BigUint> = alternatives
+            .iter()
+            .enumerate()
+            .map(|(alternative, parts)| match size.checked_sub(parts.fixed) {
+                Some(size) => self.memoized_sequence(symbol, alternative, 0, size),
+                None => BigUint::zero(),
+            })
+            .collect();
+        let alternative = pick(&weights, rng);
+        let parts = &alternatives[alternative];
+
+        let mut remaining = size - parts.fixed;
+        let mut children = Vec::new();
+        for (child, child_symbol) in parts.children.iter().enumerate() {
+            let weights: Vec<BigUint> = (0..=remaining)
+                .map(|child_size| {
+                    let child_count = self.memoized_symbol(child_symbol, child_size);
+                    if child_count.is_zero() {
+                        return child_count;
+                    }
+                    child_count
+                        * self.memoized_sequence(
+                            symbol,
+                            alternative,
+                            child + 1,
+                            remaining - child_size,
+                        )
+                })
+                .collect();
+            let child_size = pick(&weights, rng);
+            children.push(self.sample_symbol(child_symbol, child_size, rng));
+            remaining -= child_size;
+        }
+
+        let tokens = parser::tokens(&parts.expansion);
+        if tokens.is_empty() {
+            return Node::new_expanded(symbol, Children::epsilon());
+        }
+        let mut children = children.into_iter();
+        let roots = tokens
+            .iter()
+            .map(|token| match token {
+                Token::Nonterminal(_) => children.next().unwrap(),
+                Token::Terminal(t) => Node::new_terminal(t),
+            })
+            .map(RefCell::new)
+            .collect();
+        Node::new_expanded(symbol, Children { roots })
+    }
+
+    fn memoized_sequence(
+        &self,
+        symbol: &str,
+        alternative: usize,
+        child: usize,
+        size: usize,
+    ) -> BigUint {
+        if child == self.parts[symbol][alternative].children.len() {
+            return if size == 0 {
+                BigUint::one()
+            } else {
+                BigUint::zero()
+            };
+        }
+        self.sequences
+            .get(&(String::from(symbol), alternative, child, size))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn memoized_symbol(&self, symbol: &str, size: usize) -> BigUint {
+        self.symbols
+            .get(&(String::from(symbol), size))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// Picks an index with a probability proportional to its weight, the weights don't sum to 0
+fn pick<R: Rng + ?Sized>(weights: &[BigUint], rng: &mut R) -> usize {
+    let total: BigUint = weights.iter().sum();
+    let mut target = rng.gen_biguint_below(&total);
+    for (idx, weight) in weights.iter().enumerate() {
+        if target < *weight {
+            return idx;
+        }
+        target -= weight;
+    }
+    unreachable!("the target is below the sum of the weights")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::grammar;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn catalan_grammar() -> Grammar<()> {
+        grammar(&[("<s>", vec!["(<s>)<s>", ""])])
+    }
+
+    #[test]
+    fn test_count() {
+        let grammar = catalan_grammar();
+        let mut by_length = UniformSampler::new(&grammar, Measure::Length);
+        let catalan: Vec<BigUint> = [1u32, 1, 2, 5, 14, 42]
+            .iter()
+            .map(|n| BigUint::from(*n))
+            .collect();
+        for (pairs, expected) in catalan.iter().enumerate() {
+            assert_eq!(&by_length.count("<s>", 2 * pairs).unwrap(), expected);
+            assert!(by_length.count("<s>", 2 * pairs + 1).unwrap().is_zero());
+        }
+        // every pair of parentheses adds two nonterminal nodes
+        let mut by_size = UniformSampler::new(&grammar, Measure::Size);
+        assert_eq!(by_size.count("<s>", 7).unwrap(), catalan[3]);
+
+        // the counts grow beyond 64 bits
+        let big = by_length.count("<s>", 200).unwrap();
+        assert!(big > BigUint::from(u64::MAX));
+    }
+
+    #[test]
+    fn test_sample_is_uniform() {
+        let grammar = catalan_grammar();
+        let mut sampler = UniformSampler::new(&grammar, Measure::Length);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for _ in 0..1400 {
+            let tree = sampler.sample("<s>", 8, &mut rng).unwrap();
+            *counts.entry(tree.to_string()).or_insert(0) += 1;
+        }
+
+        // 14 balanced strings with 4 pairs of parentheses, about 100 draws each
+        assert_eq!(counts.len(), 14);
+        assert!(counts.values().all(|n| (60..140).contains(n)));
+    }
+
+    #[test]
+    fn test_sample_errors() {
+        let mut sampler = UniformSampler::new(&catalan_grammar(), Measure::Length);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        assert_eq!(
+            sampler.sample("<s>", 3, &mut rng),
+            Err(GrammarError::NoDerivationOfSize {
+                symbol: String::from("<s>"),
+                size: 3
+            })
+        );
+        assert_eq!(
+            sampler.count("<t>", 3),
+            Err(GrammarError::UndefinedNonterminals(vec![String::from(
+                "<t>"
+            )]))
+        );
+
+        let cyclic = grammar(&[("<a>", vec!["<a><b>", "a"]), ("<b>", vec!["b", ""])]);
+        assert_eq!(
+            UniformSampler::new(&cyclic, Measure::Length).count("<a>", 1),
+            Err(GrammarError::InfiniteDerivations {
+                symbol: String::from("<a>"),
+                size: 1
+            })
+        );
+        assert_eq!(
+            UniformSampler::new(&cyclic, Measure::Size)
+                .count("<a>", 3)
+                .unwrap(),
+            BigUint::from(2u32)
+        );
+    }
+}